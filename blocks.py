@@ -1,30 +1,28 @@
+import os
+
 import numpy as np
 
-BLOCKS = [
-    # 1 tile
-    np.array([[1]]),
-    # 2 tiles
-    np.array([[1, 1]]),
-    # 3 tiles
-    np.array([[1, 1], [0, 1]]),
-    np.array([[1, 1, 1]]),
-    # 4 tiles
-    np.array([[1, 1, 1, 1]]),
-    np.array([[1, 1, 1], [0, 0, 1]]),
-    np.array([[1, 1, 0], [0, 1, 1]]),
-    np.array([[1, 1], [1, 1]]),
-    np.array([[1, 1, 1], [0, 1, 0]]),
-    # 5 tiles
-    np.array([[0, 1, 1], [1, 1, 0], [0, 1, 0]]),
-    np.array([[1, 1, 1, 1, 1]]),
-    np.array([[1, 1, 1, 1], [1, 0, 0, 0]]),
-    np.array([[0, 1, 1, 1], [1, 1, 0, 0]]),
-    np.array([[1, 1, 1], [1, 1, 0]]),
-    np.array([[1, 1, 1], [0, 1, 0], [0, 1, 0]]),
-    np.array([[1, 1, 1], [1, 0, 1]]),
-    np.array([[1, 1, 1], [1, 0, 0], [1, 0, 0]]),
-    np.array([[0, 0, 1], [0, 1, 1], [1, 1, 0]]),
-    np.array([[0, 1, 0], [1, 1, 1], [0, 1, 0]]),
-    np.array([[1, 1, 1, 1], [0, 1, 0, 0]]),
-    np.array([[1, 1, 0], [0, 1, 0], [0, 1, 1]]),
-]
+# The pieces are defined once in pieces/standard.txt, shared with the Rust engines
+STANDARD = os.path.join(os.path.dirname(os.path.abspath(__file__)), "pieces", "standard.txt")
+
+
+def load_blocks(path=STANDARD):
+    """Load the shapes of a piece set as arrays of 1s (tiles) and 0s (empty cells)"""
+    with open(path) as f:
+        lines = [line.strip() for line in f if not line.strip().startswith("#")]
+
+    blocks, rows = [], None
+    for line in lines + [""]:
+        if not line:
+            if rows:
+                blocks.append(np.array(rows))
+            rows = None
+        elif rows is None:
+            # The first line of every shape is its name
+            rows = []
+        else:
+            rows.append([1 if cell == "X" else 0 for cell in line])
+    return blocks
+
+
+BLOCKS = load_blocks()
@@ -13,4 +13,42 @@ pub trait Algorithm {
 
     /// String name for the algorithm
     fn name(&self) -> String;
+
+    /// Start playing a new game.
+    /// Algorithms that keep state between their moves in a game override this,
+    /// by default every decision is made from scratch.
+    fn new_game(&self) -> Box<dyn Session + '_> {
+        Box::new(self)
+    }
+}
+
+/// An algorithm playing a single seat of a single game,
+/// which may remember things between its decisions
+pub trait Session {
+    /// Make a decision given a current state for a player
+    fn decide(
+        &mut self,
+        rng: &mut rand::rngs::ThreadRng,
+        state: &State,
+        player: &Player,
+        move_number: usize,
+    ) -> Option<Move>;
+
+    /// Number of times the search behind the last decision visited every move it considered,
+    /// for algorithms that count visits
+    fn visits(&self) -> Option<&[(Move, usize)]> {
+        None
+    }
+}
+
+impl<A: Algorithm + ?Sized> Session for &A {
+    fn decide(
+        &mut self,
+        rng: &mut rand::rngs::ThreadRng,
+        state: &State,
+        player: &Player,
+        move_number: usize,
+    ) -> Option<Move> {
+        Algorithm::decide(*self, rng, state, player, move_number)
+    }
 }
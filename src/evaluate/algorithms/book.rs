@@ -0,0 +1,464 @@
+use super::{Algorithm, Session};
+use crate::{
+    evaluate::GameRecord,
+    game::{Dimensioned, Game, Move, Player, State},
+};
+use std::{collections::HashMap, io::Read, path::PathBuf};
+
+/// Type alias for the serialization of an [OpeningBook]
+type StoredBook = (usize, Vec<(u64, Vec<(Vec<(i8, i8)>, usize, usize, f64)>)>);
+
+/// Statistics of a move played from a book position
+#[derive(Clone, PartialEq, Debug)]
+pub struct BookMove {
+    /// Cells covered by the move, seen from the player's corner and sorted
+    pub cells: Vec<(i8, i8)>,
+    /// Number of games the move was played in
+    pub games: usize,
+    /// Final score of the player, summed over the games
+    pub points: usize,
+    /// Games won by the player, ties sharing the win
+    pub wins: f64,
+}
+
+impl BookMove {
+    pub fn avg_points(&self) -> f64 {
+        self.points as f64 / self.games as f64
+    }
+}
+
+/// Rotate a cell of a `w` by `h` board a quarter turn, sending the starting corner
+/// of each player to the corner of the player before them
+fn rotate((x, y): (i8, i8), (w, _h): (i8, i8)) -> (i8, i8) {
+    (y, w - 1 - x)
+}
+
+/// Rotate a cell of a `w` by `h` board so that `player`'s starting corner is at the origin.
+/// Returns the cell and the dimensions of the rotated board.
+fn normalize(player: &Player, mut pos: (i8, i8), mut dims: (i8, i8)) -> ((i8, i8), (i8, i8)) {
+    for _ in 0..usize::from(player) {
+        pos = rotate(pos, dims);
+        dims = (dims.1, dims.0);
+    }
+    (pos, dims)
+}
+
+/// Cells covered by a move as seen from the player's corner, sorted so that equal moves compare equal
+fn normalize_cells(player: &Player, cells: &[(i8, i8)], dims: (i8, i8)) -> Vec<(i8, i8)> {
+    let mut cells: Vec<_> = cells
+        .iter()
+        .map(|pos| normalize(player, *pos, dims).0)
+        .collect();
+    cells.sort();
+    cells
+}
+
+/// Key of a position with `player` to move, the same for every player seeing the same board from their corner.
+/// Colors are counted from the player to move, so the key doesn't depend on which seat they play.
+fn position_key(player: &Player, (w, h): (i8, i8), cell: impl Fn(i8, i8) -> Option<Player>) -> u64 {
+    let (_, (nw, nh)) = normalize(player, (0, 0), (w, h));
+    let mut board = vec![0u8; nw as usize * nh as usize];
+    for y in 0..h {
+        for x in 0..w {
+            if let Some(owner) = cell(x, y) {
+                let ((nx, ny), _) = normalize(player, (x, y), (w, h));
+                let relative = (usize::from(owner) + Player::N - usize::from(player)) % Player::N;
+                board[ny as usize * nw as usize + nx as usize] = relative as u8 + 1;
+            }
+        }
+    }
+
+    // FNV-1a, as keys are saved and have to stay the same between runs
+    [nw as u8, nh as u8]
+        .iter()
+        .chain(&board)
+        .fold(0xcbf29ce484222325, |hash, byte| {
+            (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+        })
+}
+
+/// Moves played in the first rounds of games, keyed by position.
+/// Positions are seen from the corner of the player to move, so a position
+/// learned from one seat is also found when playing another.
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct OpeningBook {
+    positions: HashMap<u64, Vec<BookMove>>,
+    /// Number of moves of every player learned from each game
+    depth: usize,
+}
+
+impl OpeningBook {
+    pub fn new(depth: usize) -> Self {
+        Self {
+            positions: HashMap::new(),
+            depth,
+        }
+    }
+
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// Number of positions in the book
+    pub fn len(&self) -> usize {
+        self.positions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.positions.is_empty()
+    }
+
+    /// Learn from a game played on an empty `w` by `h` board, given the cells covered by every move and the final scores
+    pub fn add_moves(
+        &mut self,
+        dims: (i8, i8),
+        moves: &[(Player, Vec<(i8, i8)>)],
+        scores: &[u8; Player::N],
+    ) {
+        let (w, h) = dims;
+        let mut board = vec![None; w as usize * h as usize];
+        let mut played = [0; Player::N];
+        let best = scores.iter().max().copied().unwrap_or_default();
+        let winners = scores.iter().filter(|s| **s == best).count();
+
+        for (player, cells) in moves {
+            let pid = usize::from(player);
+            if played.iter().all(|p| *p >= self.depth) {
+                break;
+            }
+            if played[pid] < self.depth {
+                let key = position_key(player, dims, |x, y| {
+                    board[y as usize * w as usize + x as usize]
+                });
+                let cells = normalize_cells(player, cells, dims);
+                let moves = self.positions.entry(key).or_default();
+                let index = match moves.iter().position(|mv| mv.cells == cells) {
+                    Some(index) => index,
+                    None => {
+                        moves.push(BookMove {
+                            cells,
+                            games: 0,
+                            points: 0,
+                            wins: 0.,
+                        });
+                        moves.len() - 1
+                    }
+                };
+                let mv = &mut moves[index];
+                mv.games += 1;
+                mv.points += scores[pid] as usize;
+                if scores[pid] == best {
+                    mv.wins += 1. / winners as f64;
+                }
+            }
+
+            played[pid] += 1;
+            for (x, y) in cells {
+                board[*y as usize * w as usize + *x as usize] = Some(*player);
+            }
+        }
+    }
+
+    /// Learn from a tournament game, if its moves were kept.
+    /// Returns whether the game was learned.
+    pub fn add_record(&mut self, game: &GameRecord) -> bool {
+        match &game.replay {
+            Some(replay) => {
+                self.add_moves((replay.w, replay.h), &replay.moves, &game.scores);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Learn from a finished game that started on an empty board
+    pub fn add_game(&mut self, game: &Game) {
+        let state = game.state();
+        let moves: Vec<_> = game
+            .history()
+            .iter()
+            .map(|mv| (mv.player, state.move_cells(mv)))
+            .collect();
+        self.add_moves((state.w(), state.h()), &moves, state.scores());
+    }
+
+    /// Learn from `games` games of an algorithm playing every seat, starting from the empty board `start`
+    pub fn self_play(
+        &mut self,
+        rng: &mut rand::rngs::ThreadRng,
+        algorithm: &dyn Algorithm,
+        start: &State,
+        games: usize,
+    ) {
+        for _ in 0..games {
+            let mut game = Game::new(start.clone());
+            let mut sessions: Vec<_> = (0..Player::N).map(|_| algorithm.new_game()).collect();
+            while !game.is_over() {
+                let player = game.to_move();
+                let mv = sessions[usize::from(player)].decide(
+                    rng,
+                    game.state(),
+                    &player,
+                    game.move_number(),
+                );
+                game.play_or_pass(mv);
+            }
+            self.add_game(&game);
+        }
+    }
+
+    /// Moves of the book for the player to move, if the position is in it
+    pub fn lookup(&self, state: &State, player: &Player) -> Option<&[BookMove]> {
+        let key = position_key(player, (state.w(), state.h()), |x, y| state.cell(x, y));
+        self.positions.get(&key).map(Vec::as_slice)
+    }
+
+    /// Legal move with the best average score among the book moves played in at least `min_games` games
+    pub fn best_move(&self, state: &State, player: &Player, min_games: usize) -> Option<Move> {
+        let book = self.lookup(state, player)?;
+        let dims = (state.w(), state.h());
+        state
+            .get_moves(player)
+            .into_iter()
+            .filter_map(|mv| {
+                let cells = normalize_cells(player, &state.move_cells(&mv), dims);
+                book.iter()
+                    .find(|entry| entry.cells == cells && entry.games >= min_games)
+                    .map(|entry| (entry.avg_points(), mv))
+            })
+            .max_by(|(a, _), (b, _)| a.total_cmp(b))
+            .map(|(_, mv)| mv)
+    }
+
+    pub fn save(&self, path: PathBuf) -> Result<(), serde_json::Error> {
+        let store = StoredBook::from(self);
+        let file = std::fs::File::create(path).unwrap();
+        serde_json::to_writer(file, &store)
+    }
+
+    pub fn load(reader: impl Read) -> Result<Self, serde_json::Error> {
+        let store: StoredBook = serde_json::from_reader(reader)?;
+        Ok(Self::from(store))
+    }
+}
+
+impl From<&OpeningBook> for StoredBook {
+    fn from(book: &OpeningBook) -> Self {
+        let positions = book
+            .positions
+            .iter()
+            .map(|(key, moves)| {
+                let moves = moves
+                    .iter()
+                    .map(|mv| (mv.cells.clone(), mv.games, mv.points, mv.wins))
+                    .collect();
+                (*key, moves)
+            })
+            .collect();
+        (book.depth, positions)
+    }
+}
+
+impl From<StoredBook> for OpeningBook {
+    fn from((depth, positions): StoredBook) -> Self {
+        let positions = positions
+            .into_iter()
+            .map(|(key, moves)| {
+                let moves = moves
+                    .into_iter()
+                    .map(|(cells, games, points, wins)| BookMove {
+                        cells,
+                        games,
+                        points,
+                        wins,
+                    })
+                    .collect();
+                (key, moves)
+            })
+            .collect();
+        Self { positions, depth }
+    }
+}
+
+/// Algorithm playing the best move of an opening book,
+/// and the fallback algorithm once the position isn't in the book
+pub struct Book<Alg>
+where
+    Alg: Algorithm,
+{
+    book: OpeningBook,
+    fallback: Alg,
+    /// Least number of games a book move needs to have been played in
+    min_games: usize,
+}
+
+impl<Alg> Book<Alg>
+where
+    Alg: Algorithm,
+{
+    pub fn new(book: OpeningBook, fallback: Alg) -> Self {
+        Self {
+            book,
+            fallback,
+            min_games: 1,
+        }
+    }
+
+    /// Only play book moves played in at least `min_games` games
+    pub fn min_games(mut self, min_games: usize) -> Self {
+        self.min_games = min_games;
+        self
+    }
+}
+
+impl<Alg> Algorithm for Book<Alg>
+where
+    Alg: Algorithm,
+{
+    fn decide(
+        &self,
+        rng: &mut rand::rngs::ThreadRng,
+        state: &State,
+        player: &Player,
+        move_number: usize,
+    ) -> Option<Move> {
+        if move_number <= self.book.depth {
+            if let Some(mv) = self.book.best_move(state, player, self.min_games) {
+                return Some(mv);
+            }
+        }
+        self.fallback.decide(rng, state, player, move_number)
+    }
+
+    fn new_game(&self) -> Box<dyn Session + '_> {
+        Box::new(BookSession {
+            book: self,
+            fallback: self.fallback.new_game(),
+        })
+    }
+
+    fn name(&self) -> String {
+        format!(
+            "Book of {} positions, then {}",
+            self.book.len(),
+            self.fallback.name()
+        )
+    }
+}
+
+/// A game of [Book], so the fallback algorithm can keep its own state
+struct BookSession<'a, Alg: Algorithm> {
+    book: &'a Book<Alg>,
+    fallback: Box<dyn Session + 'a>,
+}
+
+impl<Alg: Algorithm> Session for BookSession<'_, Alg> {
+    fn decide(
+        &mut self,
+        rng: &mut rand::rngs::ThreadRng,
+        state: &State,
+        player: &Player,
+        move_number: usize,
+    ) -> Option<Move> {
+        if move_number <= self.book.book.depth {
+            if let Some(mv) = self.book.book.best_move(state, player, self.book.min_games) {
+                return Some(mv);
+            }
+        }
+        self.fallback.decide(rng, state, player, move_number)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::evaluate::Random;
+
+    #[test]
+    fn test_normalize_corners() {
+        let (w, h) = (14, 9);
+        for player in Player::iter() {
+            let corner = crate::game::start_corner(&player, w, h);
+            assert_eq!(normalize(&player, corner, (w, h)).0, (0, 0));
+            // the next player's corner is always on the same side
+            let next = crate::game::start_corner(&player.next(), w, h);
+            let (pos, (nw, _)) = normalize(&player, next, (w, h));
+            assert_eq!(pos, (nw - 1, 0));
+        }
+    }
+
+    #[test]
+    fn test_position_key_symmetry() {
+        // every player sees the empty board the same way
+        let state = State::new(20, 20);
+        let keys: Vec<_> = Player::iter()
+            .map(|p| position_key(&p, (20, 20), |x, y| state.cell(x, y)))
+            .collect();
+        assert!(keys.iter().all(|k| *k == keys[0]));
+
+        // a monomino in the corner of the player to move is the same position for every seat
+        let keys: Vec<_> = Player::iter()
+            .map(|p| {
+                let corner = crate::game::start_corner(&p, 20, 20);
+                position_key(&p, (20, 20), |x, y| (corner == (x, y)).then_some(p))
+            })
+            .collect();
+        assert!(keys.iter().all(|k| *k == keys[0]));
+        let other = position_key(&Player::Player2, (20, 20), |x, y| {
+            ((x, y) == (0, 0)).then_some(Player::Player1)
+        });
+        assert_ne!(other, keys[0]);
+    }
+
+    #[test]
+    fn test_book() {
+        let mut rng = rand::thread_rng();
+        let start = State::new(20, 20);
+        let mut book = OpeningBook::new(2);
+        book.self_play(&mut rng, &Random, &start, 3);
+        assert!(!book.is_empty());
+        // only the first seat moves on the empty board, the others already see pieces
+        let first = book.lookup(&start, &Player::Player1).unwrap();
+        assert_eq!(first.iter().map(|mv| mv.games).sum::<usize>(), 3);
+
+        // every seat's first move is learned under the position that seat saw
+        let mut game = Game::new(start.clone());
+        for player in Player::iter() {
+            let mv = Algorithm::decide(&Random, &mut rng, game.state(), &player, 1);
+            game.play_or_pass(mv);
+        }
+        let mut seats = OpeningBook::new(1);
+        seats.add_game(&game);
+        assert_eq!(seats.len(), Player::N);
+        let mut state = start.clone();
+        for mv in game.history() {
+            let cells = normalize_cells(&mv.player, &state.move_cells(mv), (20, 20));
+            let entries = seats.lookup(&state, &mv.player).unwrap();
+            assert!(entries.iter().any(|entry| entry.cells == cells));
+            state.place_piece(mv);
+        }
+
+        // the book move is played from the empty board
+        let algorithm = Book::new(book.clone(), Random);
+        let mv = Algorithm::decide(&algorithm, &mut rng, &start, &Player::Player1, 1).unwrap();
+        let cells = normalize_cells(&Player::Player1, &start.move_cells(&mv), (20, 20));
+        assert!(first.iter().any(|entry| entry.cells == cells));
+        // out of the book the fallback plays
+        assert!(book.best_move(&start, &Player::Player1, 4).is_none());
+        assert!(Algorithm::decide(
+            &algorithm.min_games(4),
+            &mut rng,
+            &start,
+            &Player::Player1,
+            1
+        )
+        .is_some());
+
+        // saved books load the same
+        let path = std::env::temp_dir().join(format!("book-test-{}.json", std::process::id()));
+        book.save(path.clone()).unwrap();
+        let loaded = OpeningBook::load(std::fs::File::open(&path).unwrap()).unwrap();
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(loaded, book);
+    }
+}
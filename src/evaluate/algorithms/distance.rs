@@ -1,5 +1,5 @@
 use super::Algorithm;
-use crate::game::{piece_dims, Player, State};
+use crate::game::{start_corner, Dimensioned, Player, State};
 use rand::seq::SliceRandom;
 
 /// Algorithm that sorts moves by distance to a position
@@ -14,7 +14,7 @@ pub enum Distance {
 impl Distance {
     fn distance(&self, state: &State, mv: &crate::game::Move) -> i16 {
         // Here, we get all 4 corners of the bounding box around the piece
-        let (w, h) = piece_dims(mv);
+        let (w, h) = state.move_dims(mv);
         let (w, h) = (w as i8, h as i8);
         let (x, y) = mv.pos;
 
@@ -23,15 +23,18 @@ impl Distance {
         // Four corners.
         let corners = [(x, y), (x, y + h), (x + w, y), (x + w, y + h)];
 
+        // Last row and column of the board
+        let (mx, my) = (state.w() - 1, state.h() - 1);
+        // Starting corner of a player (all coords multiplied by two)
+        let start = |player: &Player| {
+            let (x, y) = start_corner(player, state.w(), state.h());
+            (x * 2, y * 2)
+        };
+
         // The position of the target (all coords multiplied by two)
         let (rx, ry) = match self {
-            Distance::TowardCorner | Distance::AwayFromCorner => match player {
-                Player::Player1 => (0, 0),
-                Player::Player2 => (19 * 2, 0),
-                Player::Player3 => (19 * 2, 19 * 2),
-                Player::Player4 => (0, 19 * 2),
-            },
-            Distance::TowardCenter | Distance::AwayFromCenter => (19, 19),
+            Distance::TowardCorner | Distance::AwayFromCorner => start(player),
+            Distance::TowardCenter | Distance::AwayFromCenter => (mx, my),
             Distance::TowardBestOpponent => {
                 // Loop through players and find the one with the best score
                 let scores = state.scores();
@@ -41,19 +44,14 @@ impl Distance {
                     .max_by_key(|p| scores[usize::from(p)])
                     .unwrap();
 
-                match best {
-                    Player::Player1 => (0, 0),
-                    Player::Player2 => (19 * 2, 0),
-                    Player::Player3 => (19 * 2, 19 * 2),
-                    Player::Player4 => (0, 19 * 2),
-                }
+                start(&best)
             }
         };
 
         corners
             .iter()
             .map(|(cx, cy)| {
-                // we double indices here because 9.5 is the true middle
+                // we double indices here because the true middle can be halfway between cells
                 let (dx, dy) = (cx * 2 - rx, cy * 2 - ry);
                 // increase size to avoid overflow
                 let (dx, dy) = (dx as i16, dy as i16);
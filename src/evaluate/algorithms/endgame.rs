@@ -0,0 +1,435 @@
+use super::{Algorithm, Session};
+use crate::game::{Move, Player, State};
+use std::cmp::Reverse;
+
+/// How the opponents are assumed to play in a search
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum Model {
+    /// Every opponent plays to lower the margin of the searching player.
+    /// The search can prune, so it solves bigger trees.
+    #[default]
+    Paranoid,
+    /// Every player plays to raise their own margin
+    MaxN,
+}
+
+/// Result of solving a position
+#[derive(Clone, PartialEq, Eq)]
+pub struct Solution {
+    /// Best move of the player, none if they have to pass
+    pub best: Option<Move>,
+    /// Final score of the player minus the best final score of the opponents
+    pub margin: i32,
+    /// Number of positions searched
+    pub nodes: usize,
+}
+
+/// Score of a player minus the best score of the other players
+fn margin(scores: &[u8; Player::N], player: &Player) -> i32 {
+    let pid = usize::from(player);
+    let best_other = (0..Player::N)
+        .filter(|p| *p != pid)
+        .map(|p| scores[p])
+        .max()
+        .unwrap_or_default();
+    scores[pid] as i32 - best_other as i32
+}
+
+/// Moves of a player, biggest pieces first so that good moves are searched first
+fn ordered_moves(state: &State, player: &Player) -> Vec<Move> {
+    let mut moves = state.get_moves(player);
+    moves.sort_by_key(|mv| Reverse(state.move_size(mv)));
+    moves
+}
+
+/// Next player that can still move, passing for the ones that can't.
+/// Returns none once nobody can move.
+fn next_to_move(state: &State, mut player: Player, done: &mut [bool; Player::N]) -> Option<Player> {
+    for _ in 0..Player::N {
+        let pid = usize::from(player);
+        if !done[pid] {
+            if state.count_moves(&player) > 0 {
+                return Some(player);
+            }
+            done[pid] = true;
+        }
+        player = player.next();
+    }
+    None
+}
+
+/// Number of positions left in the game tree from a position with `player` to move,
+/// or none if there are more than `limit`
+pub fn tree_size(state: &State, player: &Player, limit: usize) -> Option<usize> {
+    fn count(
+        state: &State,
+        player: Player,
+        mut done: [bool; Player::N],
+        budget: &mut usize,
+    ) -> Option<()> {
+        let Some(player) = next_to_move(state, player, &mut done) else {
+            return Some(());
+        };
+        for mv in state.get_moves(&player) {
+            *budget = budget.checked_sub(1)?;
+            let mut nstate = state.clone();
+            nstate.place_piece(&mv);
+            count(&nstate, player.next(), done, budget)?;
+        }
+        Some(())
+    }
+
+    // every move of the player to move is a position of the tree
+    if state.count_moves(player) > limit {
+        return None;
+    }
+    let mut budget = limit;
+    count(state, *player, [false; Player::N], &mut budget)?;
+    Some(limit - budget)
+}
+
+/// Margin of `root` at the end of the game with alpha beta pruning,
+/// with `player` to move and everyone else playing against `root`
+fn paranoid(
+    state: &State,
+    root: &Player,
+    player: Player,
+    mut done: [bool; Player::N],
+    (mut alpha, mut beta): (i32, i32),
+    nodes: &mut usize,
+) -> i32 {
+    *nodes += 1;
+    let Some(player) = next_to_move(state, player, &mut done) else {
+        return margin(state.scores(), root);
+    };
+
+    let maximizing = player == *root;
+    let mut best = if maximizing { i32::MIN } else { i32::MAX };
+    for mv in ordered_moves(state, &player) {
+        let mut nstate = state.clone();
+        nstate.place_piece(&mv);
+        let value = paranoid(&nstate, root, player.next(), done, (alpha, beta), nodes);
+        if maximizing {
+            best = best.max(value);
+            alpha = alpha.max(value);
+        } else {
+            best = best.min(value);
+            beta = beta.min(value);
+        }
+        if alpha >= beta {
+            break;
+        }
+    }
+    best
+}
+
+/// Final scores of the game when every player maximizes their own margin, with `player` to move
+fn max_n(
+    state: &State,
+    player: Player,
+    mut done: [bool; Player::N],
+    nodes: &mut usize,
+) -> [u8; Player::N] {
+    *nodes += 1;
+    let Some(player) = next_to_move(state, player, &mut done) else {
+        return *state.scores();
+    };
+
+    ordered_moves(state, &player)
+        .into_iter()
+        .map(|mv| {
+            let mut nstate = state.clone();
+            nstate.place_piece(&mv);
+            max_n(&nstate, player.next(), done, nodes)
+        })
+        // the first of the best moves, like the order of the search
+        .rev()
+        .max_by_key(|scores| margin(scores, &player))
+        .unwrap()
+}
+
+/// Margin of `player` at the end of the game after playing `mv`
+fn evaluate_move(
+    state: &State,
+    player: &Player,
+    mv: &Move,
+    model: Model,
+    nodes: &mut usize,
+) -> i32 {
+    let mut nstate = state.clone();
+    nstate.place_piece(mv);
+    match model {
+        Model::Paranoid => paranoid(
+            &nstate,
+            player,
+            player.next(),
+            [false; Player::N],
+            (i32::MIN, i32::MAX),
+            nodes,
+        ),
+        Model::MaxN => margin(
+            &max_n(&nstate, player.next(), [false; Player::N], nodes),
+            player,
+        ),
+    }
+}
+
+/// Search to the end of the game for the move of `player` with the best final margin
+pub fn solve(state: &State, player: &Player, model: Model) -> Solution {
+    let mut nodes = 0;
+    let moves = ordered_moves(state, player);
+    if moves.is_empty() {
+        // the player passes for the rest of the game
+        let mut done = [false; Player::N];
+        done[usize::from(player)] = true;
+        let margin = match model {
+            Model::Paranoid => paranoid(
+                state,
+                player,
+                player.next(),
+                done,
+                (i32::MIN, i32::MAX),
+                &mut nodes,
+            ),
+            Model::MaxN => margin(&max_n(state, player.next(), done, &mut nodes), player),
+        };
+        return Solution {
+            best: None,
+            margin,
+            nodes,
+        };
+    }
+
+    let mut best: Option<(i32, Move)> = None;
+    for mv in moves {
+        let value = match (model, &best) {
+            // only moves beating the best so far matter
+            (Model::Paranoid, Some((alpha, _))) => {
+                let mut nstate = state.clone();
+                nstate.place_piece(&mv);
+                paranoid(
+                    &nstate,
+                    player,
+                    player.next(),
+                    [false; Player::N],
+                    (*alpha, i32::MAX),
+                    &mut nodes,
+                )
+            }
+            _ => evaluate_move(state, player, &mv, model, &mut nodes),
+        };
+        if best.as_ref().is_none_or(|(b, _)| value > *b) {
+            best = Some((value, mv));
+        }
+    }
+
+    let (margin, best) = best.unwrap();
+    Solution {
+        best: Some(best),
+        margin,
+        nodes,
+    }
+}
+
+/// Final margin of `player` after each of their moves, searching to the end of the game
+pub fn solve_moves(state: &State, player: &Player, model: Model) -> Vec<(Move, i32)> {
+    let mut nodes = 0;
+    ordered_moves(state, player)
+        .into_iter()
+        .map(|mv| {
+            let value = evaluate_move(state, player, &mv, model, &mut nodes);
+            (mv, value)
+        })
+        .collect()
+}
+
+/// Algorithm playing like another one until the rest of the game
+/// has at most `limit` positions, and solving the endgame from there
+pub struct Endgame<Alg>
+where
+    Alg: Algorithm,
+{
+    inner: Alg,
+    limit: usize,
+    model: Model,
+}
+
+impl<Alg> Endgame<Alg>
+where
+    Alg: Algorithm,
+{
+    pub fn new(inner: Alg, limit: usize) -> Self {
+        Self {
+            inner,
+            limit,
+            model: Model::default(),
+        }
+    }
+
+    pub fn model(mut self, model: Model) -> Self {
+        self.model = model;
+        self
+    }
+
+    /// Solved move if the endgame is small enough
+    fn solve(&self, state: &State, player: &Player) -> Option<Option<Move>> {
+        tree_size(state, player, self.limit)?;
+        Some(solve(state, player, self.model).best)
+    }
+}
+
+impl<Alg> Algorithm for Endgame<Alg>
+where
+    Alg: Algorithm,
+{
+    fn decide(
+        &self,
+        rng: &mut rand::rngs::ThreadRng,
+        state: &State,
+        player: &Player,
+        move_number: usize,
+    ) -> Option<Move> {
+        match self.solve(state, player) {
+            Some(mv) => mv,
+            None => self.inner.decide(rng, state, player, move_number),
+        }
+    }
+
+    fn new_game(&self) -> Box<dyn Session + '_> {
+        Box::new(EndgameSession {
+            endgame: self,
+            inner: self.inner.new_game(),
+        })
+    }
+
+    fn name(&self) -> String {
+        format!(
+            "{}, solving {:?} endgames of {} positions",
+            self.inner.name(),
+            self.model,
+            self.limit
+        )
+    }
+}
+
+/// A game of [Endgame], so the inner algorithm can keep its own state
+struct EndgameSession<'a, Alg: Algorithm> {
+    endgame: &'a Endgame<Alg>,
+    inner: Box<dyn Session + 'a>,
+}
+
+impl<Alg: Algorithm> Session for EndgameSession<'_, Alg> {
+    fn decide(
+        &mut self,
+        rng: &mut rand::rngs::ThreadRng,
+        state: &State,
+        player: &Player,
+        move_number: usize,
+    ) -> Option<Move> {
+        match self.endgame.solve(state, player) {
+            Some(mv) => mv,
+            None => self.inner.decide(rng, state, player, move_number),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{evaluate::Random, game::Game};
+    use rand::seq::SliceRandom;
+
+    /// Play randomly until the game tree is small
+    fn endgame(limit: usize) -> Game {
+        let mut rng = rand::thread_rng();
+        loop {
+            let mut game = Game::new(State::new(14, 14));
+            while !game.is_over() {
+                let state = game.state();
+                let player = game.to_move();
+                let total: usize = Player::iter().map(|p| state.count_moves(&p)).sum();
+                if total < 12
+                    && state.count_moves(&player) > 0
+                    && tree_size(state, &player, limit).is_some()
+                {
+                    return game;
+                }
+                let mv = state.get_moves(&player).choose(&mut rng).cloned();
+                game.play_or_pass(mv);
+            }
+        }
+    }
+
+    /// Paranoid search without pruning
+    fn reference(state: &State, root: &Player, player: Player, mut done: [bool; Player::N]) -> i32 {
+        let Some(player) = next_to_move(state, player, &mut done) else {
+            return margin(state.scores(), root);
+        };
+        let values = state.get_moves(&player).into_iter().map(|mv| {
+            let mut nstate = state.clone();
+            nstate.place_piece(&mv);
+            reference(&nstate, root, player.next(), done)
+        });
+        match player == *root {
+            true => values.max().unwrap(),
+            false => values.min().unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_solve_paranoid() {
+        for _ in 0..3 {
+            let game = endgame(2000);
+            let (state, player) = (game.state(), game.to_move());
+            let solution = solve(state, &player, Model::Paranoid);
+            let expected = reference(state, &player, player, [false; Player::N]);
+            assert_eq!(solution.margin, expected);
+            assert!(solution.nodes <= 2000 + 1);
+
+            let moves = solve_moves(state, &player, Model::Paranoid);
+            assert_eq!(moves.iter().map(|(_, v)| *v).max(), Some(expected));
+            let best = solution.best.unwrap();
+            assert!(moves.contains(&(best, expected)));
+        }
+    }
+
+    #[test]
+    fn test_solve_max_n() {
+        let game = endgame(2000);
+        let (state, player) = (game.state(), game.to_move());
+        let solution = solve(state, &player, Model::MaxN);
+        let moves = solve_moves(state, &player, Model::MaxN);
+        assert_eq!(moves.iter().map(|(_, v)| *v).max(), Some(solution.margin));
+        // opponents that don't play against us can't do better for us than paranoid ones
+        assert!(solution.margin >= solve(state, &player, Model::Paranoid).margin);
+    }
+
+    #[test]
+    fn test_tree_size() {
+        let state = State::new(20, 20);
+        assert_eq!(tree_size(&state, &Player::Player1, 1000), None);
+
+        let game = endgame(500);
+        let size = tree_size(game.state(), &game.to_move(), 500).unwrap();
+        assert_eq!(tree_size(game.state(), &game.to_move(), size), Some(size));
+        if size > 0 {
+            assert_eq!(tree_size(game.state(), &game.to_move(), size - 1), None);
+        }
+    }
+
+    #[test]
+    fn test_endgame_algorithm() {
+        let mut rng = rand::thread_rng();
+        let algorithm = Endgame::new(Random, 2000);
+        // out of the endgame the inner algorithm plays
+        let state = State::new(20, 20);
+        assert!(Algorithm::decide(&algorithm, &mut rng, &state, &Player::Player1, 1).is_some());
+
+        let game = endgame(2000);
+        let (state, player) = (game.state(), game.to_move());
+        let mv = Algorithm::decide(&algorithm, &mut rng, state, &player, 0).unwrap();
+        let best = solve(state, &player, Model::Paranoid).margin;
+        let moves = solve_moves(state, &player, Model::Paranoid);
+        assert!(moves.contains(&(mv, best)));
+    }
+}
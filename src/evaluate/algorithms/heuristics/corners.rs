@@ -0,0 +1,81 @@
+use super::Heuristic;
+use crate::game::{Corner, Dimensioned, Neighbor, Player, State};
+
+/// The number of cells a player can start a new piece from
+#[derive(Default)]
+pub struct CornerCount;
+
+unsafe impl Sync for CornerCount {}
+
+impl Heuristic for CornerCount {
+    fn name(&self) -> String {
+        "Corner Count".to_owned()
+    }
+
+    type Key = usize;
+
+    fn evaluate(&self, _: &mut rand::rngs::ThreadRng, state: &State, player: &Player) -> Self::Key {
+        state.corners(player).len()
+    }
+}
+
+/// Whether a cell would be a corner of `player` if it were empty,
+/// I.E. it touches the player's color diagonally but not on an edge
+fn is_corner_of(state: &State, player: Player, (x, y): (i8, i8)) -> bool {
+    Corner::iter().any(|dir| {
+        let (cx, cy) = dir + (x, y);
+        state.cell(cx, cy) == Some(player)
+    }) && Neighbor::iter().all(|dir| {
+        let (nx, ny) = dir + (x, y);
+        state.cell(nx, ny) != Some(player)
+    })
+}
+
+/// The number of enemy corners covered by a player's pieces
+#[derive(Default)]
+pub struct BlockedCorners;
+
+unsafe impl Sync for BlockedCorners {}
+
+impl Heuristic for BlockedCorners {
+    fn name(&self) -> String {
+        "Blocked Corners".to_owned()
+    }
+
+    type Key = usize;
+
+    fn evaluate(&self, _: &mut rand::rngs::ThreadRng, state: &State, player: &Player) -> Self::Key {
+        let mut blocked = 0;
+        for y in 0..state.h() {
+            for x in 0..state.w() {
+                if state.cell(x, y) != Some(*player) {
+                    continue;
+                }
+                blocked += Player::iter()
+                    .filter(|p| p != player)
+                    .filter(|p| is_corner_of(state, *p, (x, y)))
+                    .count();
+            }
+        }
+        blocked
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::evaluate::algorithms::heuristics::fixtures::walled_board;
+
+    #[test]
+    fn test_blocked_corners() {
+        let state = walled_board();
+
+        // (3, 0) would be Player 3's corner off (4, 1) and (4, 3) would be
+        // Player 4's off (3, 4); the other diagonal contacts also share an edge
+        let mut rng = rand::thread_rng();
+        let blocked: Vec<_> = Player::iter()
+            .map(|p| BlockedCorners.evaluate(&mut rng, &state, &p))
+            .collect();
+        assert_eq!(blocked, vec![0, 1, 1, 0]);
+    }
+}
@@ -0,0 +1,48 @@
+//! Positions shared by the heuristic tests
+
+use crate::game::{Player, State};
+
+/// Play the move of `player` that covers exactly `cells`
+pub fn place(state: &mut State, player: Player, cells: &[(i8, i8)]) {
+    let mut cells = cells.to_vec();
+    cells.sort();
+    let mv = state
+        .get_moves(&player)
+        .into_iter()
+        .find(|mv| {
+            let mut covered = state.move_cells(mv);
+            covered.sort();
+            covered == cells
+        })
+        .expect("no move covers these cells");
+    state.place_piece(&mv);
+}
+
+/// A 5x5 board with every player's first piece along the edge
+/// ```text
+/// 1 2 2 2 2
+/// . . . . 3
+/// . . . . 3
+/// . . . . 3
+/// 4 4 4 4 3
+/// ```
+pub fn walled_board() -> State {
+    let mut state = State::new(5, 5);
+    place(&mut state, Player::Player1, &[(0, 0)]);
+    place(
+        &mut state,
+        Player::Player2,
+        &[(1, 0), (2, 0), (3, 0), (4, 0)],
+    );
+    place(
+        &mut state,
+        Player::Player3,
+        &[(4, 1), (4, 2), (4, 3), (4, 4)],
+    );
+    place(
+        &mut state,
+        Player::Player4,
+        &[(0, 4), (1, 4), (2, 4), (3, 4)],
+    );
+    state
+}
@@ -0,0 +1,134 @@
+use super::Heuristic;
+use crate::game::{Move, Player, Shape, State};
+
+/// Average number of legal placements of each shape (in tenths),
+/// measured over positions of random games in which the shape was still in hand
+/// (see `measure_placements` in the tests).
+/// Shapes with few placements are hard to fit late in the game.
+const TYPICAL_PLACEMENTS: [i32; Shape::N] = [
+    83,  // I1
+    105, // I2
+    178, // V3
+    73,  // I3
+    54,  // I4
+    268, // L4
+    150, // Z4
+    30,  // O4
+    153, // T4
+    272, // F5
+    38,  // I5
+    207, // L5
+    237, // N5
+    249, // P5
+    114, // T5
+    103, // U5
+    99,  // V5
+    127, // W5
+    34,  // X5
+    251, // Y5
+    112, // Z5
+];
+
+/// Placements assumed for shapes that are not in the standard set
+const AVERAGE_PLACEMENTS: i32 = {
+    let (mut sum, mut i) = (0, 0);
+    while i < Shape::N {
+        sum += TYPICAL_PLACEMENTS[i];
+        i += 1;
+    }
+    sum / Shape::N as i32
+};
+
+/// How much of a burden a piece of the game's set is to keep in hand:
+/// large shapes with few placements are the most awkward
+#[inline]
+fn awkwardness(state: &State, piece: usize) -> i32 {
+    let set = state.piece_set();
+    let placements = match set.standard_shapes()[piece] {
+        Some(shape) => TYPICAL_PLACEMENTS[usize::from(shape)],
+        None => AVERAGE_PLACEMENTS,
+    };
+    set.shapes()[piece].size() as i32 * 1000 / placements
+}
+
+/// The negated awkwardness of the pieces a player has left,
+/// which favours getting rid of big, awkward pieces early
+#[derive(Default)]
+pub struct Inventory;
+
+unsafe impl Sync for Inventory {}
+
+impl Heuristic for Inventory {
+    fn name(&self) -> String {
+        "Inventory".to_owned()
+    }
+
+    type Key = i32;
+
+    fn evaluate(&self, _: &mut rand::rngs::ThreadRng, state: &State, player: &Player) -> Self::Key {
+        -state
+            .remaining_pieces(player)
+            .into_iter()
+            .map(|piece| awkwardness(state, piece))
+            .sum::<i32>()
+    }
+
+    fn evaluate_move(
+        &self,
+        rng: &mut rand::rngs::ThreadRng,
+        state: &State,
+        player: &Player,
+        mv: &Move,
+    ) -> Self::Key {
+        self.evaluate(rng, state, player)
+            + if mv.player == *player {
+                awkwardness(state, state.move_piece(mv)) // the piece left my hand
+            } else {
+                0
+            }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::Game;
+    use rand::seq::SliceRandom;
+
+    /// Average placements of each shape (in tenths) over the positions of random games
+    /// in which the player to move still holds the shape
+    fn measure_placements(games: usize) -> [i32; Shape::N] {
+        let mut rng = rand::thread_rng();
+        let mut totals = [0; Shape::N];
+        let mut samples = [0; Shape::N];
+        for _ in 0..games {
+            let mut game = Game::new(State::new(20, 20));
+            while !game.is_over() {
+                let state = game.state();
+                let player = game.to_move();
+                let counts = state.count_moves_by_piece(&player);
+                for piece in state.remaining_pieces(&player) {
+                    totals[piece] += counts[piece];
+                    samples[piece] += 1;
+                }
+                let mv = state.get_moves(&player).choose(&mut rng).cloned();
+                game.play_or_pass(mv);
+            }
+        }
+        std::array::from_fn(|i| (totals[i] * 10 / samples[i].max(1)) as i32)
+    }
+
+    #[test]
+    fn test_typical_placements() {
+        let measured = measure_placements(10);
+        for (shape, (&typical, &found)) in TYPICAL_PLACEMENTS.iter().zip(&measured).enumerate() {
+            assert!(
+                typical * 3 / 4 <= found && found <= typical * 5 / 4,
+                "{:?}: expected about {}, measured {}",
+                Shape::from(shape),
+                typical,
+                found
+            );
+        }
+    }
+}
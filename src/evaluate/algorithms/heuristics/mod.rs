@@ -1,9 +1,19 @@
+mod corners;
+#[cfg(test)]
+mod fixtures;
 mod heuristic;
+mod inventory;
 mod move_count;
+mod network;
 mod rollout;
 mod score;
+mod territory;
 
+pub use corners::{BlockedCorners, CornerCount};
 pub use heuristic::Heuristic;
+pub use inventory::Inventory;
 pub use move_count::{EnemyMoveCount, MoveCount};
+pub use network::{Layer, Network, NetworkError, Value};
 pub use rollout::Rollout;
 pub use score::Score;
+pub use territory::Territory;
@@ -20,7 +20,7 @@ impl Heuristic for MoveCount {
         state: &crate::game::State,
         player: &Player,
     ) -> Self::Key {
-        state.get_moves(player).len()
+        state.count_moves(player)
     }
 }
 
@@ -45,7 +45,7 @@ impl Heuristic for EnemyMoveCount {
     ) -> Self::Key {
         Player::iter()
             .filter(|p| p != player)
-            .map(|p| state.get_moves(&p).len())
+            .map(|p| state.count_moves(&p))
             .sum()
     }
 }
@@ -0,0 +1,589 @@
+use super::Heuristic;
+use crate::evaluate::{encode_pieces, encode_planes, move_index, policy_size, PLANES};
+use crate::game::{Dimensioned, Move, Player, Shape, State};
+use std::{cmp::Ordering, fmt::Display, io::Read, path::PathBuf};
+
+/// Type alias for the serialization of a [Layer], as its kind, shape, weights and bias
+type StoredLayer = (String, Vec<usize>, Vec<f32>, Vec<f32>);
+/// Type alias for the serialization of a [Network], as its board size, trunk, value head and policy head
+type StoredNetwork = (i8, i8, Vec<StoredLayer>, Vec<StoredLayer>, Vec<StoredLayer>);
+
+/// Number of extra inputs after the trunk, whether every player still has every piece
+const PIECE_INPUTS: usize = Player::N * Shape::N;
+
+/// Reasons a network could not be built or loaded
+#[derive(Debug)]
+pub enum NetworkError {
+    /// The file is not a stored network
+    Json(serde_json::Error),
+    /// A layer kind other than `conv` or `dense`, or a shape of the wrong length for its kind
+    UnknownLayer(String, Vec<usize>),
+    /// The weights or bias of a layer don't match its shape
+    WeightCount { expected: usize, found: usize },
+    /// A layer takes a different number of inputs than it is given
+    Inputs { expected: usize, found: usize },
+    /// A head gives a different number of outputs than it should
+    Outputs { expected: usize, found: usize },
+}
+
+impl Display for NetworkError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NetworkError::Json(err) => write!(f, "Invalid network file: {}", err),
+            NetworkError::UnknownLayer(kind, shape) => {
+                write!(f, "Unknown layer {} with shape {:?}", kind, shape)
+            }
+            NetworkError::WeightCount { expected, found } => {
+                write!(f, "Expected {} weights, found {}", expected, found)
+            }
+            NetworkError::Inputs { expected, found } => {
+                write!(f, "Layer takes {} inputs but is given {}", expected, found)
+            }
+            NetworkError::Outputs { expected, found } => {
+                write!(f, "Head should give {} outputs, found {}", expected, found)
+            }
+        }
+    }
+}
+
+impl std::error::Error for NetworkError {}
+
+impl From<serde_json::Error> for NetworkError {
+    fn from(err: serde_json::Error) -> Self {
+        NetworkError::Json(err)
+    }
+}
+
+/// A layer of a [Network]
+#[derive(Clone, PartialEq, Debug)]
+pub enum Layer {
+    /// A square convolution with an odd `size`, padded with zeros so the board keeps its size.
+    /// Weights are indexed by output channel, input channel, row and column.
+    Conv {
+        inputs: usize,
+        outputs: usize,
+        size: usize,
+        weights: Vec<f32>,
+        bias: Vec<f32>,
+    },
+    /// A fully connected layer, with weights indexed by output and input
+    Dense {
+        inputs: usize,
+        outputs: usize,
+        weights: Vec<f32>,
+        bias: Vec<f32>,
+    },
+}
+
+impl Layer {
+    pub fn conv(
+        inputs: usize,
+        outputs: usize,
+        size: usize,
+        weights: Vec<f32>,
+        bias: Vec<f32>,
+    ) -> Result<Self, NetworkError> {
+        if size.is_multiple_of(2) {
+            return Err(NetworkError::UnknownLayer(
+                "conv".to_owned(),
+                vec![outputs, inputs, size, size],
+            ));
+        }
+        check_count(outputs * inputs * size * size, weights.len())?;
+        check_count(outputs, bias.len())?;
+        Ok(Layer::Conv {
+            inputs,
+            outputs,
+            size,
+            weights,
+            bias,
+        })
+    }
+
+    pub fn dense(
+        inputs: usize,
+        outputs: usize,
+        weights: Vec<f32>,
+        bias: Vec<f32>,
+    ) -> Result<Self, NetworkError> {
+        check_count(outputs * inputs, weights.len())?;
+        check_count(outputs, bias.len())?;
+        Ok(Layer::Dense {
+            inputs,
+            outputs,
+            weights,
+            bias,
+        })
+    }
+
+    fn inputs(&self) -> usize {
+        match self {
+            Layer::Conv { inputs, .. } | Layer::Dense { inputs, .. } => *inputs,
+        }
+    }
+
+    fn outputs(&self) -> usize {
+        match self {
+            Layer::Conv { outputs, .. } | Layer::Dense { outputs, .. } => *outputs,
+        }
+    }
+
+    /// Apply the layer to channels of a `w` by `h` board, or to a vector for dense layers
+    fn forward(&self, input: &[f32], (w, h): (usize, usize)) -> Vec<f32> {
+        match self {
+            Layer::Conv {
+                inputs,
+                outputs,
+                size,
+                weights,
+                bias,
+            } => {
+                let r = (size / 2) as isize;
+                let mut output = vec![0.; outputs * w * h];
+                for o in 0..*outputs {
+                    for y in 0..h {
+                        for x in 0..w {
+                            let mut sum = bias[o];
+                            for i in 0..*inputs {
+                                let kernel = &weights[(o * inputs + i) * size * size..];
+                                for ky in 0..*size {
+                                    let iy = y as isize + ky as isize - r;
+                                    if iy < 0 || iy >= h as isize {
+                                        continue;
+                                    }
+                                    for kx in 0..*size {
+                                        let ix = x as isize + kx as isize - r;
+                                        if ix < 0 || ix >= w as isize {
+                                            continue;
+                                        }
+                                        sum += kernel[ky * size + kx]
+                                            * input[i * w * h + iy as usize * w + ix as usize];
+                                    }
+                                }
+                            }
+                            output[o * w * h + y * w + x] = sum;
+                        }
+                    }
+                }
+                output
+            }
+            Layer::Dense {
+                inputs,
+                weights,
+                bias,
+                ..
+            } => bias
+                .iter()
+                .enumerate()
+                .map(|(o, b)| {
+                    let row = &weights[o * inputs..(o + 1) * inputs];
+                    b + row.iter().zip(input).map(|(w, x)| w * x).sum::<f32>()
+                })
+                .collect(),
+        }
+    }
+}
+
+fn check_count(expected: usize, found: usize) -> Result<(), NetworkError> {
+    match expected == found {
+        true => Ok(()),
+        false => Err(NetworkError::WeightCount { expected, found }),
+    }
+}
+
+fn relu(values: &mut [f32]) {
+    for v in values {
+        *v = v.max(0.);
+    }
+}
+
+/// Run dense layers, with a ReLU between every two
+fn head(layers: &[Layer], mut values: Vec<f32>) -> Vec<f32> {
+    for (i, layer) in layers.iter().enumerate() {
+        values = layer.forward(&values, (1, 1));
+        if i + 1 < layers.len() {
+            relu(&mut values);
+        }
+    }
+    values
+}
+
+/// Output of the value head, ordered so it can be maximized
+#[derive(Clone, Copy, Debug)]
+pub struct Value(pub f32);
+
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Value {}
+
+impl PartialOrd for Value {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Value {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+/// A learned evaluation of a `w` by `h` board, run on the cpu.
+///
+/// The input is the [encode_planes] and [encode_pieces] of the position,
+/// with the players reordered so the evaluated player comes first.
+/// The planes go through the convolutions of the trunk, each followed by a ReLU.
+/// The flattened trunk output and the pieces then go through the dense layers of each head,
+/// with a ReLU between every two layers.
+/// The value head gives a single value for the evaluated player,
+/// and the optional policy head gives a logit for every [move_index].
+#[derive(Clone, PartialEq, Debug)]
+pub struct Network {
+    w: i8,
+    h: i8,
+    trunk: Vec<Layer>,
+    value: Vec<Layer>,
+    policy: Vec<Layer>,
+}
+
+impl Network {
+    /// Build a network, checking that the layers fit together.
+    /// The policy head may be empty.
+    pub fn new(
+        w: i8,
+        h: i8,
+        trunk: Vec<Layer>,
+        value: Vec<Layer>,
+        policy: Vec<Layer>,
+    ) -> Result<Self, NetworkError> {
+        let cells = w as usize * h as usize;
+        let mut channels = PLANES;
+        for layer in &trunk {
+            let Layer::Conv { .. } = layer else {
+                return Err(NetworkError::UnknownLayer("dense".to_owned(), vec![]));
+            };
+            check_inputs(layer, channels)?;
+            channels = layer.outputs();
+        }
+
+        let features = channels * cells + PIECE_INPUTS;
+        for (layers, outputs) in [(&value, Some(1)), (&policy, None)] {
+            let mut size = features;
+            for layer in layers.iter() {
+                let Layer::Dense { .. } = layer else {
+                    return Err(NetworkError::UnknownLayer("conv".to_owned(), vec![]));
+                };
+                check_inputs(layer, size)?;
+                size = layer.outputs();
+            }
+            let expected = outputs.unwrap_or(policy_size(w, h));
+            if (outputs.is_some() || !layers.is_empty()) && size != expected {
+                return Err(NetworkError::Outputs {
+                    expected,
+                    found: size,
+                });
+            }
+        }
+
+        Ok(Self {
+            w,
+            h,
+            trunk,
+            value,
+            policy,
+        })
+    }
+
+    pub fn has_policy(&self) -> bool {
+        !self.policy.is_empty()
+    }
+
+    /// Inputs of the dense heads for a position evaluated for `player`
+    fn features(&self, state: &State, player: &Player) -> Vec<f32> {
+        assert_eq!(
+            (state.w(), state.h()),
+            (self.w, self.h),
+            "The network was trained on another board size"
+        );
+        let cells = self.w as usize * self.h as usize;
+        let pid = usize::from(player);
+        let planes = encode_planes(state);
+        let pieces = encode_pieces(state);
+
+        // move every player's planes and pieces so the evaluated player comes first
+        let mut input = vec![0.; PLANES * cells];
+        for plane in 0..PLANES {
+            let (group, p) = (plane / Player::N, plane % Player::N);
+            let to = group * Player::N + (p + Player::N - pid) % Player::N;
+            for (i, cell) in planes[plane * cells..(plane + 1) * cells]
+                .iter()
+                .enumerate()
+            {
+                input[to * cells + i] = *cell as f32;
+            }
+        }
+        for layer in &self.trunk {
+            input = layer.forward(&input, (self.w as usize, self.h as usize));
+            relu(&mut input);
+        }
+        for p in 0..Player::N {
+            let from = (p + pid) % Player::N;
+            input.extend(
+                pieces[from * Shape::N..(from + 1) * Shape::N]
+                    .iter()
+                    .map(|piece| *piece as f32),
+            );
+        }
+        input
+    }
+
+    /// Value of a position for `player`
+    pub fn value(&self, state: &State, player: &Player) -> f32 {
+        head(&self.value, self.features(state, player))[0]
+    }
+
+    /// Value of a position for `player` and the probability of each of `moves`,
+    /// which are uniform without a policy head
+    pub fn predict(&self, state: &State, player: &Player, moves: &[Move]) -> (f32, Vec<f32>) {
+        let features = self.features(state, player);
+        let value = head(&self.value, features.clone())[0];
+        if !self.has_policy() {
+            return (value, vec![1. / moves.len() as f32; moves.len()]);
+        }
+
+        let logits = head(&self.policy, features);
+        let logits: Vec<_> = moves
+            .iter()
+            .map(|mv| logits[move_index(state, mv)])
+            .collect();
+        // softmax over the legal moves only
+        let max = logits.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+        let exps: Vec<_> = logits.iter().map(|l| (l - max).exp()).collect();
+        let sum: f32 = exps.iter().sum();
+        (value, exps.into_iter().map(|e| e / sum).collect())
+    }
+
+    /// Probability of each of `moves` for `player`, uniform without a policy head
+    pub fn policy(&self, state: &State, player: &Player, moves: &[Move]) -> Vec<f32> {
+        self.predict(state, player, moves).1
+    }
+
+    pub fn save(&self, path: PathBuf) -> Result<(), serde_json::Error> {
+        let store = StoredNetwork::from(self);
+        let file = std::fs::File::create(path).unwrap();
+        serde_json::to_writer(file, &store)
+    }
+
+    /// Load a network from the JSON of its [StoredNetwork]:
+    /// `[w, h, trunk, value, policy]` where every layer is `[kind, shape, weights, bias]`,
+    /// with kind `conv` and shape `[outputs, inputs, size, size]`
+    /// or kind `dense` and shape `[outputs, inputs]`
+    pub fn load(reader: impl Read) -> Result<Self, NetworkError> {
+        let (w, h, trunk, value, policy): StoredNetwork = serde_json::from_reader(reader)?;
+        let layers = |stored: Vec<StoredLayer>| -> Result<Vec<Layer>, NetworkError> {
+            stored
+                .into_iter()
+                .map(
+                    |(kind, shape, weights, bias)| match (kind.as_str(), &shape[..]) {
+                        ("conv", [outputs, inputs, size, size2]) if size == size2 => {
+                            Layer::conv(*inputs, *outputs, *size, weights, bias)
+                        }
+                        ("dense", [outputs, inputs]) => {
+                            Layer::dense(*inputs, *outputs, weights, bias)
+                        }
+                        _ => Err(NetworkError::UnknownLayer(kind, shape)),
+                    },
+                )
+                .collect()
+        };
+        Self::new(w, h, layers(trunk)?, layers(value)?, layers(policy)?)
+    }
+}
+
+fn check_inputs(layer: &Layer, given: usize) -> Result<(), NetworkError> {
+    match layer.inputs() == given {
+        true => Ok(()),
+        false => Err(NetworkError::Inputs {
+            expected: layer.inputs(),
+            found: given,
+        }),
+    }
+}
+
+impl From<&Layer> for StoredLayer {
+    fn from(layer: &Layer) -> Self {
+        match layer.clone() {
+            Layer::Conv {
+                inputs,
+                outputs,
+                size,
+                weights,
+                bias,
+            } => (
+                "conv".to_owned(),
+                vec![outputs, inputs, size, size],
+                weights,
+                bias,
+            ),
+            Layer::Dense {
+                inputs,
+                outputs,
+                weights,
+                bias,
+            } => ("dense".to_owned(), vec![outputs, inputs], weights, bias),
+        }
+    }
+}
+
+impl From<&Network> for StoredNetwork {
+    fn from(network: &Network) -> Self {
+        let layers = |layers: &[Layer]| layers.iter().map(StoredLayer::from).collect();
+        (
+            network.w,
+            network.h,
+            layers(&network.trunk),
+            layers(&network.value),
+            layers(&network.policy),
+        )
+    }
+}
+
+impl Heuristic for Network {
+    fn name(&self) -> String {
+        "Network".to_owned()
+    }
+
+    type Key = Value;
+
+    fn evaluate(&self, _: &mut rand::rngs::ThreadRng, state: &State, player: &Player) -> Self::Key {
+        Value(self.value(state, player))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::evaluate::{Algorithm, GreedyMax, MiniMax, MonteCarlo};
+
+    /// A network whose value is the number of cells the player covers,
+    /// and whose policy prefers higher move indices
+    fn counting_network(w: i8, h: i8) -> Network {
+        let cells = w as usize * h as usize;
+        // keep only the evaluated player's pieces on the board
+        let mut kernel = vec![0.; PLANES];
+        kernel[0] = 1.;
+        let trunk = vec![Layer::conv(PLANES, 1, 1, kernel, vec![0.]).unwrap()];
+        // sum them up, ignoring the pieces in hand
+        let features = cells + PIECE_INPUTS;
+        let mut weights = vec![0.; 2 * features];
+        weights[..cells].fill(1.);
+        weights[features + cells..].fill(-1.);
+        let value = vec![
+            Layer::dense(features, 2, weights, vec![0., 0.]).unwrap(),
+            Layer::dense(2, 1, vec![1., 0.], vec![0.]).unwrap(),
+        ];
+        let moves = policy_size(w, h);
+        let bias = (0..moves).map(|i| i as f32 / moves as f32).collect();
+        let policy = vec![Layer::dense(features, moves, vec![0.; moves * features], bias).unwrap()];
+        Network::new(w, h, trunk, value, policy).unwrap()
+    }
+
+    #[test]
+    fn test_counting_network() {
+        let mut rng = rand::thread_rng();
+        let network = counting_network(10, 10);
+        let mut state = State::new(10, 10);
+        for player in Player::iter().take(3) {
+            let mv = state.get_moves(&player).into_iter().next().unwrap();
+            state.place_piece(&mv);
+        }
+        for player in Player::iter() {
+            let value = network.evaluate(&mut rng, &state, &player);
+            assert_eq!(value, Value(state.scores()[usize::from(player)] as f32));
+        }
+
+        // a 3x3 convolution sees the neighbours of every cell but not past the edge
+        let mut kernel = vec![0.; PLANES * 9];
+        kernel[..9].fill(1.);
+        let cells = 100;
+        let trunk = vec![Layer::conv(PLANES, 1, 3, kernel, vec![0.]).unwrap()];
+        let mut weights = vec![0.; cells + PIECE_INPUTS];
+        weights[0] = 1.;
+        let value = vec![Layer::dense(cells + PIECE_INPUTS, 1, weights, vec![0.]).unwrap()];
+        let corner = Network::new(10, 10, trunk, value, vec![]).unwrap();
+        let covered = [(0, 0), (1, 0), (0, 1), (1, 1)]
+            .into_iter()
+            .filter(|(x, y)| state.cell(*x, *y) == Some(Player::Player1))
+            .count();
+        assert_eq!(corner.value(&state, &Player::Player1), covered as f32);
+
+        // the policy only spreads over the given moves
+        let moves = state.get_moves(&Player::Player4);
+        let policy = network.policy(&state, &Player::Player4, &moves);
+        assert!((policy.iter().sum::<f32>() - 1.).abs() < 1e-5);
+        let best = (0..moves.len())
+            .max_by(|a, b| policy[*a].total_cmp(&policy[*b]))
+            .unwrap();
+        let highest = moves.iter().map(|mv| move_index(&state, mv)).max().unwrap();
+        assert_eq!(move_index(&state, &moves[best]), highest);
+    }
+
+    #[test]
+    fn test_search_with_network() {
+        let mut rng = rand::thread_rng();
+        let network = counting_network(10, 10);
+        let state = State::new(10, 10);
+        let player = Player::Player1;
+
+        // counting cells is the same as maximizing the score
+        let greedy = GreedyMax::new(network.clone());
+        let mv = greedy.decide(&mut rng, &state, &player, 1).unwrap();
+        assert_eq!(state.move_size(&mv), 5);
+        let minimax = MiniMax::<1, _>::new(network.clone());
+        let mv = minimax.decide(&mut rng, &state, &player, 1).unwrap();
+        assert!(state.get_moves(&player).contains(&mv));
+        let mc = MonteCarlo::with_heuristic(network, 20, f64::sqrt(2.)).widening(2., 0.5);
+        let mv = mc.decide(&mut rng, &state, &player, 1).unwrap();
+        assert!(state.get_moves(&player).contains(&mv));
+    }
+
+    #[test]
+    fn test_load() {
+        let network = counting_network(10, 10);
+        let path = std::env::temp_dir().join(format!("network-test-{}.json", std::process::id()));
+        network.save(path.clone()).unwrap();
+        let loaded = Network::load(std::fs::File::open(&path).unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded, network);
+
+        let load = |json: &str| Network::load(json.as_bytes());
+        assert!(matches!(load("[3, 3]"), Err(NetworkError::Json(_))));
+        let value = format!(r#"["dense", [1, {}], [], [0]]"#, 8 * 9 + PIECE_INPUTS);
+        assert!(matches!(
+            load(&format!("[3, 3, [], [{}], []]", value)),
+            Err(NetworkError::WeightCount { found: 0, .. })
+        ));
+        assert!(matches!(
+            load(r#"[3, 3, [["conv", [1, 2, 1, 1], [0, 0], [0]]], [], []]"#),
+            Err(NetworkError::Inputs {
+                expected: 2,
+                found: 8
+            })
+        ));
+        assert!(matches!(
+            load(r#"[3, 3, [["pool", [2], [], []]], [], []]"#),
+            Err(NetworkError::UnknownLayer(..))
+        ));
+        assert!(matches!(
+            load(r#"[3, 3, [], [], []]"#),
+            Err(NetworkError::Outputs {
+                expected: 1,
+                found: _
+            })
+        ));
+    }
+}
@@ -1,6 +1,7 @@
 use super::Heuristic;
+use crate::evaluate::random_playout;
 use crate::game::{Player, State};
-use rand::seq::{IteratorRandom, SliceRandom};
+use rand::seq::IteratorRandom;
 
 /// Algorithm that rolls out random games and returns the number of wins
 pub struct Rollout {
@@ -16,31 +17,7 @@ impl Rollout {
 
 /// Run a random rollout and return whether this player won or not
 fn random_rollout(rng: &mut rand::rngs::ThreadRng, state: &State, player: Player) -> bool {
-    let mut state = state.clone();
-    let mut player = player;
-    let mut dones = [false; Player::N];
-    loop {
-        if dones.iter().all(|u| *u) {
-            // All players are done, the game is over
-            break;
-        }
-
-        if dones[usize::from(player)] {
-            player = player.next();
-            continue;
-        }
-        let moves = state.get_moves(&player);
-        if moves.is_empty() {
-            dones[usize::from(player)] = true;
-            player = player.next();
-            continue;
-        }
-
-        let mv = moves.choose(rng).unwrap();
-        state.place_piece(mv);
-
-        player = player.next();
-    }
+    let state = random_playout(rng, state, player);
 
     // Check if the player won
     let scores = state.scores();
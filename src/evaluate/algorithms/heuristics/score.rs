@@ -1,5 +1,4 @@
 use super::Heuristic;
-use crate::game::piece_size;
 
 #[derive(Default)]
 pub struct Score;
@@ -27,7 +26,7 @@ impl Heuristic for Score {
         // Place the piece that gives me the highest score
         state.scores()[usize::from(player)]
             + if mv.player == *player {
-                piece_size(mv) // I moved, so my score went up
+                state.move_size(mv) // I moved, so my score went up
             } else {
                 0 // I didn't move
             }
@@ -0,0 +1,69 @@
+use super::Heuristic;
+use crate::game::{Dimensioned, Neighbor, Player, State};
+
+/// Flood fill from a player's corners through every cell they could cover.
+/// Returns a row-major grid of the reached cells.
+fn reachable(state: &State, player: &Player) -> Vec<bool> {
+    let (w, h) = (state.w() as usize, state.h() as usize);
+    let mut seen = vec![false; w * h];
+    let mut stack = state.corners(player);
+    for &(x, y) in stack.iter() {
+        seen[y as usize * w + x as usize] = true;
+    }
+
+    while let Some(pos) = stack.pop() {
+        for dir in Neighbor::iter() {
+            let (x, y) = dir + pos;
+            if state.is_free(player, (x, y)) && !seen[y as usize * w + x as usize] {
+                seen[y as usize * w + x as usize] = true;
+                stack.push((x, y));
+            }
+        }
+    }
+    seen
+}
+
+/// The number of cells that only this player can still reach
+#[derive(Default)]
+pub struct Territory;
+
+unsafe impl Sync for Territory {}
+
+impl Heuristic for Territory {
+    fn name(&self) -> String {
+        "Territory".to_owned()
+    }
+
+    type Key = usize;
+
+    fn evaluate(&self, _: &mut rand::rngs::ThreadRng, state: &State, player: &Player) -> Self::Key {
+        let mine = reachable(state, player);
+        let theirs: Vec<_> = Player::iter()
+            .filter(|p| p != player)
+            .map(|p| reachable(state, &p))
+            .collect();
+
+        (0..mine.len())
+            .filter(|i| mine[*i] && theirs.iter().all(|t| !t[*i]))
+            .count()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::evaluate::algorithms::heuristics::fixtures::walled_board;
+
+    #[test]
+    fn test_walled_board() {
+        let state = walled_board();
+
+        // Player 1 floods from (1, 1) but can't use (0, 1), which only
+        // Player 2 reaches. Everything below row 1 is shared between them.
+        let mut rng = rand::thread_rng();
+        let owned: Vec<_> = Player::iter()
+            .map(|p| Territory.evaluate(&mut rng, &state, &p))
+            .collect();
+        assert_eq!(owned, vec![3, 1, 0, 0]);
+    }
+}
@@ -1,17 +1,64 @@
 use super::{Algorithm, Heuristic};
-use crate::game::{Move, Player, State};
-use rand::seq::SliceRandom;
+use crate::game::{symmetry::skip_mirrored, Move, Player, State};
+use rand::{rngs::ThreadRng, seq::SliceRandom};
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
 
 /// The minimax algorithm
 /// Depth is the number of moves to look ahead
 #[derive(Clone)]
 pub struct MiniMax<const DEPTH: usize, H: Heuristic> {
     heuristic: H,
+    /// Search the root moves in parallel
+    parallel: bool,
+    /// Only search one of every group of moves mirroring each other, see [skip_mirrored]
+    skip_mirrored: bool,
 }
 
 impl<const DEPTH: usize, H: Heuristic> MiniMax<DEPTH, H> {
     pub fn new(heuristic: H) -> Self {
-        Self { heuristic }
+        Self {
+            heuristic,
+            parallel: false,
+            skip_mirrored: false,
+        }
+    }
+
+    /// Split the root moves between threads, each with its own rng
+    pub fn parallel(mut self) -> Self {
+        self.parallel = true;
+        self
+    }
+
+    /// Skip moves that mirror another move from the point of view of the player to move.
+    /// This is a heuristic, the skipped moves may be worth more, see [crate::game::symmetry].
+    pub fn skip_mirrored(mut self) -> Self {
+        self.skip_mirrored = true;
+        self
+    }
+
+    /// Value of playing a move at the root
+    fn evaluate_root(
+        &self,
+        rng: &mut ThreadRng,
+        state: &State,
+        player: &Player,
+        mv: &Move,
+    ) -> H::Key {
+        let mut nstate = state.clone();
+        nstate.place_piece(mv);
+        minimax(
+            rng,
+            DEPTH - 1,
+            player,
+            &player.next(),
+            &nstate,
+            &self.heuristic,
+            self.skip_mirrored,
+        )
+        .unwrap_or(
+            // If there are no moves, then the game is over so the score is just the current score
+            self.heuristic.evaluate(rng, &nstate, player),
+        )
     }
 }
 
@@ -22,10 +69,21 @@ where
     fn default() -> Self {
         Self {
             heuristic: Default::default(),
+            parallel: false,
+            skip_mirrored: false,
         }
     }
 }
 
+/// Moves of a player, without the mirrored ones if they are skipped
+fn generate_moves(state: &State, player: &Player, mirrored: bool) -> Vec<Move> {
+    let moves = state.get_moves(player);
+    match mirrored {
+        true => skip_mirrored(state, player, moves),
+        false => moves,
+    }
+}
+
 fn minimax<H: Heuristic>(
     rng: &mut rand::rngs::ThreadRng,
     depth: usize,
@@ -35,8 +93,9 @@ fn minimax<H: Heuristic>(
     player: &Player,
     state: &State,
     heuristic: &H,
+    skip_mirrored: bool,
 ) -> Option<H::Key> {
-    let moves = state.get_moves(player);
+    let moves = generate_moves(state, player, skip_mirrored);
     match depth {
         0 => None,
         1 => {
@@ -65,6 +124,7 @@ fn minimax<H: Heuristic>(
                         &player.next(),
                         &nstate,
                         heuristic,
+                        skip_mirrored,
                     )
                 });
             // Recurse
@@ -76,9 +136,20 @@ fn minimax<H: Heuristic>(
     }
 }
 
-impl<const DEPTH: usize, H: Heuristic> Algorithm for MiniMax<DEPTH, H> {
+impl<const DEPTH: usize, H> Algorithm for MiniMax<DEPTH, H>
+where
+    H: Heuristic + Sync,
+    H::Key: Send,
+{
     fn name(&self) -> String {
-        format!("MiniMax {} Depth {}", self.heuristic.name(), DEPTH)
+        let name = match self.parallel {
+            true => format!("Parallel MiniMax {} Depth {}", self.heuristic.name(), DEPTH),
+            false => format!("MiniMax {} Depth {}", self.heuristic.name(), DEPTH),
+        };
+        match self.skip_mirrored {
+            true => format!("{}, mirrored moves skipped", name),
+            false => name,
+        }
     }
 
     fn decide(
@@ -89,23 +160,86 @@ impl<const DEPTH: usize, H: Heuristic> Algorithm for MiniMax<DEPTH, H> {
         _: usize,
     ) -> Option<Move> {
         // Find the move that maximizes the minimax algorithm
-        let mut moves = state.get_moves(player);
+        let mut moves = generate_moves(state, player, self.skip_mirrored);
         moves.shuffle(rng); // Shuffle so that ties are resolved randomly
-        moves.into_iter().max_by_key(|mv| {
-            let mut nstate = state.clone();
-            nstate.place_piece(mv);
-            minimax(
-                rng,
-                DEPTH - 1,
-                player,
-                &player.next(),
-                &nstate,
-                &self.heuristic,
-            )
-            .unwrap_or(
-                // If there are no moves, then the game is over so the score is just the current score
-                self.heuristic.evaluate(rng, &nstate, player),
-            )
-        })
+        match self.parallel {
+            // Like the serial max_by_key, the last of the shuffled maximums is chosen
+            true => moves
+                .into_par_iter()
+                .map_init(rand::thread_rng, |rng, mv| {
+                    (self.evaluate_root(rng, state, player, &mv), mv)
+                })
+                .max_by(|(a, _), (b, _)| a.cmp(b))
+                .map(|(_, mv)| mv),
+            false => moves
+                .into_iter()
+                .max_by_key(|mv| self.evaluate_root(rng, state, player, mv)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::evaluate::Score;
+
+    #[test]
+    fn test_parallel_matches_serial() {
+        let mut rng = rand::thread_rng();
+        let state = State::new(20, 20);
+        let player = Player::Player1;
+        let serial = MiniMax::<1, Score>::default();
+        let parallel = MiniMax::<1, Score>::default().parallel();
+
+        // Both have to find a move with the best value
+        let best = state
+            .get_moves(&player)
+            .iter()
+            .map(|mv| serial.evaluate_root(&mut rng, &state, &player, mv))
+            .max();
+        for alg in [&serial, &parallel] {
+            let mv = alg.decide(&mut rng, &state, &player, 0).unwrap();
+            assert_eq!(
+                Some(serial.evaluate_root(&mut rng, &state, &player, &mv)),
+                best
+            );
+        }
+    }
+
+    #[test]
+    fn test_skip_mirrored_at_depth_1() {
+        let mut rng = rand::thread_rng();
+        let state = State::new(20, 20);
+        let player = Player::Player1;
+        let full = MiniMax::<1, Score>::default();
+        let skipping = MiniMax::<1, Score>::default().skip_mirrored();
+
+        // The score of a single move only counts its tiles, so mirrored moves tie
+        // and skipping them doesn't lose the best value here
+        let best = state
+            .get_moves(&player)
+            .iter()
+            .map(|mv| full.evaluate_root(&mut rng, &state, &player, mv))
+            .max();
+        let mv = skipping.decide(&mut rng, &state, &player, 0).unwrap();
+        assert_eq!(
+            Some(full.evaluate_root(&mut rng, &state, &player, &mv)),
+            best
+        );
+        assert!(
+            generate_moves(&state, &player, skipping.skip_mirrored).len()
+                < generate_moves(&state, &player, full.skip_mirrored).len()
+        );
+    }
+
+    #[test]
+    fn test_parallel_ties_are_random() {
+        let mut rng = rand::thread_rng();
+        let state = State::new(20, 20);
+        let parallel = MiniMax::<1, Score>::default().parallel();
+
+        // Every opening 5-tile move ties, so the choice should not always be the same
+        let first = parallel.decide(&mut rng, &state, &Player::Player1, 0);
+        assert!((0..20).any(|_| parallel.decide(&mut rng, &state, &Player::Player1, 0) != first));
     }
 }
@@ -1,4 +1,4 @@
-use super::Algorithm;
+use super::{Algorithm, Session};
 use rand::Rng;
 
 /// Algorithm that stochastically chooses between two other algorithms.
@@ -66,6 +66,14 @@ where
         }
     }
 
+    fn new_game(&self) -> Box<dyn Session + '_> {
+        Box::new(MixSession {
+            s1: self.alg1.new_game(),
+            s2: self.alg2.new_game(),
+            ratio: self.ratio,
+        })
+    }
+
     fn name(&self) -> String {
         format!(
             "{}% {}, {}% {}",
@@ -77,6 +85,28 @@ where
     }
 }
 
+/// A game of [Mix], so both algorithms can keep their own state
+struct MixSession<'a> {
+    s1: Box<dyn Session + 'a>,
+    s2: Box<dyn Session + 'a>,
+    ratio: f64,
+}
+
+impl Session for MixSession<'_> {
+    fn decide(
+        &mut self,
+        rng: &mut rand::rngs::ThreadRng,
+        state: &crate::game::State,
+        player: &crate::game::Player,
+        move_number: usize,
+    ) -> Option<crate::game::Move> {
+        match rng.gen_bool(self.ratio) {
+            true => self.s1.decide(rng, state, player, move_number),
+            false => self.s2.decide(rng, state, player, move_number),
+        }
+    }
+}
+
 /// Algorithm that follows the first for `n` moves, then the second for the rest of the game.
 pub struct Opening<Alg1, Alg2>
 where
@@ -143,6 +173,14 @@ where
         }
     }
 
+    fn new_game(&self) -> Box<dyn Session + '_> {
+        Box::new(OpeningSession {
+            s1: self.alg1.new_game(),
+            s2: self.alg2.new_game(),
+            opening_length: self.opening_length,
+        })
+    }
+
     fn name(&self) -> String {
         format!(
             "Open {} moves with {}, then {}",
@@ -152,3 +190,26 @@ where
         )
     }
 }
+
+/// A game of [Opening], so both algorithms can keep their own state
+struct OpeningSession<'a> {
+    s1: Box<dyn Session + 'a>,
+    s2: Box<dyn Session + 'a>,
+    opening_length: usize,
+}
+
+impl Session for OpeningSession<'_> {
+    fn decide(
+        &mut self,
+        rng: &mut rand::rngs::ThreadRng,
+        state: &crate::game::State,
+        player: &crate::game::Player,
+        move_number: usize,
+    ) -> Option<crate::game::Move> {
+        if move_number <= self.opening_length {
+            self.s1.decide(rng, state, player, move_number)
+        } else {
+            self.s2.decide(rng, state, player, move_number)
+        }
+    }
+}
@@ -1,17 +1,23 @@
 mod algorithm;
+mod book;
 mod distance;
+mod endgame;
 mod greedy;
 mod heuristics;
 mod minimax;
 mod mix;
 mod montecarlo;
+mod puct;
 mod random;
 
-pub use algorithm::Algorithm;
+pub use algorithm::{Algorithm, Session};
+pub use book::{Book, BookMove, OpeningBook};
 pub use distance::Distance;
+pub use endgame::{solve, solve_moves, tree_size, Endgame, Model, Solution};
 pub use greedy::{GreedyMax, GreedyMin};
 pub use heuristics::*;
 pub use minimax::MiniMax;
 pub use mix::{Mix, Opening};
-pub use montecarlo::MonteCarlo;
+pub use montecarlo::{MonteCarlo, Reward};
+pub use puct::{Evaluator, HeuristicEvaluator, Numeric, Puct};
 pub use random::Random;
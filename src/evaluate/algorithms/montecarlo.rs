@@ -1,72 +1,69 @@
-use super::Algorithm;
-use crate::game::{Move, Player, State};
-use rand::{
-    rngs::ThreadRng,
-    seq::{IteratorRandom, SliceRandom},
-};
+use super::{Algorithm, Heuristic, Session};
+use crate::evaluate::playout;
+use crate::game::{symmetry::skip_mirrored, Dimensioned, Move, Neighbor, Player, State};
+use rand::{rngs::ThreadRng, seq::SliceRandom, Rng};
+use rayon::iter::{IntoParallelRefMutIterator, ParallelIterator};
+use rustc_hash::FxHashMap;
+
+/// All-moves-as-first statistics for a child of a node,
+/// from the perspective of the player choosing between the children
+#[derive(Default, Clone)]
+struct Amaf {
+    reward: f64,
+    visits: usize,
+}
 
 /// An initialized node in the Monte Carlo tree
 struct Branch {
     state: State,
     player: Player,
+    /// Children of this node. When progressive widening is enabled,
+    /// these are sorted from most to least promising by the heuristic.
     children: Vec<Node>,
+    /// AMAF statistics for every child, only updated when RAVE is enabled
+    amaf: Vec<Amaf>,
+    /// Index of every child by its move, used to update the AMAF statistics
+    index: FxHashMap<Move, usize>,
     /// Move that led to this node
     mv: Option<Move>,
-    wins: usize,
+    /// Sum of the rewards of every simulation through this node, for every player
+    rewards: [f64; Player::N],
     visits: usize,
-    /// How many players did not play in their last turn
-    done: u8,
-}
-
-/// Get the winner at a given state
-/// Ties are broken randomly
-fn get_winner(rng: &mut rand::rngs::ThreadRng, state: &State) -> Player {
-    let scores = state.scores();
-    let max_score = scores.iter().max().unwrap();
-    // Get all players with the max score and choose one randomly
-    // (because ties are broken randomly)
-    let (winner, _) = scores
-        .iter()
-        .enumerate()
-        .filter(|(_, &s)| s == *max_score)
-        .choose(rng)
-        .unwrap();
-
-    Player::from(winner)
+    /// Players that have no moves left, they will never be able to move again
+    dones: [bool; Player::N],
 }
 
-/// Run a random rollout and return whether this player won or not
-fn random_rollout(rng: &mut rand::rngs::ThreadRng, state: &State, player: Player) -> bool {
-    let mut state = state.clone();
-    let mut player = player;
-    let mut dones = [false; Player::N];
-    loop {
-        if dones.iter().all(|u| *u) {
-            // All players are done, the game is over
-            break;
-        }
-
-        if dones[usize::from(player)] {
-            player = player.next();
-            continue;
-        }
+/// How the final position of a simulation is rewarded for every player
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Reward {
+    /// The winners split a single point between them
+    WinShare,
+    /// Every player gets their score as a fraction of the highest possible score
+    Score,
+}
 
-        let moves = state.get_moves(&player);
-        if moves.is_empty() {
-            dones[usize::from(player)] = true;
-            player = player.next();
-            continue;
+impl Reward {
+    /// Get the reward of every player at the end of a game
+    pub fn evaluate(&self, state: &State) -> [f64; Player::N] {
+        let scores = state.scores();
+        match self {
+            Reward::WinShare => {
+                let max_score = scores.iter().max().unwrap();
+                let winners = scores.iter().filter(|s| *s == max_score).count();
+                scores.map(|s| match s == *max_score {
+                    true => 1. / winners as f64,
+                    false => 0.,
+                })
+            }
+            Reward::Score => {
+                let max_score: usize = state.piece_set().shapes().iter().map(|s| s.size()).sum();
+                scores.map(|s| s as f64 / max_score as f64)
+            }
         }
-
-        let mv = moves.choose(rng).unwrap();
-        state.place_piece(mv);
-
-        player = player.next();
     }
-
-    get_winner(rng, &state) == player
 }
 
+#[allow(clippy::large_enum_variant)]
 enum Node {
     /// A branch node
     Branch(Branch),
@@ -75,145 +72,546 @@ enum Node {
     Leaf(Option<Move>),
 }
 
+impl Branch {
+    /// Create a branch for a state where `player` is about to choose one of `moves`
+    fn new<H: Heuristic>(
+        rng: &mut ThreadRng,
+        mc: &MonteCarlo<H>,
+        state: State,
+        player: Player,
+        mv: Option<Move>,
+        mut moves: Vec<Move>,
+        dones: [bool; Player::N],
+    ) -> Self {
+        // shuffle the children to avoid bias
+        moves.shuffle(rng);
+
+        if mc.widening.is_some() {
+            // Sort by the heuristic so the most promising moves are expanded first,
+            // the sort is stable so ties stay shuffled
+            let mut keyed: Vec<_> = moves
+                .into_iter()
+                .map(|mv| (mc.heuristic.evaluate_move(rng, &state, &player, &mv), mv))
+                .collect();
+            keyed.sort_by(|(a, _), (b, _)| b.cmp(a));
+            moves = keyed.into_iter().map(|(_, mv)| mv).collect();
+        }
+
+        let (index, children) = if moves.is_empty() {
+            // The player has no moves, create a leaf with an empty move
+            (FxHashMap::default(), vec![Node::Leaf(None)])
+        } else {
+            (
+                match mc.rave {
+                    Some(_) => moves
+                        .iter()
+                        .cloned()
+                        .enumerate()
+                        .map(|(i, m)| (m, i))
+                        .collect(),
+                    None => FxHashMap::default(),
+                },
+                moves.into_iter().map(|m| Node::Leaf(Some(m))).collect(),
+            )
+        };
+
+        Self {
+            state,
+            player,
+            amaf: vec![Amaf::default(); children.len()],
+            index,
+            children,
+            mv,
+            rewards: [0.; Player::N],
+            visits: 0,
+            dones,
+        }
+    }
+
+    /// Number of children that can be selected at the current visit count
+    #[inline]
+    fn width<H: Heuristic>(&self, mc: &MonteCarlo<H>) -> usize {
+        match mc.widening {
+            Some((base, exponent)) => {
+                let width = (base * ((self.visits + 1) as f64).powf(exponent)).ceil() as usize;
+                width.clamp(1, self.children.len())
+            }
+            None => self.children.len(),
+        }
+    }
+
+    /// Get the selection value of a child for the player choosing at this node
+    #[inline]
+    fn score<H: Heuristic>(&self, mc: &MonteCarlo<H>, i: usize) -> f64 {
+        let parent_visits = self.visits as f64;
+        let amaf = &self.amaf[i];
+        match (&self.children[i], mc.rave) {
+            (
+                Node::Branch(Branch {
+                    rewards, visits, ..
+                }),
+                rave,
+            ) => {
+                // Every player picks the child that is best for themselves (max-n)
+                let (reward, visits) = (rewards[usize::from(self.player)], *visits as f64);
+                let exploitation = match rave {
+                    Some(k) if amaf.visits > 0 => {
+                        // Weight the AMAF value less as the child gets more real visits
+                        let beta = (k / (3. * visits + k)).sqrt();
+                        (1. - beta) * reward / visits + beta * amaf.reward / amaf.visits as f64
+                    }
+                    _ => reward / visits,
+                };
+                exploitation + mc.c * (parent_visits / visits).sqrt()
+            }
+            // With RAVE, unvisited nodes are treated as if they had been visited once
+            // with their AMAF value, so moves that did well elsewhere are tried first
+            (Node::Leaf(_), Some(_)) => {
+                let value = match amaf.visits {
+                    0 => 1.,
+                    _ => amaf.reward / amaf.visits as f64,
+                };
+                value + mc.c * parent_visits.sqrt()
+            }
+            // Unvisited nodes always take precedence
+            (Node::Leaf(_), _) => f64::INFINITY,
+        }
+    }
+}
+
 impl Node {
     /// Create a root node
     /// Returns [None] if the player has no moves
-    pub fn root(state: State, player: Player) -> Option<Self> {
+    fn root<H: Heuristic>(
+        rng: &mut ThreadRng,
+        mc: &MonteCarlo<H>,
+        state: State,
+        player: Player,
+    ) -> Option<Self> {
         // figure out how many moves each player has
-        let mut done = 0;
+        let mut dones = [false; Player::N];
         let mut children = None;
         for p in Player::iter() {
             let moves = state.get_moves(&p);
+            dones[usize::from(p)] = moves.is_empty();
             if p == player {
                 if moves.is_empty() {
                     return None;
                 }
-                children = Some(moves.iter().cloned().map(|m| Node::Leaf(Some(m))).collect());
+                children = Some(mc.generate_moves(&state, &p, moves));
             }
-            done += moves.is_empty() as u8;
         }
 
-        Some(Self::Branch(Branch {
+        Some(Self::Branch(Branch::new(
+            rng,
+            mc,
             state,
             player,
-            children: children.unwrap(),
-            mv: None,
-            wins: 0,
-            visits: 0,
-            done,
-        }))
+            None,
+            children.unwrap(),
+            dones,
+        )))
     }
 
-    /// Get the UCT value of this node
-    #[inline]
-    pub fn score(&self, c: f64, parent_visits: f64) -> f64 {
+    /// Move that led to this node
+    fn mv(&self) -> &Option<Move> {
         match self {
-            Node::Branch(Branch { wins, visits, .. }) => {
-                *wins as f64 / *visits as f64 + c * (parent_visits / *visits as f64).sqrt()
-            }
-            // Unvisited nodes always take precedence
-            Node::Leaf(_) => f64::INFINITY,
+            Node::Branch(Branch { mv, .. }) => mv,
+            Node::Leaf(mv) => mv,
         }
     }
 
-    pub fn rollout(&mut self, rng: &mut ThreadRng, c: f64) -> bool {
+    /// Number of simulations through this node
+    fn visits(&self) -> usize {
         match self {
-            Node::Branch(Branch {
-                state,
-                player,
-                children,
-                wins,
-                visits,
-                done,
-                ..
-            }) => {
-                // This node has already been initialized
-                // Here, we choose a child based on the UCT algorithm described in
-                // https://en.wikipedia.org/wiki/Monte_Carlo_tree_search
-
-                if *done >= Player::N as u8 {
-                    // All players are done, the game is over
-                    return get_winner(rng, state) == *player;
+            Node::Branch(Branch { visits, .. }) => *visits,
+            Node::Leaf(_) => 0,
+        }
+    }
+
+    /// Find the node for `state` with `player` to move among this node and its descendants,
+    /// following the moves that were played to reach it.
+    /// Returns [None] if the position was never expanded in this tree.
+    fn advance(self, state: &State, player: Player) -> Option<Self> {
+        let mut node = self;
+        loop {
+            let Node::Branch(mut branch) = node else {
+                return None;
+            };
+            if branch.player == player
+                && branch.state.scores() == state.scores()
+                && is_ancestor(&branch.state, state)
+            {
+                return Some(Node::Branch(branch));
+            }
+            // A move whose cells lie inside a piece played later, like I1 inside I2,
+            // still leaves an ancestor, so the child must have placed that exact piece
+            let i = branch.children.iter().position(|child| match child {
+                Node::Branch(child) => {
+                    child.mv.as_ref().is_none_or(|mv| {
+                        is_piece(state, branch.player, &branch.state.move_cells(mv))
+                    }) && is_ancestor(&child.state, state)
                 }
-                // shuffle the children to avoid bias
-                children.shuffle(rng);
-
-                let best = children
-                    .iter_mut()
-                    .map(|n| {
-                        let score = n.score(c, *visits as f64);
-                        (score, n)
-                    })
-                    .max_by(|(a, _), (b, _)| a.partial_cmp(b).unwrap())
-                    .unwrap()
-                    .1;
-
-                let rollout = if let Node::Leaf(mv) = best {
-                    // Initialize the node
-                    let mut nstate = state.clone();
-                    // place the move if it exists
-                    if let Some(mv) = mv {
-                        nstate.place_piece(mv);
-                    }
-                    let nplayer = player.next();
-                    let mut ndone = *done;
-
-                    let nmoves = nstate.get_moves(&nplayer);
-                    let nmoves = if nmoves.is_empty() {
-                        ndone += 1;
-                        // The player has no moves, create a leaf with an empty move
-                        vec![Node::Leaf(None)]
-                    } else {
-                        ndone = 0;
-                        nmoves
-                            .iter()
-                            .cloned()
-                            .map(|m| Node::Leaf(Some(m)))
-                            .collect()
-                    };
-
-                    let rollout = random_rollout(rng, &nstate, nplayer);
-
-                    *best = Node::Branch(Branch {
-                        state: nstate,
-                        player: nplayer,
-                        mv: mv.clone(),
-                        children: nmoves,
-                        wins: rollout as usize,
-                        visits: 1,
-                        done: ndone,
-                    });
-
-                    rollout
-                } else {
-                    best.rollout(rng, c)
-                };
+                Node::Leaf(_) => false,
+            })?;
+            node = branch.children.swap_remove(i);
+        }
+    }
+
+    /// Run one simulation through this node.
+    /// Every move played from here on is appended to `trail`.
+    fn rollout<H: Heuristic>(
+        &mut self,
+        rng: &mut ThreadRng,
+        mc: &MonteCarlo<H>,
+        trail: &mut Vec<Move>,
+    ) -> [f64; Player::N] {
+        let Node::Branch(branch) = self else {
+            unreachable!("Cannot rollout an uninitialized node")
+        };
+        // This node has already been initialized
+        // Here, we choose a child based on the UCT algorithm described in
+        // https://en.wikipedia.org/wiki/Monte_Carlo_tree_search
 
-                *visits += 1;
-                *wins += rollout as usize;
+        if branch.dones.iter().all(|d| *d) {
+            // All players are done, the game is over
+            let rewards = mc.reward.evaluate(&branch.state);
+            branch.visits += 1;
+            branch
+                .rewards
+                .iter_mut()
+                .zip(rewards)
+                .for_each(|(total, r)| *total += r);
+            return rewards;
+        }
+
+        let start = trail.len();
+
+        let best = (0..branch.width(mc))
+            .map(|i| (branch.score(mc, i), i))
+            .max_by(|(a, _), (b, _)| a.partial_cmp(b).unwrap())
+            .unwrap()
+            .1;
+
+        let rollout = match &mut branch.children[best] {
+            Node::Leaf(mv) => {
+                // Initialize the node
+                let mv = mv.take();
+                let mut nstate = branch.state.clone();
+                // place the move if it exists
+                if let Some(mv) = &mv {
+                    nstate.place_piece(mv);
+                    trail.push(mv.clone());
+                }
+                let nplayer = branch.player.next();
+
+                let nmoves = nstate.get_moves(&nplayer);
+                let mut ndones = branch.dones;
+                ndones[usize::from(nplayer)] = nmoves.is_empty();
+                let nmoves = mc.generate_moves(&nstate, &nplayer, nmoves);
+
+                let rollout = mc.random_rollout(rng, &nstate, nplayer, trail);
+
+                let mut nbranch = Branch::new(rng, mc, nstate, nplayer, mv, nmoves, ndones);
+                nbranch.rewards = rollout;
+                nbranch.visits = 1;
+                branch.children[best] = Node::Branch(nbranch);
 
-                // Propogate the win upward
                 rollout
             }
-            Node::Leaf(_) => unreachable!("Cannot rollout an uninitialized node"),
+            child @ Node::Branch(_) => {
+                if let Node::Branch(Branch { mv: Some(mv), .. }) = child {
+                    trail.push(mv.clone());
+                }
+                child.rollout(rng, mc, trail)
+            }
+        };
+
+        branch.visits += 1;
+        branch
+            .rewards
+            .iter_mut()
+            .zip(rollout)
+            .for_each(|(total, r)| *total += r);
+
+        if mc.rave.is_some() {
+            // Every move this player made in the rest of the simulation
+            // counts as if it had been played first
+            for mv in trail[start..]
+                .iter()
+                .filter(|mv| mv.player == branch.player)
+            {
+                if let Some(&i) = branch.index.get(mv) {
+                    branch.amaf[i].visits += 1;
+                    branch.amaf[i].reward += rollout[usize::from(branch.player)];
+                }
+            }
         }
+
+        // Propogate the rewards upward
+        rollout
     }
 }
 
-pub struct MonteCarlo {
+/// Whether every cell covered in `earlier` is covered by the same player in `later`
+fn is_ancestor(earlier: &State, later: &State) -> bool {
+    (0..earlier.h()).all(|y| {
+        (0..earlier.w()).all(|x| match earlier.cell(x, y) {
+            Some(p) => later.cell(x, y) == Some(p),
+            None => true,
+        })
+    })
+}
+
+/// Whether `cells` are exactly one piece of `player` in `state`.
+/// Pieces of a player never share an edge, so a piece is a group of the player's cells
+/// connected by edges.
+fn is_piece(state: &State, player: Player, cells: &[(i8, i8)]) -> bool {
+    if !cells
+        .iter()
+        .all(|(x, y)| state.cell(*x, *y) == Some(player))
+    {
+        return false;
+    }
+    let mut piece = vec![cells[0]];
+    let mut i = 0;
+    while i < piece.len() && piece.len() <= cells.len() {
+        for dir in Neighbor::iter() {
+            let (x, y) = dir + piece[i];
+            if state.cell(x, y) == Some(player) && !piece.contains(&(x, y)) {
+                piece.push((x, y));
+            }
+        }
+        i += 1;
+    }
+    piece.len() == cells.len()
+}
+
+/// Monte Carlo tree search using UCT.
+/// RAVE, progressive widening and heuristic-guided rollouts
+/// can each be enabled separately.
+pub struct MonteCarlo<H: Heuristic> {
     /// Number of simulations to run
     simulations: usize,
     /// Constant for the UCT algorithm
     c: f64,
+    /// Heuristic used to order moves for progressive widening and to guide rollouts
+    heuristic: H,
+    /// RAVE equivalence parameter, the number of visits
+    /// at which AMAF and real statistics are weighted equally
+    rave: Option<f64>,
+    /// Progressive widening as `(base, exponent)`, a node visited `n` times
+    /// only considers its best `base * n ^ exponent` children
+    widening: Option<(f64, f64)>,
+    /// Probability of playing a random move instead of the heuristic's best move in rollouts
+    epsilon: Option<f64>,
+    /// How the end of a simulation is rewarded
+    reward: Reward,
+    /// Keep the tree between moves of a game
+    reuse: bool,
+    /// Number of independent trees searched in parallel
+    threads: usize,
+    /// Only expand one of every group of moves mirroring each other, see [skip_mirrored]
+    skip_mirrored: bool,
 }
 
-impl MonteCarlo {
+unsafe impl<H: Heuristic + Sync> Sync for MonteCarlo<H> {}
+
+impl<H> MonteCarlo<H>
+where
+    H: Heuristic + Default,
+{
     pub fn new(simulations: usize, c: f64) -> Self {
-        Self { simulations, c }
+        Self::with_heuristic(Default::default(), simulations, c)
     }
 }
 
-impl Algorithm for MonteCarlo {
+impl<H: Heuristic> MonteCarlo<H> {
+    pub fn with_heuristic(heuristic: H, simulations: usize, c: f64) -> Self {
+        Self {
+            simulations,
+            c,
+            heuristic,
+            rave: None,
+            widening: None,
+            epsilon: None,
+            reward: Reward::WinShare,
+            reuse: false,
+            threads: 1,
+            skip_mirrored: false,
+        }
+    }
+
+    /// Set how the end of a simulation is rewarded
+    pub fn reward(mut self, reward: Reward) -> Self {
+        self.reward = reward;
+        self
+    }
+
+    /// Enable RAVE with the given equivalence parameter
+    pub fn rave(mut self, k: f64) -> Self {
+        self.rave = Some(k);
+        self
+    }
+
+    /// Enable progressive widening, only expanding the `base * n ^ exponent`
+    /// moves ranked best by the heuristic
+    pub fn widening(mut self, base: f64, exponent: f64) -> Self {
+        self.widening = Some((base, exponent));
+        self
+    }
+
+    /// Use epsilon-greedy rollouts that play the heuristic's best move
+    /// with probability `1 - epsilon`
+    pub fn guided(mut self, epsilon: f64) -> Self {
+        debug_assert!((0. ..=1.).contains(&epsilon));
+        self.epsilon = Some(epsilon);
+        self
+    }
+
+    /// Keep the tree between moves of a game, continuing the search
+    /// from the position reached after every player has moved
+    pub fn reuse_tree(mut self) -> Self {
+        self.reuse = true;
+        self
+    }
+
+    /// Split the simulations between `threads` independent trees searched in parallel,
+    /// the trees are merged by their visit counts to choose a move
+    pub fn threads(mut self, threads: usize) -> Self {
+        debug_assert!(threads > 0);
+        self.threads = threads;
+        self
+    }
+
+    /// Only expand one of every group of moves that mirror each other from the point of view
+    /// of the player to move. This is a heuristic, the skipped moves may be worth more,
+    /// see [crate::game::symmetry].
+    pub fn skip_mirrored(mut self) -> Self {
+        self.skip_mirrored = true;
+        self
+    }
+
+    /// Children to expand out of the moves of a player
+    fn generate_moves(&self, state: &State, player: &Player, moves: Vec<Move>) -> Vec<Move> {
+        match self.skip_mirrored {
+            true => skip_mirrored(state, player, moves),
+            false => moves,
+        }
+    }
+
+    /// Create a root node for every tree
+    /// Returns [None] if the player has no moves
+    fn roots(&self, rng: &mut ThreadRng, state: &State, player: Player) -> Option<Vec<Node>> {
+        (0..self.threads)
+            .map(|_| Node::root(rng, self, state.clone(), player))
+            .collect()
+    }
+
+    /// Run the simulations from a root node
+    fn search(&self, rng: &mut ThreadRng, root: &mut Node, simulations: usize) {
+        let mut trail = Vec::new();
+        for _ in 0..simulations {
+            trail.clear();
+            root.rollout(rng, self, &mut trail);
+        }
+    }
+
+    /// Run the simulations split between the roots, in parallel if there are several
+    fn search_roots(&self, rng: &mut ThreadRng, roots: &mut [Node])
+    where
+        H: Sync,
+    {
+        match roots {
+            [root] => self.search(rng, root, self.simulations),
+            _ => {
+                let simulations = self.simulations.div_ceil(roots.len());
+                roots
+                    .par_iter_mut()
+                    .for_each(|root| self.search(&mut rand::thread_rng(), root, simulations));
+            }
+        }
+    }
+
+    /// Choose the move with the most visits summed over the searched roots.
+    /// Returns the move along with its subtree in every root where it was expanded,
+    /// and the summed visits of every move.
+    fn best_move(
+        rng: &mut ThreadRng,
+        roots: Vec<Node>,
+    ) -> (Option<Move>, Vec<Node>, Vec<(Move, usize)>) {
+        let mut visits: FxHashMap<Option<Move>, usize> = FxHashMap::default();
+        for root in &roots {
+            let Node::Branch(Branch { children, .. }) = root else {
+                unreachable!("Root node is not a branch node");
+            };
+            for child in children {
+                *visits.entry(child.mv().clone()).or_default() += child.visits();
+            }
+        }
+
+        // shuffle the moves to avoid bias
+        let mut moves: Vec<_> = visits.into_iter().collect();
+        moves.shuffle(rng);
+        let (mv, _) = moves.iter().max_by_key(|(_, v)| *v).unwrap().clone();
+        let visits = moves
+            .into_iter()
+            .filter_map(|(mv, v)| Some((mv?, v)))
+            .collect();
+
+        let subtrees = roots
+            .into_iter()
+            .filter_map(|root| {
+                let Node::Branch(Branch { mut children, .. }) = root else {
+                    unreachable!("Root node is not a branch node");
+                };
+                let i = children.iter().position(|child| *child.mv() == mv)?;
+                match children.swap_remove(i) {
+                    child @ Node::Branch(_) => Some(child),
+                    Node::Leaf(_) => None,
+                }
+            })
+            .collect();
+
+        (mv, subtrees, visits)
+    }
+
+    /// Choose the move to play in a rollout
+    /// Returns [None] if the player has no moves
+    fn rollout_move(&self, rng: &mut ThreadRng, state: &State, player: &Player) -> Option<Move> {
+        match self.epsilon {
+            Some(epsilon) if !rng.gen_bool(epsilon) => {
+                let mut moves = state.get_moves(player);
+                // we shuffle here so that ties are resolved randomly
+                moves.shuffle(rng);
+                moves
+                    .into_iter()
+                    .max_by_key(|mv| self.heuristic.evaluate_move(rng, state, player, mv))
+            }
+            _ => state.random_move(rng, player),
+        }
+    }
+
+    /// Run a rollout and return the reward of every player
+    /// Every move played is appended to `trail`
+    fn random_rollout(
+        &self,
+        rng: &mut ThreadRng,
+        state: &State,
+        player: Player,
+        trail: &mut Vec<Move>,
+    ) -> [f64; Player::N] {
+        let state = playout(rng, state, player, |rng, state, player| {
+            let mv = self.rollout_move(rng, state, player)?;
+            trail.push(mv.clone());
+            Some(mv)
+        });
+
+        self.reward.evaluate(&state)
+    }
+}
+
+impl<H: Heuristic + Sync> Algorithm for MonteCarlo<H> {
     fn decide(
         &self,
         rng: &mut rand::rngs::ThreadRng,
@@ -221,35 +619,478 @@ impl Algorithm for MonteCarlo {
         player: &Player,
         _: usize,
     ) -> Option<Move> {
-        if let Some(mut root) = Node::root(state.clone(), *player) {
-            for _ in 0..self.simulations {
-                root.rollout(rng, self.c);
+        let mut roots = self.roots(rng, state, *player)?;
+        self.search_roots(rng, &mut roots);
+        Self::best_move(rng, roots).0
+    }
+
+    fn new_game(&self) -> Box<dyn Session + '_> {
+        Box::new(MonteCarloSession {
+            mc: self,
+            trees: Vec::new(),
+            visits: Vec::new(),
+        })
+    }
+
+    fn name(&self) -> String {
+        let mut name = format!("Monte Carlo {} sims", self.simulations);
+        if self.reward == Reward::Score {
+            name += ", score reward";
+        }
+        if let Some(k) = self.rave {
+            name += &format!(", RAVE {}", k);
+        }
+        if let Some((base, exponent)) = self.widening {
+            name += &format!(
+                ", widening {}n^{} by {}",
+                base,
+                exponent,
+                self.heuristic.name()
+            );
+        }
+        if let Some(epsilon) = self.epsilon {
+            name += &format!(", {}-greedy {} rollouts", epsilon, self.heuristic.name());
+        }
+        if self.reuse {
+            name += ", tree reuse";
+        }
+        if self.threads > 1 {
+            name += &format!(", {} threads", self.threads);
+        }
+        if self.skip_mirrored {
+            name += ", mirrored moves skipped";
+        }
+        name
+    }
+}
+
+/// A game of [MonteCarlo], which keeps its trees between moves if tree reuse is enabled
+struct MonteCarloSession<'a, H: Heuristic> {
+    mc: &'a MonteCarlo<H>,
+    /// The subtrees of the last move played by this session
+    trees: Vec<Node>,
+    /// Visits of the root moves of the last search
+    visits: Vec<(Move, usize)>,
+}
+
+impl<H: Heuristic + Sync> Session for MonteCarloSession<'_, H> {
+    fn decide(
+        &mut self,
+        rng: &mut rand::rngs::ThreadRng,
+        state: &State,
+        player: &Player,
+        _: usize,
+    ) -> Option<Move> {
+        self.visits.clear();
+        let mut roots: Vec<_> = std::mem::take(&mut self.trees)
+            .into_iter()
+            .filter_map(|tree| tree.advance(state, *player))
+            .collect();
+        if let Some(Node::Branch(Branch { dones, .. })) = roots.first() {
+            if dones[usize::from(player)] {
+                return None;
             }
+        }
+        // Start a new tree wherever the position was not expanded
+        while roots.len() < self.mc.threads {
+            roots.push(Node::root(rng, self.mc, state.clone(), *player)?);
+        }
 
-            let Node::Branch(Branch { mut children, .. }) = root else {
-                unreachable!("Root node is not a branch node");
-            };
+        self.mc.search_roots(rng, &mut roots);
 
-            // shuffle the children to avoid bias
-            children.shuffle(rng);
+        // Remember the subtrees to continue from on the next move
+        let (mv, subtrees, visits) = MonteCarlo::<H>::best_move(rng, roots);
+        if self.mc.reuse {
+            self.trees = subtrees;
+        }
+        self.visits = visits;
+        mv
+    }
 
-            // Find the node with the most visits
-            children
+    fn visits(&self) -> Option<&[(Move, usize)]> {
+        Some(&self.visits)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::evaluate::Score;
+
+    /// Best final score a player can reach if nobody else moves
+    fn best_score(state: &State, player: &Player) -> u8 {
+        state
+            .get_moves(player)
+            .iter()
+            .map(|mv| {
+                let mut nstate = state.clone();
+                nstate.place_piece(mv);
+                best_score(&nstate, player)
+            })
+            .max()
+            .unwrap_or(state.scores()[usize::from(player)])
+    }
+
+    /// Number of different ways a player can finish the game if nobody else moves
+    fn lines(state: &State, player: &Player) -> usize {
+        let moves = state.get_moves(player);
+        match moves.is_empty() {
+            true => 1,
+            false => moves
                 .iter()
-                .max_by_key(|n| match n {
-                    Node::Branch(Branch { visits, .. }) => *visits,
-                    Node::Leaf(_) => 0,
+                .map(|mv| {
+                    let mut nstate = state.clone();
+                    nstate.place_piece(mv);
+                    lines(&nstate, player)
                 })
-                .and_then(|best| match best {
-                    Node::Branch(Branch { mv, .. }) => mv.clone(),
-                    Node::Leaf(mv) => mv.clone(),
+                .sum(),
+        }
+    }
+
+    /// Play the game deterministically until only one player can move
+    /// and they have few enough ways left to finish the game
+    fn single_player_endgame(max_lines: usize) -> (State, Player) {
+        let mut state = State::new(20, 20);
+        let mut player = Player::Player1;
+        loop {
+            let movers: Vec<_> = Player::iter()
+                .filter(|p| !state.get_moves(p).is_empty())
+                .collect();
+            assert!(!movers.is_empty(), "Game ended before a small endgame");
+            if let [last] = movers[..] {
+                if lines(&state, &last) <= max_lines {
+                    return (state, last);
+                }
+            }
+            if let Some(mv) = state.get_moves(&player).last() {
+                state.place_piece(mv);
+            }
+            player = player.next();
+        }
+    }
+
+    /// Play a few rounds deterministically to reach a position with many moves
+    fn midgame(rounds: usize) -> State {
+        let mut state = State::new(20, 20);
+        for _ in 0..rounds {
+            for player in Player::iter() {
+                if let Some(mv) = state.get_moves(&player).last() {
+                    state.place_piece(mv);
+                }
+            }
+        }
+        state
+    }
+
+    #[test]
+    fn test_win_share() {
+        let mut state = State::new(20, 20);
+        assert_eq!(Reward::WinShare.evaluate(&state), [0.25; Player::N]);
+
+        let mv = state.get_moves(&Player::Player2).pop().unwrap();
+        state.place_piece(&mv);
+        assert_eq!(Reward::WinShare.evaluate(&state), [0., 1., 0., 0.]);
+    }
+
+    #[test]
+    fn test_rewards_sum_to_visits() {
+        let mut rng = rand::thread_rng();
+        let mc = MonteCarlo::<Score>::new(200, f64::sqrt(2.));
+        let state = State::new(20, 20);
+        let mut root = Node::root(&mut rng, &mc, state, Player::Player1).unwrap();
+        let mut trail = Vec::new();
+        for _ in 0..mc.simulations {
+            trail.clear();
+            root.rollout(&mut rng, &mc, &mut trail);
+        }
+
+        let Node::Branch(root) = root else {
+            unreachable!("Root node is not a branch node");
+        };
+        assert_eq!(root.visits, mc.simulations);
+        // Every simulation hands out exactly one point between the players
+        for branch in root.children.iter().filter_map(|n| match n {
+            Node::Branch(branch) => Some(branch),
+            Node::Leaf(_) => None,
+        }) {
+            let total: f64 = branch.rewards.iter().sum();
+            assert!((total - branch.visits as f64).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn test_advance() {
+        let mut rng = rand::thread_rng();
+        let mc = MonteCarlo::<Score>::new(300, f64::sqrt(2.)).widening(1., 0.5);
+        let mut root = Node::root(&mut rng, &mc, State::new(20, 20), Player::Player1).unwrap();
+        mc.search(&mut rng, &mut root, mc.simulations);
+
+        // Follow the most visited line a few moves deep
+        let mut node = &root;
+        for _ in 0..Player::N {
+            let Node::Branch(branch) = node else {
+                unreachable!("Only branches are followed");
+            };
+            match branch.children.iter().max_by_key(|child| child.visits()) {
+                Some(child @ Node::Branch(_)) => node = child,
+                _ => break,
+            }
+        }
+        let Node::Branch(target) = node else {
+            unreachable!("Only branches are followed");
+        };
+        assert!(target.mv.is_some());
+        let (state, player, visits) = (target.state.clone(), target.player, target.visits);
+
+        let Some(Node::Branch(found)) = root.advance(&state, player) else {
+            panic!("Expanded position was not found");
+        };
+        assert_eq!(found.visits, visits);
+        assert_eq!(found.state.scores(), state.scores());
+    }
+
+    #[test]
+    fn test_advance_nested_moves() {
+        let mut rng = rand::thread_rng();
+        let mc = MonteCarlo::<Score>::new(10, f64::sqrt(2.));
+        let state = State::new(20, 20);
+        let player = Player::Player1;
+        let mut root = Node::root(&mut rng, &mc, state.clone(), player).unwrap();
+
+        // I1 in the corner lies inside I2 in the corner, expand both
+        let Node::Branch(branch) = &mut root else {
+            unreachable!("Roots are branches");
+        };
+        let mut children = Vec::new();
+        let mut targets = Vec::new();
+        for cells in [vec![(0, 0)], vec![(0, 0), (1, 0)]] {
+            let mv = state
+                .get_moves(&player)
+                .into_iter()
+                .find(|mv| {
+                    let mut covered = state.move_cells(mv);
+                    covered.sort();
+                    covered == cells
                 })
-        } else {
-            None
+                .unwrap();
+            let mut next = state.clone();
+            next.place_piece(&mv);
+            let moves = next.get_moves(&player.next());
+            children.push(Node::Branch(Branch::new(
+                &mut rng,
+                &mc,
+                next.clone(),
+                player.next(),
+                Some(mv.clone()),
+                moves,
+                [false; Player::N],
+            )));
+            targets.push((next, mv));
         }
+        branch.children = children;
+
+        // Both children are ancestors of the position after I2, only the second placed it
+        let (after_i2, i2) = targets.pop().unwrap();
+        assert!(targets.iter().all(|(next, _)| is_ancestor(next, &after_i2)));
+        let Some(Node::Branch(found)) = root.advance(&after_i2, player.next()) else {
+            panic!("Expanded position was not found");
+        };
+        assert!(found.mv == Some(i2));
     }
 
-    fn name(&self) -> String {
-        "Monte Carlo".to_owned()
+    #[test]
+    fn test_tree_reuse() {
+        let mut rng = rand::thread_rng();
+        let mc = MonteCarlo::<Score>::new(100, f64::sqrt(2.))
+            .reuse_tree()
+            .threads(2);
+        let mut session = MonteCarloSession {
+            mc: &mc,
+            trees: Vec::new(),
+            visits: Vec::new(),
+        };
+        let mut state = State::new(20, 20);
+        // Play a few rounds against itself, so every position was reached from the last decision
+        for move_number in 1..=2 {
+            for player in Player::iter() {
+                let mv = session.decide(&mut rng, &state, &player, move_number);
+                let mv = mv.expect("Opening moves always exist");
+                assert!(state.get_moves(&player).contains(&mv));
+                state.place_piece(&mv);
+
+                // The next player's position must be found in every kept subtree
+                assert!(!session.trees.is_empty());
+                let trees = std::mem::take(&mut session.trees);
+                for tree in trees {
+                    session
+                        .trees
+                        .push(tree.advance(&state, player.next()).unwrap());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_root_parallel() {
+        let mut rng = rand::thread_rng();
+        let mc = MonteCarlo::<Score>::new(200, f64::sqrt(2.)).threads(4);
+        let state = State::new(20, 20);
+        let mut roots = mc.roots(&mut rng, &state, Player::Player1).unwrap();
+        mc.search_roots(&mut rng, &mut roots);
+
+        // The simulations are split evenly between the trees
+        assert!(roots.iter().all(|root| root.visits() == 50));
+
+        let (mv, subtrees, visits) = MonteCarlo::<Score>::best_move(&mut rng, roots);
+        let mv = mv.unwrap();
+        assert!(state.get_moves(&Player::Player1).contains(&mv));
+        assert!(!subtrees.is_empty());
+        assert!(subtrees.iter().all(|tree| *tree.mv() == Some(mv.clone())));
+
+        // The visits of every tree are summed, and the chosen move has the most
+        assert_eq!(visits.iter().map(|(_, v)| v).sum::<usize>(), 200);
+        let most = visits.iter().map(|(_, v)| *v).max().unwrap();
+        assert!(visits.contains(&(mv, most)));
+    }
+
+    #[test]
+    fn test_session_visits() {
+        let mut rng = rand::thread_rng();
+        let mc = MonteCarlo::<Score>::new(100, f64::sqrt(2.));
+        let mut session = mc.new_game();
+        assert!(session.visits().is_some_and(|visits| visits.is_empty()));
+        let state = State::new(20, 20);
+        let mv = session
+            .decide(&mut rng, &state, &Player::Player1, 1)
+            .unwrap();
+        let visits = session.visits().unwrap();
+        assert_eq!(visits.iter().map(|(_, v)| v).sum::<usize>(), 100);
+        assert!(visits.iter().any(|(visited, _)| *visited == mv));
+    }
+
+    #[test]
+    fn test_skip_mirrored_root() {
+        let mut rng = rand::thread_rng();
+        let state = State::new(20, 20);
+        let player = Player::Player1;
+        let children = |mc: &MonteCarlo<Score>| {
+            let Some(Node::Branch(root)) =
+                Node::root(&mut rand::thread_rng(), mc, state.clone(), player)
+            else {
+                unreachable!("Opening moves always exist");
+            };
+            root.children.len()
+        };
+        let mc = MonteCarlo::<Score>::new(100, f64::sqrt(2.));
+        let skipping = MonteCarlo::<Score>::new(100, f64::sqrt(2.)).skip_mirrored();
+
+        // The opening looks symmetric about the player's diagonal
+        assert_eq!(children(&mc), state.get_moves(&player).len());
+        assert!(children(&skipping) < children(&mc));
+        let mv = skipping.decide(&mut rng, &state, &player, 1).unwrap();
+        assert!(state.get_moves(&player).contains(&mv));
+    }
+
+    #[test]
+    fn test_amaf_follows_trail() {
+        let mut rng = rand::thread_rng();
+        let mc = MonteCarlo::<Score>::new(50, f64::sqrt(2.)).rave(100.);
+        let player = Player::Player1;
+        let mut root = Node::root(&mut rng, &mc, midgame(3), player).unwrap();
+
+        // Replay every simulation's trail against the root's moves
+        let (mut visits, mut rewards) = (FxHashMap::default(), FxHashMap::default());
+        let mut trail = Vec::new();
+        for _ in 0..mc.simulations {
+            trail.clear();
+            let reward = root.rollout(&mut rng, &mc, &mut trail);
+            for mv in trail.iter().filter(|mv| mv.player == player) {
+                *visits.entry(mv.clone()).or_insert(0) += 1;
+                *rewards.entry(mv.clone()).or_insert(0.) += reward[usize::from(player)];
+            }
+        }
+
+        let Node::Branch(root) = root else {
+            unreachable!("Root node is not a branch node");
+        };
+        for (mv, &i) in root.index.iter() {
+            let amaf = &root.amaf[i];
+            assert_eq!(amaf.visits, visits.get(mv).copied().unwrap_or(0));
+            assert!((amaf.reward - rewards.get(mv).copied().unwrap_or(0.)).abs() < 1e-9);
+            // The move itself always starts the trail of its own simulations
+            assert!(amaf.visits >= root.children[i].visits());
+        }
+        // Moves played later in the rollouts count as well
+        let total: usize = root.amaf.iter().map(|amaf| amaf.visits).sum();
+        assert!(total > root.visits);
+    }
+
+    #[test]
+    fn test_widening() {
+        let mut rng = rand::thread_rng();
+        for (base, exponent) in [(1., 0.5), (1., 0.3)] {
+            let mc = MonteCarlo::<Score>::new(0, f64::sqrt(2.)).widening(base, exponent);
+            let mut root = Node::root(&mut rng, &mc, State::new(20, 20), Player::Player1).unwrap();
+            for n in [1, 10, 50, 200] {
+                let visits = root.visits();
+                mc.search(&mut rng, &mut root, n - visits);
+                let Node::Branch(branch) = &root else {
+                    unreachable!("Root node is not a branch node");
+                };
+
+                // Only the most promising `base * n^exponent` children were expanded
+                let width = (base * (n as f64).powf(exponent)).ceil() as usize;
+                let expanded = branch
+                    .children
+                    .iter()
+                    .take_while(|child| matches!(child, Node::Branch(_)))
+                    .count();
+                assert_eq!(expanded, width);
+                assert!(branch.children[width..]
+                    .iter()
+                    .all(|child| matches!(child, Node::Leaf(_))));
+            }
+        }
+    }
+
+    #[test]
+    fn test_guided_rollouts() {
+        let mut rng = rand::thread_rng();
+        let state = midgame(2);
+        let player = Player::Player1;
+        let best = state
+            .get_moves(&player)
+            .iter()
+            .map(|mv| Score.evaluate_move(&mut rng, &state, &player, mv))
+            .max()
+            .unwrap();
+        let rollout_scores = |epsilon: f64, rng: &mut ThreadRng| -> Vec<u8> {
+            let mc = MonteCarlo::<Score>::new(0, f64::sqrt(2.)).guided(epsilon);
+            (0..50)
+                .map(|_| {
+                    let mv = mc.rollout_move(rng, &state, &player).unwrap();
+                    Score.evaluate_move(rng, &state, &player, &mv)
+                })
+                .collect()
+        };
+
+        // epsilon is the chance of a random move, so 0 always follows the heuristic
+        assert!(rollout_scores(0., &mut rng).iter().all(|s| *s == best));
+        assert!(rollout_scores(1., &mut rng).iter().any(|s| *s < best));
+    }
+
+    #[test]
+    fn test_single_player_endgame() {
+        let mut rng = rand::thread_rng();
+        let (state, player) = single_player_endgame(100);
+        let best = best_score(&state, &player);
+
+        let mc = MonteCarlo::<Score>::new(1000, f64::sqrt(2.)).reward(Reward::Score);
+        let mv = mc.decide(&mut rng, &state, &player, 0).unwrap();
+        let mut nstate = state.clone();
+        nstate.place_piece(&mv);
+
+        // The move must still allow the best possible final score
+        assert_eq!(best_score(&nstate, &player), best);
     }
 }
@@ -0,0 +1,522 @@
+use super::{Algorithm, Heuristic, Network, Reward, Session, Value};
+use crate::evaluate::random_playout;
+use crate::game::{Move, Player, State};
+use rand::{rngs::ThreadRng, seq::SliceRandom};
+use rand_distr::{Dirichlet, Distribution};
+
+/// Gives [Puct] its prior over the moves of a position and the value of the position
+pub trait Evaluator {
+    /// Prior probability of each of `moves` for `player` to move, summing to one,
+    /// and the expected reward of every player from the position
+    fn evaluate(
+        &self,
+        rng: &mut ThreadRng,
+        state: &State,
+        player: &Player,
+        moves: &[Move],
+    ) -> (Vec<f64>, [f64; Player::N]);
+
+    /// String name for the evaluator
+    fn name(&self) -> String;
+}
+
+/// Heuristic keys that can be used as a number
+pub trait Numeric {
+    fn to_f64(&self) -> f64;
+}
+
+impl Numeric for u8 {
+    fn to_f64(&self) -> f64 {
+        *self as f64
+    }
+}
+
+impl Numeric for i32 {
+    fn to_f64(&self) -> f64 {
+        *self as f64
+    }
+}
+
+impl Numeric for usize {
+    fn to_f64(&self) -> f64 {
+        *self as f64
+    }
+}
+
+impl Numeric for Value {
+    fn to_f64(&self) -> f64 {
+        self.0 as f64
+    }
+}
+
+/// Softmax of `logits` divided by `temperature`
+fn softmax(logits: &[f64], temperature: f64) -> Vec<f64> {
+    let max = logits.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    let exps: Vec<_> = logits
+        .iter()
+        .map(|l| ((l - max) / temperature).exp())
+        .collect();
+    let sum: f64 = exps.iter().sum();
+    exps.into_iter().map(|e| e / sum).collect()
+}
+
+/// Evaluator built from a heuristic, for searching without a network.
+/// The prior is a softmax over how much every move changes the heuristic,
+/// and the value is the reward of random rollouts.
+pub struct HeuristicEvaluator<H: Heuristic> {
+    heuristic: H,
+    /// Change of the heuristic that makes a move e times as likely
+    temperature: f64,
+    /// Number of rollouts averaged for the value
+    rollouts: usize,
+    reward: Reward,
+}
+
+impl<H> Default for HeuristicEvaluator<H>
+where
+    H: Heuristic + Default,
+    H::Key: Numeric,
+{
+    fn default() -> Self {
+        Self::new(H::default(), 10.)
+    }
+}
+
+impl<H> HeuristicEvaluator<H>
+where
+    H: Heuristic,
+    H::Key: Numeric,
+{
+    pub fn new(heuristic: H, temperature: f64) -> Self {
+        Self {
+            heuristic,
+            temperature,
+            rollouts: 1,
+            reward: Reward::WinShare,
+        }
+    }
+
+    /// Average the value over several rollouts
+    pub fn rollouts(mut self, rollouts: usize) -> Self {
+        self.rollouts = rollouts;
+        self
+    }
+
+    /// Set how the end of a rollout is rewarded
+    pub fn reward(mut self, reward: Reward) -> Self {
+        self.reward = reward;
+        self
+    }
+}
+
+impl<H> Evaluator for HeuristicEvaluator<H>
+where
+    H: Heuristic,
+    H::Key: Numeric,
+{
+    fn evaluate(
+        &self,
+        rng: &mut ThreadRng,
+        state: &State,
+        player: &Player,
+        moves: &[Move],
+    ) -> (Vec<f64>, [f64; Player::N]) {
+        let before = self.heuristic.evaluate(rng, state, player).to_f64();
+        let deltas: Vec<_> = moves
+            .iter()
+            .map(|mv| {
+                self.heuristic
+                    .evaluate_move(rng, state, player, mv)
+                    .to_f64()
+                    - before
+            })
+            .collect();
+
+        let mut values = [0.; Player::N];
+        for _ in 0..self.rollouts {
+            let end = random_playout(rng, state, *player);
+            for (value, reward) in values.iter_mut().zip(self.reward.evaluate(&end)) {
+                *value += reward / self.rollouts as f64;
+            }
+        }
+        (softmax(&deltas, self.temperature), values)
+    }
+
+    fn name(&self) -> String {
+        format!(
+            "softmax {} at {}, {} rollouts",
+            self.heuristic.name(),
+            self.temperature,
+            self.rollouts
+        )
+    }
+}
+
+/// The prior comes from the policy head and every player's value from the value head
+impl Evaluator for Network {
+    fn evaluate(
+        &self,
+        _: &mut ThreadRng,
+        state: &State,
+        player: &Player,
+        moves: &[Move],
+    ) -> (Vec<f64>, [f64; Player::N]) {
+        let (value, priors) = self.predict(state, player, moves);
+        let mut values = [0.; Player::N];
+        for p in Player::iter() {
+            values[usize::from(p)] = match p == *player {
+                true => value as f64,
+                false => self.value(state, &p) as f64,
+            };
+        }
+        (priors.into_iter().map(|p| p as f64).collect(), values)
+    }
+
+    fn name(&self) -> String {
+        "Network".to_owned()
+    }
+}
+
+/// A position in the search tree
+struct Node {
+    /// Player to move
+    player: Player,
+    moves: Vec<Move>,
+    priors: Vec<f64>,
+    /// Index of the node reached by every move, once expanded
+    children: Vec<Option<usize>>,
+    /// Number of simulations through every move
+    visits: Vec<usize>,
+    /// Sum of the rewards of every player over the simulations through every move
+    rewards: Vec<[f64; Player::N]>,
+}
+
+impl Node {
+    fn new(player: Player, moves: Vec<Move>, priors: Vec<f64>) -> Self {
+        let n = moves.len();
+        Self {
+            player,
+            moves,
+            priors,
+            children: vec![None; n],
+            visits: vec![0; n],
+            rewards: vec![[0.; Player::N]; n],
+        }
+    }
+}
+
+/// Next player after `player` that has a move, and their moves.
+/// Players without a move now will never move again, so they are passed.
+fn next_to_move(state: &State, player: Player) -> Option<(Player, Vec<Move>)> {
+    let mut next = player;
+    for _ in 0..Player::N {
+        next = next.next();
+        let moves = state.get_moves(&next);
+        if !moves.is_empty() {
+            return Some((next, moves));
+        }
+    }
+    None
+}
+
+/// AlphaZero style search, choosing moves by PUCT from the priors and values of an [Evaluator]
+/// instead of rolling out every simulation
+pub struct Puct<E: Evaluator> {
+    evaluator: E,
+    /// Number of simulations to run
+    simulations: usize,
+    /// Weight of the prior against the mean reward
+    c: f64,
+    /// Dirichlet noise mixed into the root priors as `(alpha, fraction)`
+    noise: Option<(f64, f64)>,
+}
+
+impl<E: Evaluator> Puct<E> {
+    pub fn new(evaluator: E, simulations: usize, c: f64) -> Self {
+        Self {
+            evaluator,
+            simulations,
+            c,
+            noise: None,
+        }
+    }
+
+    /// Mix Dirichlet noise with parameter `alpha` into the root priors,
+    /// so `fraction` of the prior is noise, to explore in self-play
+    pub fn noise(mut self, alpha: f64, fraction: f64) -> Self {
+        self.noise = Some((alpha, fraction));
+        self
+    }
+
+    /// Create the root of a search with `player` to move.
+    /// Returns [None] if the player has no moves.
+    fn root(&self, rng: &mut ThreadRng, state: &State, player: Player) -> Option<Node> {
+        let moves = state.get_moves(&player);
+        if moves.is_empty() {
+            return None;
+        }
+        let (mut priors, _) = self.evaluator.evaluate(rng, state, &player, &moves);
+        if let (Some((alpha, fraction)), true) = (self.noise, moves.len() > 1) {
+            let dirichlet = Dirichlet::new_with_size(alpha, moves.len()).unwrap();
+            for (prior, noise) in priors.iter_mut().zip(dirichlet.sample(rng)) {
+                *prior = (1. - fraction) * *prior + fraction * noise;
+            }
+        }
+        Some(Node::new(player, moves, priors))
+    }
+
+    /// Index of the move to simulate from a node.
+    /// Moves that were never simulated count as a reward of zero.
+    fn select(&self, node: &Node) -> usize {
+        let pid = usize::from(node.player);
+        // the visit that expanded the node counts too, so the first choice follows the prior
+        let total = (node.visits.iter().sum::<usize>() + 1) as f64;
+        let score = |i: usize| {
+            let visits = node.visits[i] as f64;
+            let mean = match node.visits[i] {
+                0 => 0.,
+                _ => node.rewards[i][pid] / visits,
+            };
+            mean + self.c * node.priors[i] * total.sqrt() / (1. + visits)
+        };
+        (0..node.moves.len())
+            .max_by(|a, b| score(*a).total_cmp(&score(*b)))
+            .unwrap()
+    }
+
+    /// Run the simulations from the root at `tree[0]`, for the position `state`
+    fn search(&self, rng: &mut ThreadRng, state: &State, tree: &mut Vec<Node>) {
+        for _ in 0..self.simulations {
+            let mut state = state.clone();
+            let mut path = Vec::new();
+            let mut index = 0;
+
+            // go down the tree until a move leads to a new position
+            let value = loop {
+                let node = &tree[index];
+                let i = self.select(node);
+                path.push((index, i));
+                state.place_piece(&node.moves[i]);
+                if let Some(child) = node.children[i] {
+                    index = child;
+                    continue;
+                }
+
+                let player = node.player;
+                break match next_to_move(&state, player) {
+                    Some((next, moves)) => {
+                        let (priors, value) = self.evaluator.evaluate(rng, &state, &next, &moves);
+                        tree.push(Node::new(next, moves, priors));
+                        let child = tree.len() - 1;
+                        tree[index].children[i] = Some(child);
+                        value
+                    }
+                    // the game is over, so nothing is left to expand
+                    None => Reward::WinShare.evaluate(&state),
+                };
+            };
+
+            for (index, i) in path {
+                let node = &mut tree[index];
+                node.visits[i] += 1;
+                for (reward, v) in node.rewards[i].iter_mut().zip(value) {
+                    *reward += v;
+                }
+            }
+        }
+    }
+
+    /// Search from a position and choose the move with the most visits, breaking ties randomly.
+    /// Returns the move along with the visits of every root move.
+    fn decide_with_visits(
+        &self,
+        rng: &mut ThreadRng,
+        state: &State,
+        player: &Player,
+    ) -> Option<(Move, Vec<(Move, usize)>)> {
+        let mut tree = vec![self.root(rng, state, *player)?];
+        self.search(rng, state, &mut tree);
+
+        let root = tree.swap_remove(0);
+        let mut visits: Vec<_> = root.moves.into_iter().zip(root.visits).collect();
+        visits.shuffle(rng);
+        let (mv, _) = visits.iter().max_by_key(|(_, v)| *v)?.clone();
+        Some((mv, visits))
+    }
+}
+
+impl<E: Evaluator> Algorithm for Puct<E> {
+    fn decide(
+        &self,
+        rng: &mut ThreadRng,
+        state: &State,
+        player: &Player,
+        _: usize,
+    ) -> Option<Move> {
+        self.decide_with_visits(rng, state, player)
+            .map(|(mv, _)| mv)
+    }
+
+    fn new_game(&self) -> Box<dyn Session + '_> {
+        Box::new(PuctSession {
+            puct: self,
+            visits: Vec::new(),
+        })
+    }
+
+    fn name(&self) -> String {
+        let mut name = format!(
+            "PUCT {} sims, c {}, {}",
+            self.simulations,
+            self.c,
+            self.evaluator.name()
+        );
+        if let Some((alpha, fraction)) = self.noise {
+            name += &format!(", Dirichlet {} noise at {}", alpha, fraction);
+        }
+        name
+    }
+}
+
+/// A game of [Puct], remembering the visits of the last search
+struct PuctSession<'a, E: Evaluator> {
+    puct: &'a Puct<E>,
+    visits: Vec<(Move, usize)>,
+}
+
+impl<E: Evaluator> Session for PuctSession<'_, E> {
+    fn decide(
+        &mut self,
+        rng: &mut ThreadRng,
+        state: &State,
+        player: &Player,
+        _: usize,
+    ) -> Option<Move> {
+        let (mv, visits) = match self.puct.decide_with_visits(rng, state, player) {
+            Some((mv, visits)) => (Some(mv), visits),
+            None => (None, Vec::new()),
+        };
+        self.visits = visits;
+        mv
+    }
+
+    fn visits(&self) -> Option<&[(Move, usize)]> {
+        Some(&self.visits)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::evaluate::{Layer, MoveCount, Score, PLANES};
+    use crate::game::Shape;
+
+    #[test]
+    fn test_heuristic_evaluator() {
+        let mut rng = rand::thread_rng();
+        let state = State::new(20, 20);
+        let player = Player::Player1;
+        let moves = state.get_moves(&player);
+        let evaluator = HeuristicEvaluator::<MoveCount>::default();
+        let (priors, values) = evaluator.evaluate(&mut rng, &state, &player, &moves);
+        assert!((priors.iter().sum::<f64>() - 1.).abs() < 1e-9);
+        assert!((values.iter().sum::<f64>() - 1.).abs() < 1e-9);
+
+        // moves opening up more placements are more likely
+        let mut rng = rand::thread_rng();
+        let counts: Vec<_> = moves
+            .iter()
+            .map(|mv| MoveCount.evaluate_move(&mut rng, &state, &player, mv))
+            .collect();
+        for i in 0..moves.len() {
+            for j in 0..moves.len() {
+                if counts[i] > counts[j] {
+                    assert!(priors[i] > priors[j]);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_visits() {
+        let mut rng = rand::thread_rng();
+        let puct = Puct::new(HeuristicEvaluator::new(Score, 0.1), 100, 1.5);
+        let state = State::new(20, 20);
+        let player = Player::Player1;
+        let (mv, visits) = puct.decide_with_visits(&mut rng, &state, &player).unwrap();
+        assert!(state.get_moves(&player).contains(&mv));
+        assert_eq!(visits.iter().map(|(_, v)| v).sum::<usize>(), 100);
+        let most = visits.iter().map(|(_, v)| *v).max().unwrap();
+        assert!(visits.contains(&(mv.clone(), most)));
+        // a sharp prior for big pieces keeps the search on them
+        assert_eq!(state.move_size(&mv), 5);
+
+        let mut session = puct.new_game();
+        let mv = session.decide(&mut rng, &state, &player, 1).unwrap();
+        let visits = session.visits().unwrap();
+        assert!(visits.iter().any(|(visited, _)| *visited == mv));
+    }
+
+    #[test]
+    fn test_root_noise() {
+        let mut rng = rand::thread_rng();
+        let state = State::new(20, 20);
+        let player = Player::Player1;
+        let evaluator = || HeuristicEvaluator::<MoveCount>::default();
+        let plain = Puct::new(evaluator(), 10, 1.5);
+        let noisy = Puct::new(evaluator(), 10, 1.5).noise(0.3, 0.25);
+
+        let plain = plain.root(&mut rng, &state, player).unwrap();
+        let noisy = noisy.root(&mut rng, &state, player).unwrap();
+        assert!((noisy.priors.iter().sum::<f64>() - 1.).abs() < 1e-9);
+        // the prior is mixed with noise but keeps most of its weight
+        assert_ne!(plain.priors, noisy.priors);
+        for (p, n) in plain.priors.iter().zip(&noisy.priors) {
+            assert!(*n >= 0.75 * p - 1e-9);
+        }
+    }
+
+    #[test]
+    fn test_network_evaluator() {
+        let mut rng = rand::thread_rng();
+        // a network without a policy head gives uniform priors
+        let features = PLANES * 100 + Player::N * Shape::N;
+        let value = Layer::dense(features, 1, vec![0.; features], vec![0.5]).unwrap();
+        let network = Network::new(10, 10, vec![], vec![value], vec![]).unwrap();
+        let state = State::new(10, 10);
+        let player = Player::Player2;
+        let moves = state.get_moves(&player);
+        let (priors, values) = Evaluator::evaluate(&network, &mut rng, &state, &player, &moves);
+        assert!(priors
+            .iter()
+            .all(|p| (p * moves.len() as f64 - 1.).abs() < 1e-6));
+        assert_eq!(values, [0.5; Player::N]);
+
+        let puct = Puct::new(network, 20, 1.5);
+        let mv = puct.decide(&mut rng, &state, &player, 1).unwrap();
+        assert!(moves.contains(&mv));
+    }
+
+    #[test]
+    fn test_endgame() {
+        // play a game to the end, so the search has to handle passes and finished games
+        let mut rng = rand::thread_rng();
+        let puct = Puct::new(HeuristicEvaluator::new(Score, 1.), 20, 1.5);
+        let mut sessions: Vec<_> = Player::iter().map(|_| puct.new_game()).collect();
+        let mut game = crate::game::Game::new(State::new(10, 10));
+        while !game.is_over() {
+            let player = game.to_move();
+            let mv = sessions[usize::from(player)].decide(
+                &mut rng,
+                game.state(),
+                &player,
+                game.move_number(),
+            );
+            match &mv {
+                Some(mv) => assert!(game.state().get_moves(&player).contains(mv)),
+                None => assert!(game.state().get_moves(&player).is_empty()),
+            }
+            game.play_or_pass(mv);
+        }
+    }
+}
@@ -1,5 +1,13 @@
 mod algorithms;
+mod playout;
+mod report;
+mod selfplay;
 mod tournament;
 
 pub use algorithms::*;
-pub use tournament::{GameStats, Tournament};
+pub use playout::{playout, random_playout};
+pub use report::Report;
+pub use selfplay::{
+    encode_pieces, encode_planes, move_index, policy_size, Dataset, Sample, SelfPlay, PLANES,
+};
+pub use tournament::{read_games, GameRecord, GameStats, Replay, Tournament};
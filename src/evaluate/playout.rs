@@ -0,0 +1,79 @@
+use crate::game::{Game, Move, Player, State, Turn};
+use rand::rngs::ThreadRng;
+
+/// Play a game out from `state` with `player` to move until nobody can move.
+/// Every move is chosen by `choose`, which returns [None] when the player has no moves left.
+pub fn playout<F>(rng: &mut ThreadRng, state: &State, player: Player, mut choose: F) -> State
+where
+    F: FnMut(&mut ThreadRng, &State, &Player) -> Option<Move>,
+{
+    let turn = Turn {
+        to_move: player,
+        ..Default::default()
+    };
+    let mut game = Game::with_turn(state.clone(), turn);
+    while !game.is_over() {
+        let mv = choose(rng, game.state(), &game.to_move());
+        game.play_or_pass(mv);
+    }
+    game.into_state()
+}
+
+/// Play a game out with random moves, sampled without generating every move
+pub fn random_playout(rng: &mut ThreadRng, state: &State, player: Player) -> State {
+    playout(rng, state, player, |rng, state, player| {
+        state.random_move(rng, player)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_random_playout() {
+        let mut rng = rand::thread_rng();
+        let state = random_playout(&mut rng, &State::new(20, 20), Player::Player1);
+        assert!(Player::iter().all(|p| state.get_moves(&p).is_empty()));
+        assert!(state.scores().iter().all(|s| *s > 0));
+    }
+
+    #[test]
+    fn test_random_move_is_legal() {
+        let mut rng = rand::thread_rng();
+        playout(
+            &mut rng,
+            &State::new(20, 20),
+            Player::Player1,
+            |rng, state, player| {
+                let moves = state.get_moves(player);
+                let mv = state.random_move(rng, player);
+                match &mv {
+                    Some(mv) => assert!(moves.contains(mv)),
+                    None => assert!(moves.is_empty()),
+                }
+                mv
+            },
+        );
+    }
+
+    #[test]
+    fn test_random_move_is_uniform_over_pieces() {
+        // pieces with many orientations are not picked more often than symmetric ones
+        let mut rng = rand::thread_rng();
+        let state = State::new(20, 20);
+        let fits = state.count_moves_by_piece(&Player::Player1);
+        let pieces = fits.iter().filter(|n| **n > 0).count();
+        let mut counts = vec![0; fits.len()];
+        for _ in 0..200 * pieces {
+            let mv = state.random_move(&mut rng, &Player::Player1).unwrap();
+            counts[state.move_piece(&mv)] += 1;
+        }
+        for (count, fits) in counts.iter().zip(fits) {
+            match fits {
+                0 => assert_eq!(*count, 0),
+                _ => assert!((120..=280).contains(count), "{:?}", counts),
+            }
+        }
+    }
+}
@@ -0,0 +1,430 @@
+use super::tournament::{read_games, read_store, GameRecord, Store, Tournament};
+use crate::game::{svg, Player};
+use itertools::Itertools;
+use std::{
+    collections::HashMap,
+    f64::consts::LN_10,
+    fmt::Write,
+    io::{BufRead, Read},
+    time::Duration,
+};
+
+/// Replays shown in a report, the most recent ones first
+const MAX_REPLAYS: usize = 20;
+/// Most points drawn per agent in the rating chart
+const MAX_CHART_POINTS: usize = 400;
+/// Size of the rating chart
+const CHART_W: f64 = 800.;
+const CHART_H: f64 = 360.;
+const CHART_PAD: f64 = 50.;
+
+/// Self contained HTML report of a tournament, built from its ratings and recorded games
+pub struct Report {
+    ratings: Store,
+    games: Vec<GameRecord>,
+}
+
+impl Report {
+    /// Read the ratings written by [Tournament::save] and the games written by [Tournament::save_games]
+    pub fn load(ratings: impl Read, games: impl BufRead) -> Result<Self, serde_json::Error> {
+        Ok(Self {
+            ratings: read_store(ratings)?,
+            games: read_games(games)?,
+        })
+    }
+
+    /// Names of the agents, best rated first
+    fn ranking(&self) -> Vec<&String> {
+        self.ratings
+            .iter()
+            .sorted_by(|(a, (a_elo, ..)), (b, (b_elo, ..))| {
+                b_elo.partial_cmp(a_elo).unwrap().then(a.cmp(b))
+            })
+            .map(|(name, _)| name)
+            .collect()
+    }
+
+    pub fn to_html(&self) -> String {
+        let mut out = String::new();
+        out.push_str(concat!(
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n",
+            "<title>Tournament report</title>\n<style>\n",
+            "body { font-family: sans-serif; margin: 2em; }\n",
+            "table { border-collapse: collapse; margin-bottom: 2em; }\n",
+            "th, td { border: 1px solid #ccc; padding: 4px 8px; text-align: right; }\n",
+            "th:first-child, td:first-child { text-align: left; }\n",
+            ".swatch { display: inline-block; width: 10px; height: 10px; margin-right: 4px; }\n",
+            "</style>\n</head>\n<body>\n<h1>Tournament report</h1>\n",
+        ));
+        writeln!(
+            out,
+            "<p>{} agents, {} recorded games.</p>",
+            self.ratings.len(),
+            self.games.len()
+        )
+        .unwrap();
+
+        self.write_ranking(&mut out);
+        self.write_chart(&mut out);
+        self.write_head_to_head(&mut out);
+        self.write_seats(&mut out);
+        self.write_replays(&mut out);
+
+        out.push_str("</body>\n</html>\n");
+        out
+    }
+
+    fn write_ranking(&self, out: &mut String) {
+        out.push_str("<h2>Ranking</h2>\n<table>\n<tr><th>Algorithm</th><th>Rank</th><th>ELO</th><th>95% interval</th><th>Games Played</th><th>Avg Pts</th><th>Avg ms / Game</th><th>Illegal Games</th></tr>\n");
+        for (rank, name) in self.ranking().into_iter().enumerate() {
+            let (elo, games, points, elapsed, illegal) = self.ratings[name];
+            let margin = match games {
+                0 => "-".to_string(),
+                _ => format!("&plusmn;{:.0}", elo_margin(games)),
+            };
+            writeln!(
+                out,
+                "<tr><td>{}</td><td>{}</td><td>{:.0}</td><td>{}</td><td>{}</td><td>{:.2}</td><td>{:.2}</td><td>{}</td></tr>",
+                escape(name),
+                rank + 1,
+                elo,
+                margin,
+                games,
+                points as f64 / games.max(1) as f64,
+                elapsed.as_secs_f64() * 1000. / games.max(1) as f64,
+                illegal,
+            )
+            .unwrap();
+        }
+        out.push_str("</table>\n");
+    }
+
+    fn write_chart(&self, out: &mut String) {
+        out.push_str("<h2>Rating over time</h2>\n");
+
+        // ELO of every agent after each of their recorded games
+        let mut series: HashMap<&str, Vec<(usize, f64)>> = HashMap::new();
+        for (i, game) in self.games.iter().enumerate() {
+            for (name, elo) in game.agents.iter().zip(game.elos) {
+                let points = series.entry(name).or_default();
+                // an agent can fill several seats of a game
+                if points.last().map(|(g, _)| *g) != Some(i) {
+                    points.push((i, elo));
+                }
+            }
+        }
+        if series.is_empty() {
+            out.push_str("<p>No recorded games.</p>\n");
+            return;
+        }
+
+        let (lo, hi) = series
+            .values()
+            .flatten()
+            .map(|(_, elo)| *elo)
+            .minmax()
+            .into_option()
+            .unwrap();
+        let (lo, hi) = (lo.floor() - 10., hi.ceil() + 10.);
+        let games = self.games.len().max(2) - 1;
+        let x = |g: usize| CHART_PAD + g as f64 / games as f64 * (CHART_W - 2. * CHART_PAD);
+        let y =
+            |elo: f64| CHART_H - CHART_PAD - (elo - lo) / (hi - lo) * (CHART_H - 2. * CHART_PAD);
+
+        writeln!(
+            out,
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{CHART_W}" height="{CHART_H}" font-family="sans-serif" font-size="10">"#
+        )
+        .unwrap();
+        for i in 0..=4 {
+            let elo = lo + (hi - lo) * i as f64 / 4.;
+            writeln!(
+                out,
+                r##"<line x1="{CHART_PAD}" x2="{}" y1="{y:.1}" y2="{y:.1}" stroke="#eee"/><text x="{}" y="{:.1}" text-anchor="end">{elo:.0}</text>"##,
+                CHART_W - CHART_PAD,
+                CHART_PAD - 4.,
+                y(elo) + 3.,
+                y = y(elo),
+            )
+            .unwrap();
+        }
+        writeln!(
+            out,
+            r#"<text x="{}" y="{}" text-anchor="middle">Games</text>"#,
+            CHART_W / 2.,
+            CHART_H - CHART_PAD / 2.
+        )
+        .unwrap();
+
+        let ranking = self.ranking();
+        let mut legend = String::new();
+        for (i, name) in ranking.iter().enumerate() {
+            let Some(points) = series.get(name.as_str()) else {
+                continue;
+            };
+            let color = chart_color(i, ranking.len());
+            let step = points.len().div_ceil(MAX_CHART_POINTS);
+            let line = points
+                .iter()
+                .step_by(step)
+                .chain(points.last())
+                .map(|(g, elo)| format!("{:.1},{:.1}", x(*g), y(*elo)))
+                .join(" ");
+            writeln!(
+                out,
+                r#"<polyline points="{line}" fill="none" stroke="{color}" stroke-width="1.5"><title>{}</title></polyline>"#,
+                escape(name)
+            )
+            .unwrap();
+            writeln!(
+                legend,
+                r#"<li><span class="swatch" style="background: {color}"></span>{}</li>"#,
+                escape(name)
+            )
+            .unwrap();
+        }
+        out.push_str("</svg>\n<ul>\n");
+        out.push_str(&legend);
+        out.push_str("</ul>\n");
+    }
+
+    fn write_head_to_head(&self, out: &mut String) {
+        // points of the first agent against the second, and number of pairings
+        let mut results: HashMap<(&str, &str), (f64, usize)> = HashMap::new();
+        for game in &self.games {
+            for (a, b) in (0..Player::N).tuple_combinations() {
+                let (name_a, name_b) = (game.agents[a].as_str(), game.agents[b].as_str());
+                if name_a == name_b {
+                    continue;
+                }
+                let points = match game.scores[a].cmp(&game.scores[b]) {
+                    std::cmp::Ordering::Less => 0.,
+                    std::cmp::Ordering::Equal => 0.5,
+                    std::cmp::Ordering::Greater => 1.,
+                };
+                let ab = results.entry((name_a, name_b)).or_default();
+                ab.0 += points;
+                ab.1 += 1;
+                let ba = results.entry((name_b, name_a)).or_default();
+                ba.0 += 1. - points;
+                ba.1 += 1;
+            }
+        }
+
+        out.push_str("<h2>Head to head</h2>\n<p>Share of pairwise results won by the row against the column, with the number of pairings.</p>\n<table>\n<tr><th></th>");
+        let ranking = self.ranking();
+        for i in 0..ranking.len() {
+            write!(out, "<th>{}</th>", i + 1).unwrap();
+        }
+        out.push_str("</tr>\n");
+        for (i, row) in ranking.iter().enumerate() {
+            write!(out, "<tr><td>{} {}</td>", i + 1, escape(row)).unwrap();
+            for col in &ranking {
+                match results.get(&(row.as_str(), col.as_str())) {
+                    Some((points, n)) => {
+                        let share = points / *n as f64;
+                        write!(
+                            out,
+                            r#"<td style="background: hsl({:.0}, 60%, 85%)">{:.0}% ({n})</td>"#,
+                            share * 120.,
+                            share * 100.
+                        )
+                        .unwrap();
+                    }
+                    None => out.push_str("<td>-</td>"),
+                }
+            }
+            out.push_str("</tr>\n");
+        }
+        out.push_str("</table>\n");
+    }
+
+    fn write_seats(&self, out: &mut String) {
+        out.push_str("<h2>Seats</h2>\n<table>\n<tr><th>Seat</th><th>Avg Pts</th><th>Win Share</th><th>Avg ms / Game</th><th>Illegal Games</th></tr>\n");
+        let games = self.games.len().max(1) as f64;
+        for player in Player::iter() {
+            let seat = usize::from(player);
+            let mut points = 0;
+            let mut wins = 0.;
+            let mut elapsed = Duration::default();
+            let mut illegal = 0;
+            for game in &self.games {
+                let best = game.scores.iter().max().unwrap();
+                if game.scores[seat] == *best {
+                    // ties share the win
+                    wins += 1. / game.scores.iter().filter(|s| *s == best).count() as f64;
+                }
+                points += game.scores[seat] as usize;
+                elapsed += game.elapsed[seat];
+                illegal += game.illegal[seat] as usize;
+            }
+            writeln!(
+                out,
+                r#"<tr><td><span class="swatch" style="background: {}"></span>Player {}</td><td>{:.2}</td><td>{:.1}%</td><td>{:.2}</td><td>{}</td></tr>"#,
+                svg::fill(&player),
+                seat + 1,
+                points as f64 / games,
+                wins / games * 100.,
+                elapsed.as_secs_f64() * 1000. / games,
+                illegal
+            )
+            .unwrap();
+        }
+        out.push_str("</table>\n");
+    }
+
+    fn write_replays(&self, out: &mut String) {
+        out.push_str("<h2>Replays</h2>\n");
+        let replays: Vec<_> = self
+            .games
+            .iter()
+            .enumerate()
+            .rev()
+            .filter_map(|(i, game)| game.replay.as_ref().map(|replay| (i, game, replay)))
+            .take(MAX_REPLAYS)
+            .collect();
+        if replays.is_empty() {
+            out.push_str("<p>No game was recorded with its moves.</p>\n");
+            return;
+        }
+
+        out.push_str("<ul>\n");
+        for (i, game, _) in &replays {
+            writeln!(
+                out,
+                r##"<li><a href="#game-{i}">Game {}</a>: {}</li>"##,
+                i + 1,
+                seats(game)
+            )
+            .unwrap();
+        }
+        out.push_str("</ul>\n");
+
+        for (i, game, replay) in replays {
+            writeln!(
+                out,
+                "<section id=\"game-{i}\">\n<h3>Game {}</h3>\n<p>{}</p>",
+                i + 1,
+                seats(game)
+            )
+            .unwrap();
+            out.push_str(&svg::render_replay(
+                replay.w,
+                replay.h,
+                &replay.moves,
+                Duration::from_millis(500),
+            ));
+            out.push_str("</section>\n");
+        }
+    }
+}
+
+impl From<&Tournament> for Report {
+    fn from(tournament: &Tournament) -> Self {
+        Self {
+            ratings: Store::from(tournament),
+            games: tournament.games().to_vec(),
+        }
+    }
+}
+
+/// Half width of the 95% confidence interval of a rating after `games` games.
+/// Every game counts as 3 pairwise results with a standard deviation of at most 0.5,
+/// and between even players a point of expected score is worth 400 / (ln 10 / 4) ELO.
+fn elo_margin(games: usize) -> f64 {
+    let deviation = 0.5 / ((Player::N - 1) as f64 * games as f64).sqrt();
+    1.96 * deviation * 400. / (LN_10 / 4.)
+}
+
+/// Color of the `i`th of `n` agents in the rating chart
+fn chart_color(i: usize, n: usize) -> String {
+    format!("hsl({:.0}, 70%, 45%)", i as f64 * 360. / n as f64)
+}
+
+/// Agents and scores of a game, in seat order
+fn seats(game: &GameRecord) -> String {
+    Player::iter()
+        .map(|player| {
+            let seat = usize::from(player);
+            format!(
+                r#"<span class="swatch" style="background: {}"></span>{} ({}{})"#,
+                svg::fill(&player),
+                escape(&game.agents[seat]),
+                game.scores[seat],
+                if game.illegal[seat] { ", illegal" } else { "" }
+            )
+        })
+        .join(", ")
+}
+
+/// Escape text for HTML, as algorithm names are free text
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::evaluate::{GreedyMax, Random, Score};
+    use std::io::BufReader;
+
+    #[test]
+    fn test_report() {
+        let mut tournament = Tournament::new(
+            100.,
+            1200.,
+            200.,
+            vec![Box::new(Random), Box::new(GreedyMax::<Score>::default())],
+            None,
+        )
+        .unwrap()
+        .record_replays(1.);
+        let results = [[0, 1, 0, 1], [1, 1, 0, 0]]
+            .into_iter()
+            .filter_map(|agents| tournament.simulate_game(agents).map(|r| (agents, r)))
+            .collect();
+        tournament.finish_games(results);
+        assert_eq!(tournament.games().len(), 2);
+        let games = tournament.games().to_vec();
+        let html = Report::from(&tournament).to_html();
+
+        // the saved tournament gives the same report
+        let dir = std::env::temp_dir().join(format!("report-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        tournament.save(dir.join("ratings.json")).unwrap();
+        tournament.save_games(dir.join("games.jsonl")).unwrap();
+        let loaded = Report::load(
+            std::fs::File::open(dir.join("ratings.json")).unwrap(),
+            BufReader::new(std::fs::File::open(dir.join("games.jsonl")).unwrap()),
+        )
+        .unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(loaded.games, games);
+        // written games are not kept in memory
+        assert!(tournament.games().is_empty());
+
+        assert_eq!(html, loaded.to_html());
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(html.ends_with("</html>\n"));
+        for name in ["Random", "Max by Score"] {
+            assert!(html.contains(name));
+        }
+        assert_eq!(html.matches("<polyline").count(), 2);
+        assert_eq!(html.matches("<section").count(), 2);
+        assert!(html.contains(r##"href="#game-0""##));
+    }
+
+    #[test]
+    fn test_escape() {
+        assert_eq!(escape("Mix<A & B>"), "Mix&lt;A &amp; B&gt;");
+    }
+
+    #[test]
+    fn test_elo_margin() {
+        assert!(elo_margin(1) > elo_margin(100));
+        assert!((elo_margin(100) - 39.3).abs() < 0.5);
+    }
+}
@@ -0,0 +1,457 @@
+//! Self-play games written out as training data for machine learning.
+//!
+//! [Dataset::save] writes one sample per move played into a directory of NPY files,
+//! which `numpy.load` reads directly. With `N` samples on a `w` by `h` board:
+//!
+//! | File          | Type | Shape              | Contents                                                        |
+//! |---------------|------|--------------------|-----------------------------------------------------------------|
+//! | `planes.npy`  | u1   | `(N, 8, h, w)`     | Cells covered by each player, then the valid corners of each player |
+//! | `pieces.npy`  | u1   | `(N, 4, 21)`       | Whether each player still has each piece, in [Shape] order     |
+//! | `player.npy`  | u1   | `(N,)`             | Side to move, 0 for player 1                                    |
+//! | `moves.npy`   | i4   | `(N,)`             | Move played, as a [move_index]                                  |
+//! | `visits.npy`  | i4   | `(M, 3)`           | Rows of sample, [move_index] and visits, for searches that count visits |
+//! | `scores.npy`  | u1   | `(N, 4)`           | Final score of every player                                    |
+//! | `outcome.npy` | f4   | `(N, 4)`           | Share of the win of every player, the winners split one point  |
+//! | `game.npy`    | i4   | `(N,)`             | Game the sample comes from                                      |
+//!
+//! Players are always in absolute order, so models that want the side to move first
+//! rotate the planes by `player.npy`.
+
+use super::{Algorithm, Reward};
+use crate::game::{Dimensioned, Game, Move, PieceSet, Player, Polyomino, Shape, State};
+use once_cell::sync::Lazy;
+use rustc_hash::FxHashMap;
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+/// Feature planes of a position, occupancy and valid corners for every player
+pub const PLANES: usize = 2 * Player::N;
+
+/// Every orientation of every standard piece, numbered in the order of [Polyomino::transformations]
+static ORIENTATIONS: Lazy<FxHashMap<Vec<(i8, i8)>, usize>> = Lazy::new(|| {
+    PieceSet::standard()
+        .shapes()
+        .iter()
+        .flat_map(Polyomino::transformations)
+        .enumerate()
+        .map(|(i, orientation)| (orientation.cells().to_vec(), i))
+        .collect()
+});
+
+/// Number of different move indices on a `w` by `h` board
+pub fn policy_size(w: i8, h: i8) -> usize {
+    ORIENTATIONS.len() * w as usize * h as usize
+}
+
+/// Index of a move that is the same for every engine,
+/// from the orientation of the piece and the top left corner of its bounding box:
+/// `orientation * w * h + y * w + x`.
+/// Orientations are numbered through the shapes of [PieceSet::standard].
+pub fn move_index(state: &State, mv: &Move) -> usize {
+    let cells = state.move_cells(mv);
+    let x = cells.iter().map(|(x, _)| *x).min().unwrap();
+    let y = cells.iter().map(|(_, y)| *y).min().unwrap();
+    let orientation = ORIENTATIONS[Polyomino::new("", cells).cells()];
+    let (w, h) = (state.w() as usize, state.h() as usize);
+    orientation * w * h + y as usize * w + x as usize
+}
+
+/// Occupancy planes followed by valid corner planes, `PLANES x h x w` in row major order
+pub fn encode_planes(state: &State) -> Vec<u8> {
+    let (w, h) = (state.w() as usize, state.h() as usize);
+    let mut planes = vec![0; PLANES * w * h];
+    for y in 0..h {
+        for x in 0..w {
+            if let Some(player) = state.cell(x as i8, y as i8) {
+                planes[usize::from(player) * w * h + y * w + x] = 1;
+            }
+        }
+    }
+    for player in Player::iter() {
+        let plane = Player::N + usize::from(player);
+        for (x, y) in state.corners(&player) {
+            planes[plane * w * h + y as usize * w + x as usize] = 1;
+        }
+    }
+    planes
+}
+
+/// Whether every player still has every standard shape, `Player::N x Shape::N`.
+/// Pieces of other sets are encoded as the standard shape they match,
+/// and shapes that are not in the standard set are left out.
+pub fn encode_pieces(state: &State) -> Vec<u8> {
+    let mut pieces = vec![0; Player::N * Shape::N];
+    let shapes = state.piece_set().standard_shapes();
+    for player in Player::iter() {
+        for piece in state.remaining_pieces(&player) {
+            if let Some(shape) = shapes[piece] {
+                pieces[usize::from(player) * Shape::N + usize::from(shape)] = 1;
+            }
+        }
+    }
+    pieces
+}
+
+/// A position of a self-play game and what became of it
+#[derive(Clone, PartialEq, Debug)]
+pub struct Sample {
+    /// Game the position comes from
+    pub game: usize,
+    pub player: Player,
+    /// See [encode_planes]
+    pub planes: Vec<u8>,
+    /// See [encode_pieces]
+    pub pieces: Vec<u8>,
+    /// [move_index] of the move played
+    pub mv: usize,
+    /// [move_index] and visits of every move the search considered, if it counts visits
+    pub visits: Vec<(usize, usize)>,
+    /// Final scores of the game
+    pub scores: [u8; Player::N],
+    /// See [Reward::WinShare]
+    pub outcome: [f64; Player::N],
+}
+
+/// Samples of self-play games on a `w` by `h` board
+#[derive(Clone, PartialEq, Debug)]
+pub struct Dataset {
+    pub w: i8,
+    pub h: i8,
+    pub samples: Vec<Sample>,
+}
+
+impl Dataset {
+    pub fn new(w: i8, h: i8) -> Self {
+        Self {
+            w,
+            h,
+            samples: Vec::new(),
+        }
+    }
+
+    /// Write the samples to a directory of NPY files, described in the [module docs](self)
+    pub fn save(&self, dir: impl AsRef<Path>) -> io::Result<()> {
+        let dir = dir.as_ref();
+        std::fs::create_dir_all(dir)?;
+        let n = self.samples.len();
+        let (w, h) = (self.w as usize, self.h as usize);
+        let samples = &self.samples;
+
+        let planes: Vec<u8> = samples.iter().flat_map(|s| s.planes.clone()).collect();
+        write_npy(dir.join("planes.npy"), &[n, PLANES, h, w], &planes)?;
+        let pieces: Vec<u8> = samples.iter().flat_map(|s| s.pieces.clone()).collect();
+        write_npy(dir.join("pieces.npy"), &[n, Player::N, Shape::N], &pieces)?;
+        let player: Vec<u8> = samples
+            .iter()
+            .map(|s| usize::from(s.player) as u8)
+            .collect();
+        write_npy(dir.join("player.npy"), &[n], &player)?;
+        let moves: Vec<i32> = samples.iter().map(|s| s.mv as i32).collect();
+        write_npy(dir.join("moves.npy"), &[n], &moves)?;
+        let visits: Vec<i32> = samples
+            .iter()
+            .enumerate()
+            .flat_map(|(i, s)| {
+                s.visits
+                    .iter()
+                    .flat_map(move |(mv, v)| [i as i32, *mv as i32, *v as i32])
+            })
+            .collect();
+        write_npy(dir.join("visits.npy"), &[visits.len() / 3, 3], &visits)?;
+        let scores: Vec<u8> = samples.iter().flat_map(|s| s.scores).collect();
+        write_npy(dir.join("scores.npy"), &[n, Player::N], &scores)?;
+        let outcome: Vec<f32> = samples
+            .iter()
+            .flat_map(|s| s.outcome.map(|o| o as f32))
+            .collect();
+        write_npy(dir.join("outcome.npy"), &[n, Player::N], &outcome)?;
+        let game: Vec<i32> = samples.iter().map(|s| s.game as i32).collect();
+        write_npy(dir.join("game.npy"), &[n], &game)
+    }
+}
+
+/// Plays games between algorithms and records every move as a [Sample]
+pub struct SelfPlay {
+    algorithms: Vec<Box<dyn Algorithm>>,
+    start: State,
+}
+
+impl SelfPlay {
+    /// Seats are filled in turn from the algorithms, shifting by one seat every game
+    pub fn new(algorithms: Vec<Box<dyn Algorithm>>) -> Self {
+        assert!(!algorithms.is_empty(), "Self-play needs an algorithm");
+        Self {
+            algorithms,
+            start: State::new(20, 20),
+        }
+    }
+
+    /// Start every game from a position other than the empty 20 by 20 board
+    pub fn start(mut self, start: State) -> Self {
+        self.start = start;
+        self
+    }
+
+    /// Algorithm playing a seat of a game
+    fn seat(&self, game: usize, player: &Player) -> &dyn Algorithm {
+        self.algorithms[(game + usize::from(player)) % self.algorithms.len()].as_ref()
+    }
+
+    /// Play a game and record every move played
+    pub fn play_game(&self, rng: &mut rand::rngs::ThreadRng, game_id: usize) -> Vec<Sample> {
+        let mut game = Game::new(self.start.clone());
+        let mut sessions: Vec<_> = Player::iter()
+            .map(|player| self.seat(game_id, &player).new_game())
+            .collect();
+        let mut samples = Vec::new();
+        while !game.is_over() {
+            let player = game.to_move();
+            let session = &mut sessions[usize::from(player)];
+            let state = game.state();
+            let Some(mv) = session.decide(rng, state, &player, game.move_number()) else {
+                game.pass();
+                continue;
+            };
+            let visits = session
+                .visits()
+                .unwrap_or_default()
+                .iter()
+                .map(|(mv, v)| (move_index(state, mv), *v))
+                .collect();
+            samples.push(Sample {
+                game: game_id,
+                player,
+                planes: encode_planes(state),
+                pieces: encode_pieces(state),
+                mv: move_index(state, &mv),
+                visits,
+                scores: [0; Player::N],
+                outcome: [0.; Player::N],
+            });
+            game.play(mv);
+        }
+
+        let state = game.state();
+        let outcome = Reward::WinShare.evaluate(state);
+        for sample in &mut samples {
+            sample.scores = *state.scores();
+            sample.outcome = outcome;
+        }
+        samples
+    }
+
+    /// Play `games` games, numbering them from `first_game`
+    pub fn play(
+        &self,
+        rng: &mut rand::rngs::ThreadRng,
+        first_game: usize,
+        games: usize,
+    ) -> Dataset {
+        let mut dataset = Dataset::new(self.start.w(), self.start.h());
+        for game in first_game..first_game + games {
+            dataset.samples.extend(self.play_game(rng, game));
+        }
+        dataset
+    }
+}
+
+/// Element types of NPY arrays
+trait Element: Copy {
+    /// Type description in the header
+    const DESCR: &'static str;
+
+    fn write(self, out: &mut impl Write) -> io::Result<()>;
+}
+
+impl Element for u8 {
+    const DESCR: &'static str = "|u1";
+
+    fn write(self, out: &mut impl Write) -> io::Result<()> {
+        out.write_all(&[self])
+    }
+}
+
+impl Element for i32 {
+    const DESCR: &'static str = "<i4";
+
+    fn write(self, out: &mut impl Write) -> io::Result<()> {
+        out.write_all(&self.to_le_bytes())
+    }
+}
+
+impl Element for f32 {
+    const DESCR: &'static str = "<f4";
+
+    fn write(self, out: &mut impl Write) -> io::Result<()> {
+        out.write_all(&self.to_le_bytes())
+    }
+}
+
+/// Header of a version 1.0 NPY file, padded so the data starts on a multiple of 64 bytes
+fn npy_header(descr: &str, shape: &[usize]) -> Vec<u8> {
+    let dims = match shape {
+        [n] => format!("({},)", n),
+        _ => format!(
+            "({})",
+            shape
+                .iter()
+                .map(|d| d.to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+    };
+    let mut dict = format!(
+        "{{'descr': '{}', 'fortran_order': False, 'shape': {}, }}",
+        descr, dims
+    );
+    // magic, version and header length take 10 bytes, and the header ends with a newline
+    let padding = (64 - (10 + dict.len() + 1) % 64) % 64;
+    dict.extend(std::iter::repeat_n(' ', padding));
+    dict.push('\n');
+
+    let mut header = b"\x93NUMPY\x01\x00".to_vec();
+    header.extend((dict.len() as u16).to_le_bytes());
+    header.extend(dict.bytes());
+    header
+}
+
+/// Write an array to an NPY file
+fn write_npy<T: Element>(path: impl AsRef<Path>, shape: &[usize], data: &[T]) -> io::Result<()> {
+    debug_assert_eq!(shape.iter().product::<usize>(), data.len());
+    let mut out = BufWriter::new(File::create(path)?);
+    out.write_all(&npy_header(T::DESCR, shape))?;
+    for x in data {
+        x.write(&mut out)?;
+    }
+    out.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::evaluate::{MonteCarlo, Random, Score};
+
+    #[test]
+    fn test_move_index() {
+        let state = State::new(20, 20);
+        assert_eq!(
+            ORIENTATIONS.len(),
+            PieceSet::standard().transformation_count()
+        );
+        for player in Player::iter() {
+            let moves = state.get_moves(&player);
+            let mut indices: Vec<_> = moves.iter().map(|mv| move_index(&state, mv)).collect();
+            assert!(indices.iter().all(|i| *i < policy_size(20, 20)));
+            // moves covering the same cells may only come from symmetric pieces
+            indices.sort();
+            indices.dedup();
+            let mut cells: Vec<_> = moves
+                .iter()
+                .map(|mv| {
+                    let mut cells = state.move_cells(mv);
+                    cells.sort();
+                    cells
+                })
+                .collect();
+            cells.sort();
+            cells.dedup();
+            assert_eq!(indices.len(), cells.len());
+        }
+    }
+
+    #[test]
+    fn test_npy_header() {
+        let header = npy_header("<i4", &[3]);
+        assert_eq!(header.len() % 64, 0);
+        assert!(header.starts_with(b"\x93NUMPY\x01\x00"));
+        let dict = std::str::from_utf8(&header[10..]).unwrap();
+        assert!(dict.starts_with("{'descr': '<i4', 'fortran_order': False, 'shape': (3,), }"));
+        assert!(dict.ends_with(" \n"));
+
+        let header = npy_header("|u1", &[5, 8, 20, 20]);
+        assert_eq!(header.len() % 64, 0);
+        assert!(std::str::from_utf8(&header[10..]).is_ok_and(|h| h.contains("(5, 8, 20, 20)")));
+    }
+
+    #[test]
+    fn test_self_play() {
+        let mut rng = rand::thread_rng();
+        let sims = 20;
+        let self_play = SelfPlay::new(vec![
+            Box::new(Random),
+            Box::new(MonteCarlo::<Score>::new(sims, f64::sqrt(2.))),
+        ])
+        .start(State::new(10, 10));
+        let dataset = self_play.play(&mut rng, 0, 2);
+        let (w, h) = (10, 10);
+
+        for game in 0..2 {
+            let samples: Vec<_> = dataset.samples.iter().filter(|s| s.game == game).collect();
+            assert!(!samples.is_empty());
+            // replay the game and check the planes against the position at every ply
+            let mut state = State::new(10, 10);
+            for sample in &samples {
+                for player in Player::iter() {
+                    let p = usize::from(player);
+                    let occupancy = &sample.planes[p * w * h..(p + 1) * w * h];
+                    let covered = occupancy.iter().filter(|c| **c == 1).count();
+                    assert_eq!(covered, state.scores()[p] as usize);
+
+                    let plane = (Player::N + p) * w * h;
+                    let mut corners: Vec<_> = (0..w * h)
+                        .filter(|i| sample.planes[plane + i] == 1)
+                        .map(|i| ((i % w) as i8, (i / w) as i8))
+                        .collect();
+                    let mut expected = state.corners(&player);
+                    corners.sort();
+                    expected.sort();
+                    expected.dedup();
+                    assert_eq!(corners, expected);
+                }
+                let mv = state
+                    .get_moves(&sample.player)
+                    .into_iter()
+                    .find(|mv| move_index(&state, mv) == sample.mv)
+                    .unwrap();
+                state.place_piece(&mv);
+            }
+            let last = samples.last().unwrap();
+            assert_eq!(&last.scores, state.scores());
+            assert!((last.outcome.iter().sum::<f64>() - 1.).abs() < 1e-9);
+        }
+        for sample in &dataset.samples {
+            assert_eq!(sample.planes.len(), PLANES * w * h);
+            assert_eq!(sample.pieces.len(), Player::N * Shape::N);
+            let mcts = (sample.game + usize::from(sample.player)) % 2 == 1;
+            match mcts {
+                true => {
+                    assert_eq!(sample.visits.iter().map(|(_, v)| v).sum::<usize>(), sims);
+                    assert!(sample.visits.iter().any(|(mv, _)| *mv == sample.mv));
+                }
+                false => assert!(sample.visits.is_empty()),
+            }
+        }
+
+        let dir = std::env::temp_dir().join(format!("selfplay-test-{}", std::process::id()));
+        dataset.save(&dir).unwrap();
+        let n = dataset.samples.len();
+        let size = |name: &str, header: usize| {
+            let len = std::fs::metadata(dir.join(name)).unwrap().len() as usize;
+            len - header
+        };
+        let header = |descr, shape: &[usize]| npy_header(descr, shape).len();
+        assert_eq!(
+            size("planes.npy", header("|u1", &[n, PLANES, h, w])),
+            n * PLANES * w * h
+        );
+        assert_eq!(size("moves.npy", header("<i4", &[n])), 4 * n);
+        assert_eq!(
+            size("outcome.npy", header("<f4", &[n, Player::N])),
+            4 * n * Player::N
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
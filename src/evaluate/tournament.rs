@@ -1,21 +1,53 @@
 use super::Algorithm;
-use crate::game::{Player, State};
+use crate::game::{Dimensioned, Game, Player, State};
 use colored::Colorize;
 use itertools::Itertools;
-use rand::seq::SliceRandom;
+use rand::{seq::SliceRandom, Rng};
 use rayon::iter::{IntoParallelIterator, ParallelIterator};
 use std::{
     array,
     cmp::min,
     collections::HashMap,
     fmt::Display,
-    iter::repeat,
+    fs::OpenOptions,
+    io::{BufRead, BufWriter, Read, Write},
+    iter::repeat_n,
     path::PathBuf,
     time::{Duration, Instant},
 };
 
-/// Type alias for the serialization of the tournament statistics
-type Store = HashMap<String, (f64, usize, usize, Duration)>;
+/// Type alias for the serialization of the tournament statistics:
+/// ELO, games played, total points, time spent and illegal games of every agent
+pub(crate) type Store = HashMap<String, (f64, usize, usize, Duration, usize)>;
+
+/// Statistics saved before illegal games were counted
+type LegacyStore = HashMap<String, (f64, usize, usize, Duration)>;
+
+/// Read the statistics written by [Tournament::save],
+/// also accepting files written before illegal games were saved
+pub(crate) fn read_store(reader: impl Read) -> Result<Store, serde_json::Error> {
+    let value: serde_json::Value = serde_json::from_reader(reader)?;
+    serde_json::from_value(value.clone()).or_else(|err| {
+        let legacy: LegacyStore = serde_json::from_value(value).map_err(|_| err)?;
+        Ok(legacy
+            .into_iter()
+            .map(|(name, (elo, games, points, elapsed))| (name, (elo, games, points, elapsed, 0)))
+            .collect())
+    })
+}
+
+/// Type alias for the serialization of a [GameRecord]
+type StoredGame = (
+    [String; Player::N],
+    [u8; Player::N],
+    [bool; Player::N],
+    [Duration; Player::N],
+    [f64; Player::N],
+    Option<(i8, i8, Vec<(usize, Vec<(i8, i8)>)>)>,
+);
+
+/// Results of a game, result of [Tournament::simulate_game]
+type GameResult = ([GameStats; Player::N], Option<Replay>);
 
 /// Player in a tournament
 /// Contains statistics about the player
@@ -25,6 +57,8 @@ struct Agent {
     games_played: usize,
     cumulative_points: usize,
     elapsed: Duration,
+    /// Number of games forfeited by playing an illegal move
+    illegal_games: usize,
 }
 
 impl Agent {
@@ -35,6 +69,7 @@ impl Agent {
             games_played: 0,
             cumulative_points: 0,
             elapsed: Duration::default(),
+            illegal_games: 0,
         }
     }
 }
@@ -61,9 +96,89 @@ impl Display for Agent {
 
 /// Struct representing a single player's performance in a game
 /// Contains scores as well as extra info
+#[derive(Clone, Copy)]
 pub struct GameStats {
     score: u8,
     elapsed: Duration,
+    /// The player tried an illegal move and forfeited the rest of the game
+    illegal: bool,
+}
+
+/// Cells covered by every move of a game, enough to draw it without an engine
+#[derive(Clone, PartialEq, Debug)]
+pub struct Replay {
+    pub w: i8,
+    pub h: i8,
+    pub moves: Vec<(Player, Vec<(i8, i8)>)>,
+}
+
+/// A finished game of a tournament, kept for reports
+#[derive(Clone, PartialEq, Debug)]
+pub struct GameRecord {
+    /// Names of the agents, in seat order
+    pub agents: [String; Player::N],
+    /// Scores of every seat, 0 for seats that played an illegal move
+    pub scores: [u8; Player::N],
+    pub illegal: [bool; Player::N],
+    pub elapsed: [Duration; Player::N],
+    /// ELO of every seat's agent after the game
+    pub elos: [f64; Player::N],
+    /// Only kept for the games sampled with [Tournament::record_replays]
+    pub replay: Option<Replay>,
+}
+
+impl From<&GameRecord> for StoredGame {
+    fn from(game: &GameRecord) -> Self {
+        (
+            game.agents.clone(),
+            game.scores,
+            game.illegal,
+            game.elapsed,
+            game.elos,
+            game.replay.as_ref().map(|replay| {
+                let moves = replay
+                    .moves
+                    .iter()
+                    .map(|(player, cells)| (usize::from(player), cells.clone()))
+                    .collect();
+                (replay.w, replay.h, moves)
+            }),
+        )
+    }
+}
+
+impl From<StoredGame> for GameRecord {
+    fn from((agents, scores, illegal, elapsed, elos, replay): StoredGame) -> Self {
+        Self {
+            agents,
+            scores,
+            illegal,
+            elapsed,
+            elos,
+            replay: replay.map(|(w, h, moves)| Replay {
+                w,
+                h,
+                moves: moves
+                    .into_iter()
+                    .map(|(player, cells)| (Player::from(player % Player::N), cells))
+                    .collect(),
+            }),
+        }
+    }
+}
+
+/// Read the games saved with [Tournament::save_games]
+pub fn read_games(reader: impl BufRead) -> Result<Vec<GameRecord>, serde_json::Error> {
+    let mut games = Vec::new();
+    for line in reader.lines() {
+        let line = line.map_err(serde_json::Error::io)?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let game: StoredGame = serde_json::from_str(&line)?;
+        games.push(GameRecord::from(game));
+    }
+    Ok(games)
 }
 
 /// Hosts a tournament with elo ratings
@@ -74,6 +189,10 @@ pub struct Tournament {
     elo_floor: f64,
     /// Range of ELO values that agents can play against
     elo_range: f64,
+    /// Games finished since the last [Tournament::save_games]
+    games: Vec<GameRecord>,
+    /// Share of the games whose moves are kept
+    replay_rate: f64,
 }
 
 impl Tournament {
@@ -85,20 +204,23 @@ impl Tournament {
         load: Option<std::fs::File>,
     ) -> Result<Self, serde_json::Error> {
         // Load Store from file
-        let load: Option<Store> = load.map(serde_json::from_reader).transpose()?;
+        let load = load.map(read_store).transpose()?;
         let load = load.unwrap_or_default();
         Ok(Self {
             elo_floor,
             elo_range,
+            games: Vec::new(),
+            replay_rate: 0.,
             agents: algorithms
                 .into_iter()
                 .map(|alg| match load.get(&alg.name()).cloned() {
-                    Some((elo, games_played, cumulative_points, elapsed)) => Agent {
+                    Some((elo, games_played, cumulative_points, elapsed, illegal_games)) => Agent {
                         algorithm: alg,
                         elo,
                         games_played,
                         cumulative_points,
                         elapsed,
+                        illegal_games,
                     },
                     None => Agent::new(alg, starting_elo),
                 })
@@ -106,16 +228,40 @@ impl Tournament {
         })
     }
 
+    /// Keep the moves of a random share of the games, to replay them in reports
+    pub fn record_replays(mut self, rate: f64) -> Self {
+        self.replay_rate = rate;
+        self
+    }
+
     pub fn save(&self, path: PathBuf) -> Result<(), serde_json::Error> {
         let store = Store::from(self);
         let file = std::fs::File::create(path).unwrap();
         serde_json::to_writer(file, &store)
     }
 
+    /// Games finished since the last [Tournament::save_games]
+    pub fn games(&self) -> &[GameRecord] {
+        &self.games
+    }
+
+    /// Append the games finished since the last save to a file, one JSON line per game.
+    /// The games are dropped once written, so a long tournament only keeps the last round.
+    pub fn save_games(&mut self, path: PathBuf) -> std::io::Result<()> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let mut writer = BufWriter::new(file);
+        for game in &self.games {
+            serde_json::to_writer(&mut writer, &StoredGame::from(game))?;
+            writeln!(writer)?;
+        }
+        writer.flush()?;
+        self.games.clear();
+        Ok(())
+    }
+
     /// Simulate one round robin round
     pub fn round_robin(&mut self) {
-        let games: Vec<_> = repeat(0..self.agents.len())
-            .take(Player::N)
+        let games: Vec<_> = repeat_n(0..self.agents.len(), Player::N)
             .multi_cartesian_product()
             .map(|agents| <[usize; Player::N]>::try_from(agents).unwrap())
             .collect();
@@ -125,25 +271,21 @@ impl Tournament {
             .filter_map(|agents| self.simulate_game(agents).map(|scores| (agents, scores)))
             .collect();
 
-        for (agents, score) in scores {
-            self.update_elo(agents, score)
-        }
+        self.finish_games(scores);
     }
 
     /// Play one game for each agent
     /// Each agent chooses opponents with similar ELO
     pub fn stochastic_round(&mut self, count: usize) {
         let scores: Vec<_> = (0..self.agents.len())
-            .flat_map(|i| repeat(i).take(count))
+            .flat_map(|i| repeat_n(i, count))
             .collect::<Vec<_>>()
             .into_par_iter()
             .map(|i| self.random_game(i))
             .filter_map(|agents| self.simulate_game(agents).map(|scores| (agents, scores)))
             .collect();
 
-        for (agents, score) in scores {
-            self.update_elo(agents, score)
-        }
+        self.finish_games(scores);
     }
 
     pub fn play_least_played(&mut self, count: usize) {
@@ -163,9 +305,7 @@ impl Tournament {
             .filter_map(|agents| self.simulate_game(agents).map(|scores| (agents, scores)))
             .collect();
 
-        for (agents, score) in scores {
-            self.update_elo(agents, score)
-        }
+        self.finish_games(scores);
     }
 
     /// Have a single agent choose random opponents to play against
@@ -205,8 +345,23 @@ impl Tournament {
         players
     }
 
+    /// Update the ratings with the results of games and record them
+    pub(crate) fn finish_games(&mut self, results: Vec<([usize; Player::N], GameResult)>) {
+        for (agents, (stats, replay)) in results {
+            self.update_elo(agents, stats);
+            self.games.push(GameRecord {
+                agents: agents.map(|i| self.agents[i].algorithm.name()),
+                scores: stats.map(|s| s.score),
+                illegal: stats.map(|s| s.illegal),
+                elapsed: stats.map(|s| s.elapsed),
+                elos: agents.map(|i| self.agents[i].elo),
+                replay,
+            });
+        }
+    }
+
     /// Run a single game with 4 agents
-    pub fn simulate_game(&self, agents: [usize; Player::N]) -> Option<[GameStats; Player::N]> {
+    pub fn simulate_game(&self, agents: [usize; Player::N]) -> Option<GameResult> {
         // skip the game if all the players are the same, as elo will never change
         #[cfg(not(debug_assertions))]
         if agents.iter().all_equal() {
@@ -216,38 +371,53 @@ impl Tournament {
         // create a new rng
         let mut rng = rand::thread_rng();
 
-        let mut game = State::new(20, 20);
-        let mut alive = true;
+        let mut game = Game::new(State::new(20, 20));
         let mut times = [Duration::default(); Player::N];
-        let mut move_number = 0;
+        let mut illegal = [false; Player::N];
+        // every seat gets its own session, even if the same agent plays several
+        let mut sessions: [_; Player::N] =
+            array::from_fn(|pid| self.agents[agents[pid]].algorithm.new_game());
         // run as long as a player is still playing
-        while alive {
-            alive = false;
-            move_number += 1;
-            for player in Player::iter() {
-                let pid = usize::from(player);
-
-                let agent = &self.agents[agents[pid]];
-                let now = Instant::now();
-                if let Some(mv) = agent
-                    .algorithm
-                    .decide(&mut rng, &game, &player, move_number)
-                {
-                    game.place_piece(&mv);
-                    alive = true;
+        while !game.is_over() {
+            let player = game.to_move();
+            let pid = usize::from(player);
+
+            let now = Instant::now();
+            let mv = sessions[pid].decide(&mut rng, game.state(), &player, game.move_number());
+            match mv {
+                Some(mv) => {
+                    if game.try_play(mv).is_err() {
+                        // an illegal move forfeits the rest of the game
+                        illegal[pid] = true;
+                        game.pass();
+                    }
                 }
-                times[pid] += now.elapsed();
-                #[cfg(debug_assertions)]
-                println!("Player {} played:\n{:?}", pid, game);
+                None => game.pass(),
             }
+            times[pid] += now.elapsed();
+            #[cfg(debug_assertions)]
+            println!("Player {} played:\n{:?}", pid, game.state());
         }
 
-        let scores = game.scores();
+        let state = game.state();
+        let scores = state.scores();
+        let replay = (rng.gen::<f64>() < self.replay_rate).then(|| Replay {
+            w: state.w(),
+            h: state.h(),
+            moves: game
+                .history()
+                .iter()
+                .map(|mv| (mv.player, state.move_cells(mv)))
+                .collect(),
+        });
 
-        Some(array::from_fn(|pid| GameStats {
-            score: scores[pid],
+        // players that played an illegal move score nothing
+        let stats = array::from_fn(|pid| GameStats {
+            score: if illegal[pid] { 0 } else { scores[pid] },
             elapsed: times[pid],
-        }))
+            illegal: illegal[pid],
+        });
+        Some((stats, replay))
         // println!(
         //     "Game {:?} had scores {:?}",
         //     agents
@@ -303,6 +473,7 @@ impl Tournament {
             agent.cumulative_points += stats[player].score as usize;
             agent.games_played += 1;
             agent.elapsed += stats[player].elapsed;
+            agent.illegal_games += stats[player].illegal as usize;
         }
     }
 }
@@ -319,8 +490,8 @@ impl Display for Tournament {
             + 2;
         writeln!(
             f,
-            "{: <w$}{: <15}{: <15}{: <15}{: <15}",
-            "Algorithm", "ELO", "Avg Pts", "Avg ms / Game", "Games Played",
+            "{: <w$}{: <15}{: <15}{: <15}{: <15}{: <15}",
+            "Algorithm", "ELO", "Avg Pts", "Avg ms / Game", "Games Played", "Illegal Games",
         )?;
         // sort agents by ELO
         for agent in self
@@ -330,12 +501,13 @@ impl Display for Tournament {
         {
             writeln!(
                 f,
-                "{: <w$}{: <15.4}{: <15.4}{: <15.4}{: <15.4}",
+                "{: <w$}{: <15.4}{: <15.4}{: <15.4}{: <15.4}{: <15.4}",
                 agent.algorithm.name().yellow(),
                 agent.elo,
                 (agent.cumulative_points as f32) / (agent.games_played as f32),
                 (agent.elapsed.as_millis() as f32) / (agent.games_played as f32),
                 agent.games_played,
+                agent.illegal_games,
             )?;
         }
 
@@ -366,9 +538,66 @@ impl From<&Tournament> for Store {
                         agent.games_played,
                         agent.cumulative_points,
                         agent.elapsed,
+                        agent.illegal_games,
                     ),
                 )
             })
             .collect()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::evaluate::{GreedyMax, Random, Score};
+
+    fn agents() -> Vec<Box<dyn Algorithm + Sync + Send>> {
+        vec![Box::new(Random), Box::new(GreedyMax::<Score>::default())]
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let mut tournament = Tournament::new(100., 1200., 200., agents(), None).unwrap();
+        tournament.agents[0].games_played = 5;
+        tournament.agents[0].illegal_games = 3;
+        tournament.agents[1].elo = 1300.;
+
+        let path = std::env::temp_dir().join(format!("ratings-test-{}.json", std::process::id()));
+        tournament.save(path.clone()).unwrap();
+        let file = std::fs::File::open(&path).unwrap();
+        let loaded = Tournament::new(100., 1200., 200., agents(), Some(file)).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(Store::from(&loaded), Store::from(&tournament));
+        assert_eq!(loaded.agents[0].illegal_games, 3);
+
+        // ratings saved without the illegal games count none
+        let legacy = r#"{"Random": [1250.0, 4, 30, {"secs": 1, "nanos": 0}]}"#;
+        let store = read_store(legacy.as_bytes()).unwrap();
+        assert_eq!(store["Random"], (1250., 4, 30, Duration::from_secs(1), 0));
+        assert!(read_store(r#"{"Random": [1250.0]}"#.as_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_save_games() {
+        let mut tournament = Tournament::new(100., 1200., 200., agents(), None).unwrap();
+        let path = std::env::temp_dir().join(format!("games-test-{}.jsonl", std::process::id()));
+        let mut played = Vec::new();
+        for round in [[0, 1, 0, 1], [1, 1, 0, 0]] {
+            let results = tournament
+                .simulate_game(round)
+                .map(|result| (round, result))
+                .into_iter()
+                .collect();
+            tournament.finish_games(results);
+            played.extend(tournament.games().to_vec());
+            tournament.save_games(path.clone()).unwrap();
+            // every game is written once and then dropped
+            assert!(tournament.games().is_empty());
+        }
+        let file = std::io::BufReader::new(std::fs::File::open(&path).unwrap());
+        let saved = read_games(file).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(saved.len(), 2);
+        assert_eq!(saved, played);
+    }
+}
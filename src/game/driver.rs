@@ -0,0 +1,198 @@
+use super::{IllegalMove, Move, Player, State, Turn};
+
+/// A game being played, keeping track of whose turn it is on top of the [State].
+/// A player without moves passes, and as they can never move again
+/// they are finished and skipped for the rest of the game.
+#[derive(Clone)]
+pub struct Game {
+    state: State,
+    turn: Turn,
+    /// Every move played, in order
+    history: Vec<Move>,
+    /// Number of turns taken, including passes and turns of finished players
+    turns: usize,
+}
+
+impl Game {
+    /// Start a game from a position with player 1 to move
+    pub fn new(state: State) -> Self {
+        Self::with_turn(state, Turn::default())
+    }
+
+    /// Continue a game from a position, for example one that was saved mid-game.
+    /// Move numbers count from this position.
+    pub fn with_turn(state: State, turn: Turn) -> Self {
+        Self {
+            state,
+            turn,
+            history: Vec::new(),
+            turns: usize::from(turn.to_move),
+        }
+    }
+
+    pub fn state(&self) -> &State {
+        &self.state
+    }
+
+    pub fn into_state(self) -> State {
+        self.state
+    }
+
+    pub fn turn(&self) -> &Turn {
+        &self.turn
+    }
+
+    pub fn to_move(&self) -> Player {
+        self.turn.to_move
+    }
+
+    /// Whether a player has passed, and so has no moves for the rest of the game
+    pub fn is_finished(&self, player: &Player) -> bool {
+        self.turn.passed[usize::from(player)]
+    }
+
+    pub fn history(&self) -> &[Move] {
+        &self.history
+    }
+
+    /// Number of the current round, starting at 1 and increasing every time play goes around the table
+    pub fn move_number(&self) -> usize {
+        self.turns / Player::N + 1
+    }
+
+    pub fn is_over(&self) -> bool {
+        self.turn.passed.iter().all(|p| *p)
+    }
+
+    /// Players with the highest score
+    pub fn winners(&self) -> Vec<Player> {
+        let scores = self.state.scores();
+        let best = scores.iter().max().copied().unwrap_or_default();
+        Player::iter()
+            .filter(|player| scores[usize::from(player)] == best)
+            .collect()
+    }
+
+    /// Play a move for the player to move
+    pub fn play(&mut self, mv: Move) {
+        debug_assert!(mv.player == self.turn.to_move, "Played out of turn");
+        self.state.place_piece(&mv);
+        self.history.push(mv);
+        self.next_turn();
+    }
+
+    /// Play a move for the player to move if it is legal, leaving the game untouched otherwise
+    pub fn try_play(&mut self, mv: Move) -> Result<(), IllegalMove> {
+        if mv.player != self.turn.to_move {
+            return Err(IllegalMove::OutOfTurn);
+        }
+        self.state.try_place_piece(&mv)?;
+        self.history.push(mv);
+        self.next_turn();
+        Ok(())
+    }
+
+    /// Pass the turn, finishing the player to move
+    pub fn pass(&mut self) {
+        self.turn.passed[usize::from(self.turn.to_move)] = true;
+        self.next_turn();
+    }
+
+    /// Play a move, or pass if there is none
+    pub fn play_or_pass(&mut self, mv: Option<Move>) {
+        match mv {
+            Some(mv) => self.play(mv),
+            None => self.pass(),
+        }
+    }
+
+    /// Move on to the next player that isn't finished
+    fn next_turn(&mut self) {
+        for _ in 0..Player::N {
+            self.turn.to_move = self.turn.to_move.next();
+            self.turns += 1;
+            if !self.is_finished(&self.turn.to_move) {
+                return;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::seq::SliceRandom;
+
+    #[test]
+    fn test_random_game() {
+        let mut rng = rand::thread_rng();
+        let mut game = Game::new(State::new(20, 20));
+        assert_eq!(game.to_move(), Player::Player1);
+        assert_eq!(game.move_number(), 1);
+        assert_eq!(game.winners().len(), Player::N);
+
+        while !game.is_over() {
+            let player = game.to_move();
+            assert!(!game.is_finished(&player));
+            let mv = game.state().get_moves(&player).choose(&mut rng).cloned();
+            let passed = mv.is_none();
+            game.play_or_pass(mv);
+            assert_eq!(game.is_finished(&player), passed);
+        }
+
+        assert!(Player::iter().all(|p| game.state().get_moves(&p).is_empty()));
+        let scores = game.state().scores();
+        let placed: usize = scores.iter().map(|s| *s as usize).sum();
+        assert!(game.history().len() <= placed);
+        assert!(game.history().len() >= placed / 5);
+        let best = scores.iter().max().unwrap();
+        assert!(game
+            .winners()
+            .iter()
+            .all(|p| scores[usize::from(p)] == *best));
+    }
+
+    #[test]
+    fn test_try_play() {
+        let mut game = Game::new(State::new(20, 20));
+        let mv = game.state().get_moves(&Player::Player1).pop().unwrap();
+        let other = game.state().get_moves(&Player::Player2).pop().unwrap();
+        assert_eq!(game.try_play(other), Err(IllegalMove::OutOfTurn));
+        assert_eq!(game.try_play(mv.clone()), Ok(()));
+        assert_eq!(game.to_move(), Player::Player2);
+        game.pass();
+        game.pass();
+        game.pass();
+        assert_eq!(game.try_play(mv), Err(IllegalMove::PieceUsed));
+        assert_eq!(game.to_move(), Player::Player1);
+        assert_eq!(game.history().len(), 1);
+    }
+
+    #[test]
+    fn test_finished_players_are_skipped() {
+        let mut game = Game::new(State::new(20, 20));
+        let mv = game.state().get_moves(&Player::Player1).pop().unwrap();
+        game.play(mv);
+        assert_eq!(game.to_move(), Player::Player2);
+        game.pass();
+        game.pass();
+        assert_eq!(game.to_move(), Player::Player4);
+        let mv = game.state().get_moves(&Player::Player4).pop().unwrap();
+        game.play(mv);
+        assert_eq!(game.move_number(), 2);
+
+        // Player 2 and 3 don't get a turn anymore
+        assert_eq!(game.to_move(), Player::Player1);
+        let mv = game.state().get_moves(&Player::Player1).pop().unwrap();
+        game.play(mv);
+        assert_eq!(game.to_move(), Player::Player4);
+        assert_eq!(game.move_number(), 2);
+        game.pass();
+        assert_eq!(game.to_move(), Player::Player1);
+        assert_eq!(game.move_number(), 3);
+        game.pass();
+        assert!(game.is_over());
+        assert_eq!(game.history().len(), 3);
+        assert_eq!(game.winners(), vec![Player::Player1]);
+    }
+}
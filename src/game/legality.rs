@@ -0,0 +1,167 @@
+use super::{Corner, Neighbor, Player};
+use std::fmt::Display;
+
+/// Reasons a move can't be played
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum IllegalMove {
+    /// The piece doesn't exist
+    UnknownPiece,
+    /// The player has already placed the piece
+    PieceUsed,
+    /// Part of the piece is off the board
+    OutOfBounds,
+    /// The piece covers a cell that is already covered
+    Overlaps,
+    /// The piece shares an edge with a piece of the same color
+    TouchesOwnColor,
+    /// The piece doesn't touch the corner of a piece of the same color
+    NoCornerContact,
+    /// The player's first piece doesn't cover their starting corner
+    NotOnStartCorner,
+    /// The move is for a player whose turn it isn't
+    OutOfTurn,
+}
+
+impl Display for IllegalMove {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let reason = match self {
+            IllegalMove::UnknownPiece => "the piece doesn't exist",
+            IllegalMove::PieceUsed => "the piece was already placed",
+            IllegalMove::OutOfBounds => "the piece is off the board",
+            IllegalMove::Overlaps => "the piece overlaps another piece",
+            IllegalMove::TouchesOwnColor => "the piece shares an edge with its own color",
+            IllegalMove::NoCornerContact => "the piece doesn't touch a corner of its own color",
+            IllegalMove::NotOnStartCorner => "the first piece doesn't cover the starting corner",
+            IllegalMove::OutOfTurn => "it isn't the player's turn",
+        };
+        write!(f, "Illegal move: {}", reason)
+    }
+}
+
+impl std::error::Error for IllegalMove {}
+
+/// Corner of a `w` by `h` board that a player's first piece has to cover
+pub fn start_corner(player: &Player, w: i8, h: i8) -> (i8, i8) {
+    match player {
+        Player::Player1 => (0, 0),
+        Player::Player2 => (w - 1, 0),
+        Player::Player3 => (w - 1, h - 1),
+        Player::Player4 => (0, h - 1),
+    }
+}
+
+/// Check the placement rules for `player` covering `cells` moved by `pos`
+/// on a `w` by `h` board, where `cell` gets the player covering a cell.
+/// Whether the player still has the piece is up to the caller.
+pub(crate) fn check_placement(
+    player: &Player,
+    cells: &[(i8, i8)],
+    pos: (i8, i8),
+    (w, h): (i8, i8),
+    first_move: bool,
+    cell: impl Fn(i8, i8) -> Option<Player>,
+) -> Result<(), IllegalMove> {
+    let in_bounds = |(x, y): (i8, i8)| x >= 0 && y >= 0 && x < w && y < h;
+
+    let mut placed = Vec::with_capacity(cells.len());
+    for (x, y) in cells {
+        let (x, y) = (pos.0 as i16 + *x as i16, pos.1 as i16 + *y as i16);
+        if x < 0 || y < 0 || x >= w as i16 || y >= h as i16 {
+            return Err(IllegalMove::OutOfBounds);
+        }
+        placed.push((x as i8, y as i8));
+    }
+
+    if placed.iter().any(|(x, y)| cell(*x, *y).is_some()) {
+        return Err(IllegalMove::Overlaps);
+    }
+
+    let own = |pos: (i8, i8)| in_bounds(pos) && cell(pos.0, pos.1) == Some(*player);
+    if placed
+        .iter()
+        .any(|pos| Neighbor::iter().any(|n| own(n + *pos)))
+    {
+        return Err(IllegalMove::TouchesOwnColor);
+    }
+
+    if first_move {
+        match placed.contains(&start_corner(player, w, h)) {
+            true => Ok(()),
+            false => Err(IllegalMove::NotOnStartCorner),
+        }
+    } else {
+        match placed
+            .iter()
+            .any(|pos| Corner::iter().any(|c| own(c + *pos)))
+        {
+            true => Ok(()),
+            false => Err(IllegalMove::NoCornerContact),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::game::{
+        utils::{PieceID, PieceTransformID},
+        ver_1, ver_3, Dimensioned, Player,
+    };
+    use rand::{seq::SliceRandom, Rng};
+
+    /// Play random games, checking that every generated move is legal
+    /// and that every random move is legal exactly when it was generated
+    macro_rules! check_engine {
+        ($state:expr, $random_move:expr) => {{
+            let mut rng = rand::thread_rng();
+            let mut state = $state;
+            let mut done = [false; Player::N];
+            while !done.iter().all(|d| *d) {
+                for player in Player::iter() {
+                    let moves = state.get_moves(&player);
+                    for mv in &moves {
+                        assert_eq!(state.is_legal(mv), Ok(()));
+                    }
+                    for _ in 0..200 {
+                        let mv = $random_move(&mut rng, &state, player);
+                        assert_eq!(state.is_legal(&mv).is_ok(), moves.contains(&mv));
+                    }
+
+                    match moves.choose(&mut rng) {
+                        Some(mv) => state.try_place_piece(mv).unwrap(),
+                        None => done[usize::from(player)] = true,
+                    }
+                }
+            }
+        }};
+    }
+
+    #[test]
+    fn test_ver_3_matches_moves() {
+        check_engine!(
+            ver_3::State::new(14, 14),
+            |rng: &mut rand::rngs::ThreadRng, state: &ver_3::State, player| {
+                let pos = (rng.gen_range(-2..state.w()), rng.gen_range(-2..state.h()));
+                ver_3::Move::new(player, rng.gen_range(0..91), pos)
+            }
+        );
+    }
+
+    #[test]
+    fn test_ver_1_matches_moves() {
+        check_engine!(
+            ver_1::State::new(14, 14),
+            |rng: &mut rand::rngs::ThreadRng, state: &ver_1::State, player| {
+                // Any version of any piece, even if the player already used it
+                let pid = usize::from(player);
+                let piece = rng.gen_range(0..ver_1::PIECES[pid].len());
+                let version = rng.gen_range(0..ver_1::PIECES[pid][piece].versions.len());
+                let pos = (rng.gen_range(-2..state.w()), rng.gen_range(-2..state.h()));
+                ver_1::Move::new(
+                    player,
+                    PieceTransformID::new(&PieceID::from(piece), version),
+                    pos,
+                )
+            }
+        );
+    }
+}
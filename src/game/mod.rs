@@ -1,3 +1,8 @@
+mod driver;
+mod legality;
+pub mod pieces;
+pub mod svg;
+pub mod symmetry;
 mod utils;
 pub mod ver_1;
 pub mod ver_2;
@@ -10,4 +15,9 @@ pub use ver_2::*;
 #[cfg(alg_ver = "3")]
 pub use ver_3::*;
 
-pub use utils::{Corner, Dimensioned, Neighbor, Player, Reflection, Rotation, Transformation};
+pub use driver::Game;
+pub use legality::{start_corner, IllegalMove};
+pub use pieces::{PieceSet, PieceSetError, Polyomino};
+pub use utils::{
+    Corner, Dimensioned, Neighbor, Player, Reflection, Rotation, Shape, Transformation, Turn,
+};
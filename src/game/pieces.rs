@@ -0,0 +1,494 @@
+use super::{Dimensioned, Reflection, Rotation, Shape, Transformation};
+use once_cell::sync::{Lazy, OnceCell};
+use std::{
+    fmt::{Debug, Display},
+    path::Path,
+    str::FromStr,
+};
+
+/// The pieces of standard Blokus, in the same order as [crate::game::Shape]
+const STANDARD: &str = include_str!("../../pieces/standard.txt");
+
+static STANDARD_SET: Lazy<PieceSet> = Lazy::new(PieceSet::standard);
+
+/// A named polyomino, stored as the cells it covers.
+/// Cells are relative to the top left corner of the bounding box
+/// and sorted by row, then column.
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct Polyomino {
+    name: String,
+    cells: Vec<(i8, i8)>,
+}
+
+impl Polyomino {
+    pub fn new(name: &str, cells: impl IntoIterator<Item = (i8, i8)>) -> Self {
+        let mut cells: Vec<_> = cells.into_iter().collect();
+        // Move the cells to the top left corner
+        let min_x = cells.iter().map(|(x, _)| *x).min().unwrap_or(0);
+        let min_y = cells.iter().map(|(_, y)| *y).min().unwrap_or(0);
+        for (x, y) in cells.iter_mut() {
+            *x -= min_x;
+            *y -= min_y;
+        }
+        cells.sort_by_key(|&(x, y)| (y, x));
+        cells.dedup();
+
+        Self {
+            name: name.to_owned(),
+            cells,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn cells(&self) -> &[(i8, i8)] {
+        &self.cells
+    }
+
+    /// Number of tiles in the polyomino
+    pub fn size(&self) -> usize {
+        self.cells.len()
+    }
+
+    /// One bitmask per row, with bit x set if the cell at x is covered
+    pub fn rows(&self) -> Vec<u32> {
+        let mut rows = vec![0; self.h() as usize];
+        for &(x, y) in &self.cells {
+            rows[y as usize] |= 1 << x;
+        }
+        rows
+    }
+
+    /// Rotate and then reflect the polyomino.
+    /// Rotations match the masks of [crate::game::ver_1], and reflections flip it vertically.
+    pub fn transform(&self, Transformation(rotation, reflection): &Transformation) -> Self {
+        let turns = match rotation {
+            Rotation::Zero => 0,
+            Rotation::Ninety => 1,
+            Rotation::OneEighty => 2,
+            Rotation::TwoSeventy => 3,
+        };
+        let cells = self.cells.iter().map(|&(x, y)| {
+            let (x, y) = (0..turns).fold((x, y), |(x, y), _| (y, -x));
+            match reflection {
+                Reflection::Flip => (x, -y),
+                Reflection::NoFlip => (x, y),
+            }
+        });
+        Self::new(&self.name, cells)
+    }
+
+    /// Every distinct rotation and reflection of the polyomino,
+    /// in the order of [Transformation::iter]
+    pub fn transformations(&self) -> Vec<Self> {
+        let mut transformations: Vec<Self> = Vec::with_capacity(8);
+        for transformation in Transformation::iter() {
+            let transformed = self.transform(&transformation);
+            if !transformations.contains(&transformed) {
+                transformations.push(transformed);
+            }
+        }
+        transformations
+    }
+
+    /// Whether every cell can be reached from every other through shared edges
+    fn connected(&self) -> bool {
+        let mut seen = vec![false; self.cells.len()];
+        let mut stack = vec![0];
+        seen[0] = true;
+        while let Some(i) = stack.pop() {
+            let (x, y) = self.cells[i];
+            for (j, &(nx, ny)) in self.cells.iter().enumerate() {
+                if !seen[j] && (x - nx).abs() + (y - ny).abs() == 1 {
+                    seen[j] = true;
+                    stack.push(j);
+                }
+            }
+        }
+        seen.into_iter().all(|s| s)
+    }
+}
+
+impl Dimensioned for Polyomino {
+    #[inline]
+    fn w(&self) -> i8 {
+        self.cells.iter().map(|(x, _)| x + 1).max().unwrap_or(0)
+    }
+
+    #[inline]
+    fn h(&self) -> i8 {
+        self.cells.last().map_or(0, |(_, y)| y + 1)
+    }
+}
+
+impl Display for Polyomino {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{}", self.name)?;
+        for row in self.rows() {
+            for x in 0..self.w() {
+                write!(f, "{}", if row >> x & 1 != 0 { 'X' } else { '.' })?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+impl Debug for Polyomino {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        Display::fmt(self, f)
+    }
+}
+
+/// The base shapes every player starts with.
+///
+/// Sets are written as a name on its own line followed by the rows of the shape,
+/// using `X` for tiles and `.` for empty cells.
+/// Shapes are separated by blank lines and lines starting with `#` are ignored.
+#[derive(Clone, Debug)]
+pub struct PieceSet {
+    shapes: Vec<Polyomino>,
+    /// See [PieceSet::standard_shapes]
+    standard: OnceCell<Vec<Option<Shape>>>,
+}
+
+impl PieceSet {
+    /// The 21 pieces of standard Blokus, in the same order as [crate::game::Shape]
+    pub fn standard() -> Self {
+        STANDARD.parse().expect("Invalid standard piece set")
+    }
+
+    /// Load a piece set from a file
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, PieceSetError> {
+        std::fs::read_to_string(path)
+            .map_err(PieceSetError::Io)?
+            .parse()
+    }
+
+    pub fn shapes(&self) -> &[Polyomino] {
+        &self.shapes
+    }
+
+    /// The [Shape] of standard Blokus that every shape is a rotation or reflection of,
+    /// or [None] for shapes that are not in the standard set
+    pub fn standard_shapes(&self) -> &[Option<Shape>] {
+        self.standard.get_or_init(|| {
+            self.shapes
+                .iter()
+                .map(|shape| {
+                    let transformations = shape.transformations();
+                    STANDARD_SET
+                        .shapes
+                        .iter()
+                        .position(|standard| {
+                            transformations.iter().any(|t| t.cells == standard.cells)
+                        })
+                        .map(Shape::from)
+                })
+                .collect()
+        })
+    }
+
+    /// Total number of distinct transformations of all the shapes
+    pub fn transformation_count(&self) -> usize {
+        self.shapes
+            .iter()
+            .map(|shape| shape.transformations().len())
+            .sum()
+    }
+}
+
+impl FromStr for PieceSet {
+    type Err = PieceSetError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut shapes: Vec<Polyomino> = vec![];
+
+        let lines: Vec<_> = s
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.starts_with('#'))
+            .collect();
+        for block in lines.split(|line| line.is_empty()) {
+            let Some((name, rows)) = block.split_first() else {
+                continue;
+            };
+
+            let mut cells = vec![];
+            for (y, row) in rows.iter().enumerate() {
+                for (x, cell) in row.chars().enumerate() {
+                    match cell {
+                        'X' => cells.push((x as i8, y as i8)),
+                        '.' => {}
+                        cell => {
+                            return Err(PieceSetError::InvalidCell {
+                                shape: name.to_string(),
+                                cell,
+                            })
+                        }
+                    }
+                }
+            }
+
+            let shape = Polyomino::new(name, cells);
+            if shape.size() == 0 {
+                return Err(PieceSetError::Empty(shape.name));
+            }
+            if !shape.connected() {
+                return Err(PieceSetError::Disconnected(shape.name));
+            }
+            if let Some(other) = shapes.iter().find(|other| {
+                shape
+                    .transformations()
+                    .iter()
+                    .any(|t| t.cells == other.cells)
+            }) {
+                return Err(PieceSetError::Duplicate(
+                    other.name.clone(),
+                    shape.name.clone(),
+                ));
+            }
+            shapes.push(shape);
+        }
+
+        match shapes.is_empty() {
+            true => Err(PieceSetError::NoShapes),
+            false => Ok(Self {
+                shapes,
+                standard: OnceCell::new(),
+            }),
+        }
+    }
+}
+
+impl Display for PieceSet {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (i, shape) in self.shapes.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{}", shape)?;
+        }
+        Ok(())
+    }
+}
+
+/// Reasons a piece set could not be loaded
+#[derive(Debug)]
+pub enum PieceSetError {
+    Io(std::io::Error),
+    /// A shape contains a character other than `X` or `.`
+    InvalidCell {
+        shape: String,
+        cell: char,
+    },
+    /// A shape has no tiles
+    Empty(String),
+    /// The tiles of a shape are not all connected by their edges
+    Disconnected(String),
+    /// Two shapes are rotations or reflections of each other
+    Duplicate(String, String),
+    /// The set has no shapes
+    NoShapes,
+}
+
+impl Display for PieceSetError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PieceSetError::Io(err) => write!(f, "Failed to read piece set: {}", err),
+            PieceSetError::InvalidCell { shape, cell } => {
+                write!(f, "Shape {} has invalid cell {:?}", shape, cell)
+            }
+            PieceSetError::Empty(shape) => write!(f, "Shape {} has no tiles", shape),
+            PieceSetError::Disconnected(shape) => write!(f, "Shape {} is not connected", shape),
+            PieceSetError::Duplicate(a, b) => write!(f, "Shapes {} and {} are the same", a, b),
+            PieceSetError::NoShapes => write!(f, "Piece set has no shapes"),
+        }
+    }
+}
+
+impl std::error::Error for PieceSetError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::{ver_1, Player, Shape};
+    use rand::seq::SliceRandom;
+
+    #[test]
+    fn test_standard_matches_shapes() {
+        let set = PieceSet::standard();
+        assert_eq!(set.shapes().len(), Shape::N);
+        for (shape, polyomino) in Shape::iter().zip(set.shapes()) {
+            assert_eq!(polyomino.name(), format!("{:?}", shape));
+            assert_eq!(polyomino.size(), shape.size() as usize);
+        }
+        assert_eq!(set.transformation_count(), 91);
+    }
+
+    #[test]
+    fn test_transformations() {
+        let count = |rows: &str| {
+            PieceSet::from_str(&format!("A\n{}", rows))
+                .unwrap()
+                .shapes()[0]
+                .transformations()
+                .len()
+        };
+        assert_eq!(count(".X.\nXXX\n.X."), 1);
+        assert_eq!(count("XX\nX."), 4);
+        assert_eq!(count(".XX\nXX."), 4);
+        assert_eq!(count("X..\nXXX"), 8);
+
+        // Same cases as the ver_1 mask tests, where bit x is column x
+        let v = Polyomino::new("V3", [(0, 0), (0, 1), (1, 1)]);
+        assert_eq!(v.rows(), vec![0b01, 0b11]);
+        let rotated = v.transform(&Transformation(Rotation::Ninety, Reflection::NoFlip));
+        assert_eq!(rotated.rows(), vec![0b10, 0b11]);
+        let flipped = v.transform(&Transformation(Rotation::Zero, Reflection::Flip));
+        assert_eq!(flipped.rows(), vec![0b11, 0b01]);
+        let both = v.transform(&Transformation(Rotation::OneEighty, Reflection::Flip));
+        assert_eq!(both.rows(), vec![0b10, 0b11]);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let set = PieceSet::standard();
+        let parsed: PieceSet = set.to_string().parse().unwrap();
+        assert_eq!(parsed.shapes(), set.shapes());
+    }
+
+    #[test]
+    fn test_load() {
+        let set = PieceSet::load("pieces/tetrominoes.txt").unwrap();
+        assert_eq!(set.shapes().len(), 5);
+        assert!(set.shapes().iter().all(|shape| shape.size() == 4));
+        assert!(matches!(
+            PieceSet::load("pieces/missing.txt"),
+            Err(PieceSetError::Io(_))
+        ));
+    }
+
+    #[test]
+    fn test_standard_shapes() {
+        let standard: Vec<_> = Shape::iter().map(Some).collect();
+        assert_eq!(PieceSet::standard().standard_shapes(), standard);
+
+        let set = PieceSet::load("pieces/tetrominoes.txt").unwrap();
+        let tetrominoes = [Shape::I4, Shape::L4, Shape::Z4, Shape::O4, Shape::T4].map(Some);
+        assert_eq!(set.standard_shapes(), tetrominoes);
+
+        // rotated and reflected shapes are recognized, bigger ones are not
+        let set: PieceSet = "J\nX..\nXXX\n\nI6\nXXXXXX".parse().unwrap();
+        assert_eq!(set.standard_shapes(), [Some(Shape::L4), None]);
+    }
+
+    #[test]
+    fn test_invalid_sets() {
+        assert!(matches!(
+            "A\nX.X".parse::<PieceSet>(),
+            Err(PieceSetError::Disconnected(_))
+        ));
+        assert!(matches!(
+            "A\nXO".parse::<PieceSet>(),
+            Err(PieceSetError::InvalidCell { cell: 'O', .. })
+        ));
+        assert!(matches!(
+            "A\n...".parse::<PieceSet>(),
+            Err(PieceSetError::Empty(_))
+        ));
+        assert!(matches!(
+            "A\nXX\nX.\n\nB\nX.\nXX".parse::<PieceSet>(),
+            Err(PieceSetError::Duplicate(..))
+        ));
+        assert!(matches!(
+            "# nothing".parse::<PieceSet>(),
+            Err(PieceSetError::NoShapes)
+        ));
+    }
+
+    #[test]
+    fn test_play_custom_set() {
+        let standard = ver_1::State::with_pieces(20, 20, &PieceSet::standard());
+        let player = Player::Player1;
+        assert_eq!(
+            standard.get_moves(&player).len(),
+            ver_1::State::new(20, 20).get_moves(&player).len()
+        );
+
+        let set = PieceSet::load("pieces/tetrominoes.txt").unwrap();
+        let mut game = ver_1::State::with_pieces(14, 14, &set);
+        let mut rng = rand::thread_rng();
+        let mut done = [false; Player::N];
+        while !done.iter().all(|d| *d) {
+            for player in Player::iter() {
+                match game.get_moves(&player).choose(&mut rng) {
+                    Some(mv) => game.place_piece(mv),
+                    None => done[usize::from(player)] = true,
+                }
+            }
+        }
+        // Every player can place at least their first piece, and at most all 5
+        assert!(game
+            .scores()
+            .iter()
+            .all(|s| (4..=20).contains(s) && s % 4 == 0));
+    }
+
+    /// A whole game with a custom set, through everything that looks at the pieces
+    #[test]
+    #[cfg(not(any(alg_ver = "2", alg_ver = "3")))]
+    fn test_custom_set_helpers() {
+        use crate::evaluate::{encode_pieces, Algorithm, Distance, Heuristic, Inventory, Score};
+        use crate::game::{svg, State};
+
+        let set = PieceSet::load("pieces/tetrominoes.txt").unwrap();
+        let tetrominoes = [Shape::I4, Shape::L4, Shape::Z4, Shape::O4, Shape::T4];
+        let mut state = State::with_pieces(14, 14, &set);
+        let mut rng = rand::thread_rng();
+        let mut done = [false; Player::N];
+        while !done.iter().all(|d| *d) {
+            for player in Player::iter() {
+                let Some(mv) = Distance::TowardCenter.decide(&mut rng, &state, &player, 0) else {
+                    done[usize::from(player)] = true;
+                    continue;
+                };
+                let piece = state.move_piece(&mv);
+                assert!(state.remaining_pieces(&player).contains(&piece));
+                assert_eq!(state.move_size(&mv), 4);
+                let cells = state.move_cells(&mv);
+                let w = cells.iter().map(|(x, _)| x - mv.pos.0 + 1).max().unwrap();
+                let h = cells.iter().map(|(_, y)| y - mv.pos.1 + 1).max().unwrap();
+                assert_eq!(state.move_dims(&mv), (w as u8, h as u8));
+
+                let score = Score.evaluate_move(&mut rng, &state, &player, &mv);
+                let inventory = Inventory.evaluate_move(&mut rng, &state, &player, &mv);
+                state.place_piece(&mv);
+                assert_eq!(score, state.scores()[usize::from(player)]);
+                assert_eq!(inventory, Inventory.evaluate(&mut rng, &state, &player));
+
+                // the pieces are encoded as the standard tetrominoes they are
+                let encoded = encode_pieces(&state);
+                for p in Player::iter() {
+                    let mut expected = vec![0; Shape::N];
+                    for piece in state.remaining_pieces(&p) {
+                        expected[usize::from(tetrominoes[piece])] = 1;
+                    }
+                    let pid = usize::from(p);
+                    assert_eq!(encoded[pid * Shape::N..(pid + 1) * Shape::N], expected);
+                }
+            }
+        }
+
+        // the hand shows the cells of the pieces that are left
+        let drawing = svg::render_state(&state, None);
+        for player in Player::iter() {
+            let pid = usize::from(player);
+            let hand = format!(r#"class="hand p{}""#, pid + 1);
+            let left = 20 - state.scores()[pid] as usize;
+            assert_eq!(drawing.matches(&hand).count(), left);
+        }
+    }
+}
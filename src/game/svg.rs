@@ -0,0 +1,397 @@
+//! SVG drawings of positions and recorded games, for reports and reviews.
+//! Recorded games are replayed from their starting position, for example with [Game::history](super::Game::history).
+
+use super::{Dimensioned, Move, PieceSet, Player, State};
+use colored::Color;
+use std::{fmt::Write, time::Duration};
+
+/// Side of a board cell
+const CELL: i32 = 20;
+/// Room left around the board for the coordinates
+const MARGIN: i32 = 20;
+/// Side of a cell of the pieces drawn beside the board
+const HAND_CELL: i32 = 5;
+/// Room taken by one piece in hand, including the gap to the next
+const SLOT: i32 = 6 * HAND_CELL;
+/// Pieces in hand drawn on one row
+const SLOTS_PER_ROW: i32 = 7;
+/// Height of the label above a player's pieces
+const LABEL: i32 = 16;
+
+/// Fill color of a player, following the terminal color from [Player::color]
+pub fn fill(player: &Player) -> &'static str {
+    match player.color() {
+        Color::Red => "#e53935",
+        Color::Green => "#43a047",
+        Color::Yellow => "#fdd835",
+        Color::Blue => "#1e88e5",
+        _ => "#9e9e9e",
+    }
+}
+
+/// Height of the pieces in hand of one player, with room for every piece of the set
+fn hand_height(set: &PieceSet) -> i32 {
+    let rows = (set.shapes().len() as i32 + SLOTS_PER_ROW - 1) / SLOTS_PER_ROW;
+    LABEL + rows * SLOT
+}
+
+/// Width and height of a drawing of a `w` by `h` board alone
+fn board_size(w: i8, h: i8) -> (i32, i32) {
+    (2 * MARGIN + w as i32 * CELL, 2 * MARGIN + h as i32 * CELL)
+}
+
+/// Width and height of a drawing of the state
+fn size(state: &State) -> (i32, i32) {
+    let (board_w, board_h) = board_size(state.w(), state.h());
+    let hand_h = MARGIN + Player::N as i32 * hand_height(state.piece_set());
+    (board_w + SLOTS_PER_ROW * SLOT, board_h.max(hand_h))
+}
+
+fn header(out: &mut String, (w, h): (i32, i32)) {
+    writeln!(
+        out,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{w}" height="{h}" viewBox="0 0 {w} {h}" font-family="sans-serif" font-size="10">"#
+    )
+    .unwrap();
+    writeln!(out, r#"<rect width="{w}" height="{h}" fill="white"/>"#).unwrap();
+}
+
+/// Draw the coordinates and the cells of a board, outlining the `last` cells
+fn draw_board(
+    out: &mut String,
+    (w, h): (i8, i8),
+    cell: impl Fn(i8, i8) -> Option<Player>,
+    last: &[(i8, i8)],
+) {
+    // coordinates
+    for x in 0..w {
+        let cx = MARGIN + x as i32 * CELL + CELL / 2;
+        writeln!(
+            out,
+            r#"<text x="{cx}" y="{}" text-anchor="middle">{x}</text>"#,
+            MARGIN - 6
+        )
+        .unwrap();
+    }
+    for y in 0..h {
+        let cy = MARGIN + y as i32 * CELL + CELL / 2 + 4;
+        writeln!(
+            out,
+            r#"<text x="{}" y="{cy}" text-anchor="end">{y}</text>"#,
+            MARGIN - 4
+        )
+        .unwrap();
+    }
+
+    // cells
+    for y in 0..h {
+        for x in 0..w {
+            let (px, py) = (MARGIN + x as i32 * CELL, MARGIN + y as i32 * CELL);
+            let (class, color) = match cell(x, y) {
+                Some(player) => (format!("p{}", usize::from(player) + 1), fill(&player)),
+                None => ("empty".to_string(), "#f5f5f5"),
+            };
+            writeln!(
+                out,
+                r##"<rect class="{class}" x="{px}" y="{py}" width="{CELL}" height="{CELL}" fill="{color}" stroke="#bdbdbd"/>"##
+            )
+            .unwrap();
+        }
+    }
+
+    // last move
+    for (x, y) in last {
+        let (px, py) = (MARGIN + *x as i32 * CELL, MARGIN + *y as i32 * CELL);
+        writeln!(
+            out,
+            r#"<rect class="last" x="{}" y="{}" width="{}" height="{}" fill="none" stroke="black" stroke-width="2"/>"#,
+            px + 1,
+            py + 1,
+            CELL - 2,
+            CELL - 2
+        )
+        .unwrap();
+    }
+}
+
+/// Draw the board, the coordinates and the pieces in hand
+fn draw(out: &mut String, state: &State, last: Option<&Move>) {
+    let (w, h) = (state.w(), state.h());
+    let last = last.map(|mv| state.move_cells(mv)).unwrap_or_default();
+    draw_board(out, (w, h), |x, y| state.cell(x, y), &last);
+
+    // pieces in hand
+    let scores = state.scores();
+    let left = board_size(w, h).0;
+    let shapes = state.piece_set().shapes();
+    let hand_h = hand_height(state.piece_set());
+    for player in Player::iter() {
+        let pid = usize::from(player);
+        let top = MARGIN + pid as i32 * hand_h;
+        writeln!(
+            out,
+            r#"<text x="{left}" y="{}">Player {}: {}</text>"#,
+            top + LABEL - 6,
+            pid + 1,
+            scores[pid]
+        )
+        .unwrap();
+        for (i, piece) in state.remaining_pieces(&player).into_iter().enumerate() {
+            let (col, row) = (i as i32 % SLOTS_PER_ROW, i as i32 / SLOTS_PER_ROW);
+            let (sx, sy) = (left + col * SLOT, top + LABEL + row * SLOT);
+            for (x, y) in shapes[piece].cells() {
+                writeln!(
+                    out,
+                    r#"<rect class="hand p{}" x="{}" y="{}" width="{HAND_CELL}" height="{HAND_CELL}" fill="{}" stroke="white" stroke-width="0.5"/>"#,
+                    pid + 1,
+                    sx + *x as i32 * HAND_CELL,
+                    sy + *y as i32 * HAND_CELL,
+                    fill(&player)
+                )
+                .unwrap();
+            }
+        }
+    }
+}
+
+/// Open the group of frame `i` out of `n` of an animation showing every frame for `frame`, looping forever.
+/// Only the last frame is visible without animation support.
+fn open_frame(out: &mut String, i: usize, n: usize, frame: Duration) {
+    let visibility = if i == n - 1 { "visible" } else { "hidden" };
+    writeln!(out, r#"<g class="frame" visibility="{visibility}">"#).unwrap();
+
+    // every frame is shown for its share of the loop, with discrete steps
+    let dur = frame.as_secs_f64() * n as f64;
+    let (start, end) = (i as f64 / n as f64, (i + 1) as f64 / n as f64);
+    let steps = match (i, n) {
+        (_, 1) => None,
+        (0, _) => Some(("visible;hidden", format!("0;{end:.4}"))),
+        (i, n) if i == n - 1 => Some(("hidden;visible", format!("0;{start:.4}"))),
+        _ => Some(("hidden;visible;hidden", format!("0;{start:.4};{end:.4}"))),
+    };
+    if let Some((values, key_times)) = steps {
+        writeln!(
+            out,
+            r#"<animate attributeName="visibility" values="{values}" keyTimes="{key_times}" dur="{dur}s" calcMode="discrete" repeatCount="indefinite"/>"#
+        )
+        .unwrap();
+    }
+}
+
+/// Draw a position, outlining the cells of the last move if there is one
+pub fn render_state(state: &State, last: Option<&Move>) -> String {
+    let mut out = String::new();
+    header(&mut out, size(state));
+    draw(&mut out, state, last);
+    out.push_str("</svg>\n");
+    out
+}
+
+/// Draw every position of a game played from `start`, starting with `start` itself
+pub fn game_frames(start: &State, moves: &[Move]) -> Vec<String> {
+    let mut state = start.clone();
+    let mut frames = vec![render_state(&state, None)];
+    for mv in moves {
+        state.place_piece(mv);
+        frames.push(render_state(&state, Some(mv)));
+    }
+    frames
+}
+
+/// Draw a game played from `start` as one animated drawing showing every position for `frame`, looping forever.
+/// Viewers without animation support show the final position.
+pub fn render_game(start: &State, moves: &[Move], frame: Duration) -> String {
+    let mut out = String::new();
+    header(&mut out, size(start));
+
+    let n = moves.len() + 1;
+    let mut state = start.clone();
+    for i in 0..n {
+        let last = match i {
+            0 => None,
+            _ => {
+                let mv = &moves[i - 1];
+                state.place_piece(mv);
+                Some(mv)
+            }
+        };
+        open_frame(&mut out, i, n, frame);
+        draw(&mut out, &state, last);
+        out.push_str("</g>\n");
+    }
+
+    out.push_str("</svg>\n");
+    out
+}
+
+/// Draw a game on an empty `w` by `h` board from the cells covered by every move, without the pieces in hand.
+/// Like [render_game] it shows every position for `frame`, but needs no engine to replay the game.
+pub fn render_replay(w: i8, h: i8, moves: &[(Player, Vec<(i8, i8)>)], frame: Duration) -> String {
+    let mut out = String::new();
+    header(&mut out, board_size(w, h));
+
+    let n = moves.len() + 1;
+    let mut cells = vec![None; w as usize * h as usize];
+    let index = |x: i8, y: i8| y as usize * w as usize + x as usize;
+    for i in 0..n {
+        let last: &[(i8, i8)] = match i {
+            0 => &[],
+            _ => {
+                let (player, covered) = &moves[i - 1];
+                for (x, y) in covered {
+                    cells[index(*x, *y)] = Some(*player);
+                }
+                covered
+            }
+        };
+        open_frame(&mut out, i, n, frame);
+        draw_board(&mut out, (w, h), |x, y| cells[index(x, y)], last);
+        out.push_str("</g>\n");
+    }
+
+    out.push_str("</svg>\n");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::Game;
+    use rand::seq::SliceRandom;
+
+    fn count(svg: &str, pattern: &str) -> usize {
+        svg.matches(pattern).count()
+    }
+
+    /// Play a few random moves
+    fn random_game(moves: usize) -> Game {
+        let mut rng = rand::thread_rng();
+        let mut game = Game::new(State::new(20, 20));
+        for _ in 0..moves {
+            let mv = game
+                .state()
+                .get_moves(&game.to_move())
+                .choose(&mut rng)
+                .cloned();
+            game.play_or_pass(mv);
+        }
+        game
+    }
+
+    #[test]
+    fn test_render_state() {
+        let game = random_game(9);
+        let state = game.state();
+        let svg = render_state(state, game.history().last());
+        assert!(svg.starts_with("<svg"));
+        assert!(svg.ends_with("</svg>\n"));
+
+        let total: usize = state.piece_set().shapes().iter().map(|s| s.size()).sum();
+        for player in Player::iter() {
+            let pid = usize::from(player);
+            let score = state.scores()[pid] as usize;
+            assert_eq!(count(&svg, &format!(r#"class="p{}""#, pid + 1)), score);
+            assert_eq!(
+                count(&svg, &format!(r#"class="hand p{}""#, pid + 1)),
+                total - score
+            );
+        }
+        let last = game.history().last().unwrap();
+        assert_eq!(count(&svg, r#"class="last""#), state.move_cells(last).len());
+        let placed: usize = state.scores().iter().map(|s| *s as usize).sum();
+        assert_eq!(count(&svg, r#"class="empty""#) + placed, 400);
+    }
+
+    /// The hand of every player stays in its own rows however many pieces the set has
+    #[test]
+    #[cfg(not(any(alg_ver = "2", alg_ver = "3")))]
+    fn test_render_large_set() {
+        let hexominoes = [
+            ("H1", "XXX\nXXX"),
+            ("H2", "XXX\nXX.\nX.."),
+            ("H3", "XXX\nX.X\nX.."),
+            ("H4", "XX.\nXXX\nX.."),
+            ("H5", ".X.\nXXX\nXX."),
+            ("H6", ".XX\nXXX\nX.."),
+            ("H7", "X..\nXXX\nX.X"),
+        ];
+        let mut text = std::fs::read_to_string("pieces/standard.txt").unwrap();
+        for (name, rows) in hexominoes {
+            text += &format!("\n\n{name}\n{rows}\n");
+        }
+        let set: PieceSet = text.parse().unwrap();
+        assert!(set.shapes().len() > PieceSet::standard().shapes().len());
+        let state = State::with_pieces(20, 20, &set);
+        let svg = render_state(&state, None);
+
+        let (_, height) = size(&state);
+        let mut bottom = 0;
+        for player in Player::iter() {
+            let hand = format!(r#"class="hand p{}""#, usize::from(player) + 1);
+            let ys: Vec<i32> = svg
+                .lines()
+                .filter(|line| line.contains(&hand))
+                .map(|line| {
+                    let y = &line[line.find(" y=\"").unwrap() + 4..];
+                    y[..y.find('"').unwrap()].parse().unwrap()
+                })
+                .collect();
+            let total: usize = set.shapes().iter().map(|s| s.size()).sum();
+            assert_eq!(ys.len(), total);
+            assert!(*ys.iter().min().unwrap() > bottom);
+            bottom = *ys.iter().max().unwrap() + HAND_CELL;
+        }
+        assert!(bottom <= height);
+    }
+
+    #[test]
+    fn test_render_game() {
+        let game = random_game(8);
+        let start = State::new(20, 20);
+        let frames = game_frames(&start, game.history());
+        assert_eq!(frames.len(), game.history().len() + 1);
+        assert_eq!(frames[0], render_state(&start, None));
+        assert_eq!(
+            frames.last().unwrap(),
+            &render_state(game.state(), game.history().last())
+        );
+
+        let svg = render_game(&start, game.history(), Duration::from_millis(500));
+        assert_eq!(count(&svg, r#"<g class="frame""#), frames.len());
+        assert_eq!(count(&svg, "<animate"), frames.len());
+        assert_eq!(count(&svg, r#"visibility="visible">"#), 1);
+        assert!(svg.contains(&format!(r#"dur="{}s""#, frames.len() as f64 * 0.5)));
+        // every frame contains the drawing of its position
+        for frame in &frames {
+            let body = frame.lines().skip(2).take_while(|l| *l != "</svg>");
+            assert!(body.into_iter().all(|line| svg.contains(line)));
+        }
+    }
+
+    #[test]
+    fn test_render_replay() {
+        let game = random_game(8);
+        let state = game.state();
+        let moves: Vec<_> = game
+            .history()
+            .iter()
+            .map(|mv| (mv.player, state.move_cells(mv)))
+            .collect();
+        let svg = render_replay(state.w(), state.h(), &moves, Duration::from_millis(200));
+        assert_eq!(count(&svg, r#"<g class="frame""#), moves.len() + 1);
+
+        // the cells of a move are drawn in every frame from the one it was played in
+        for player in Player::iter() {
+            let drawn: usize = moves
+                .iter()
+                .enumerate()
+                .filter(|(_, (p, _))| *p == player)
+                .map(|(i, (_, cells))| cells.len() * (moves.len() - i))
+                .sum();
+            let class = format!(r#"class="p{}""#, usize::from(player) + 1);
+            assert_eq!(count(&svg, &class), drawn);
+        }
+        let last: usize = moves.iter().map(|(_, cells)| cells.len()).sum();
+        assert_eq!(count(&svg, r#"class="last""#), last);
+    }
+}
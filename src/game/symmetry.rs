@@ -0,0 +1,230 @@
+//! Symmetries of the board, and a heuristic for searches to skip moves that mirror each other.
+//!
+//! No symmetry but the identity keeps the start corners of all four players in place,
+//! so no position is exactly symmetric and skipping mirrored moves is never exact.
+//! [skip_mirrored] only asks the player to move to keep their own corner, so the opening
+//! looks symmetric about the player's diagonal even though the players on either side
+//! of it move in a different order. Mirrored moves can then be worth different amounts
+//! and a search that skips them may miss the best move.
+
+use super::{start_corner, Dimensioned, Move, Player, State};
+use rustc_hash::FxHashSet;
+
+/// A symmetry of a rectangular board.
+/// The quarter turns and diagonal reflections only exist on square boards.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Symmetry {
+    Identity,
+    Rotate90,
+    Rotate180,
+    Rotate270,
+    /// Mirror left and right
+    FlipX,
+    /// Mirror top and bottom
+    FlipY,
+    /// Mirror along the diagonal through player 1's corner
+    Transpose,
+    /// Mirror along the diagonal through player 2's corner
+    AntiTranspose,
+}
+
+impl Symmetry {
+    pub const N: usize = 8;
+
+    pub fn iter() -> impl Iterator<Item = Symmetry> {
+        [
+            Symmetry::Identity,
+            Symmetry::Rotate90,
+            Symmetry::Rotate180,
+            Symmetry::Rotate270,
+            Symmetry::FlipX,
+            Symmetry::FlipY,
+            Symmetry::Transpose,
+            Symmetry::AntiTranspose,
+        ]
+        .into_iter()
+    }
+
+    /// Whether the symmetry maps a `w` by `h` board onto itself
+    pub fn fits(&self, w: i8, h: i8) -> bool {
+        match self {
+            Symmetry::Identity | Symmetry::Rotate180 | Symmetry::FlipX | Symmetry::FlipY => true,
+            _ => w == h,
+        }
+    }
+
+    /// Image of a cell of a `w` by `h` board
+    pub fn apply(&self, (x, y): (i8, i8), (w, h): (i8, i8)) -> (i8, i8) {
+        match self {
+            Symmetry::Identity => (x, y),
+            Symmetry::Rotate90 => (w - 1 - y, x),
+            Symmetry::Rotate180 => (w - 1 - x, h - 1 - y),
+            Symmetry::Rotate270 => (y, h - 1 - x),
+            Symmetry::FlipX => (w - 1 - x, y),
+            Symmetry::FlipY => (x, h - 1 - y),
+            Symmetry::Transpose => (y, x),
+            Symmetry::AntiTranspose => (h - 1 - y, w - 1 - x),
+        }
+    }
+}
+
+/// Whether a symmetry maps the cells onto themselves and keeps the start corner of `player`
+fn preserves(symmetry: Symmetry, state: &State, player: &Player) -> bool {
+    let dims = (state.w(), state.h());
+    if !symmetry.fits(dims.0, dims.1) {
+        return false;
+    }
+    if symmetry == Symmetry::Identity {
+        return true;
+    }
+
+    // a player still waiting for their first move has to keep their corner,
+    // the corners of the other players are ignored
+    let corner = start_corner(player, dims.0, dims.1);
+    let corner_kept =
+        state.scores()[usize::from(player)] > 0 || symmetry.apply(corner, dims) == corner;
+
+    corner_kept
+        && (0..dims.1).all(|y| {
+            (0..dims.0).all(|x| {
+                let (sx, sy) = symmetry.apply((x, y), dims);
+                state.cell(x, y) == state.cell(sx, sy)
+            })
+        })
+}
+
+/// Symmetries the position looks like it has to `player`, always including [Symmetry::Identity].
+/// See the [module docs](self) for why this is only an approximation.
+pub fn player_symmetries(state: &State, player: &Player) -> Vec<Symmetry> {
+    Symmetry::iter()
+        .filter(|symmetry| preserves(*symmetry, state, player))
+        .collect()
+}
+
+/// Keep one move out of every group of moves that mirror each other under [player_symmetries].
+/// The first move of every group is kept, so the order of `moves` is kept.
+pub fn skip_mirrored(state: &State, player: &Player, moves: Vec<Move>) -> Vec<Move> {
+    let symmetries = player_symmetries(state, player);
+    if symmetries.len() == 1 {
+        return moves;
+    }
+
+    let dims = (state.w(), state.h());
+    let mut seen = FxHashSet::default();
+    moves
+        .into_iter()
+        .filter(|mv| {
+            let cells = state.move_cells(mv);
+            // the same cells for every move of the group
+            let key = symmetries
+                .iter()
+                .map(|symmetry| {
+                    let mut image: Vec<_> =
+                        cells.iter().map(|c| symmetry.apply(*c, dims)).collect();
+                    image.sort();
+                    image
+                })
+                .min()
+                .unwrap();
+            seen.insert(key)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_symmetries_are_permutations() {
+        for (w, h) in [(20, 20), (14, 9)] {
+            for symmetry in Symmetry::iter().filter(|s| s.fits(w, h)) {
+                let mut image: Vec<_> = (0..h)
+                    .flat_map(|y| (0..w).map(move |x| symmetry.apply((x, y), (w, h))))
+                    .collect();
+                image.sort();
+                image.dedup();
+                assert_eq!(image.len(), w as usize * h as usize);
+                assert!(image
+                    .iter()
+                    .all(|(x, y)| (0..w).contains(x) && (0..h).contains(y)));
+            }
+        }
+    }
+
+    #[test]
+    fn test_empty_board() {
+        let state = State::new(20, 20);
+        for player in Player::iter() {
+            // every player's diagonal, the other corners swap
+            let found = player_symmetries(&state, &player);
+            let diagonal = match player {
+                Player::Player1 | Player::Player3 => Symmetry::Transpose,
+                Player::Player2 | Player::Player4 => Symmetry::AntiTranspose,
+            };
+            assert_eq!(found, vec![Symmetry::Identity, diagonal]);
+        }
+    }
+
+    #[test]
+    fn test_no_exact_symmetries() {
+        // every other symmetry moves some player's start corner, so none is exact
+        for (w, h) in [(20, 20), (14, 9), (5, 5)] {
+            for symmetry in Symmetry::iter().filter(|s| s.fits(w, h)) {
+                let kept = Player::iter().all(|p| {
+                    let corner = start_corner(&p, w, h);
+                    symmetry.apply(corner, (w, h)) == corner
+                });
+                assert_eq!(kept, symmetry == Symmetry::Identity);
+            }
+        }
+    }
+
+    #[test]
+    fn test_opening_branching_factor() {
+        let state = State::new(20, 20);
+        for player in Player::iter() {
+            let moves = state.get_moves(&player);
+            let unique = skip_mirrored(&state, &player, moves.clone());
+            assert!(unique.len() < moves.len());
+            // at most every pair of mirrored moves becomes one
+            assert!(2 * unique.len() >= moves.len());
+
+            // every move mirrors a kept move
+            let symmetry = player_symmetries(&state, &player)[1];
+            let sorted = |mut cells: Vec<(i8, i8)>| {
+                cells.sort();
+                cells
+            };
+            let kept: Vec<_> = unique
+                .iter()
+                .map(|mv| sorted(state.move_cells(mv)))
+                .collect();
+            for mv in &moves {
+                let cells = state.move_cells(mv);
+                let image = cells.iter().map(|c| symmetry.apply(*c, (20, 20))).collect();
+                assert!(kept.contains(&sorted(cells)) || kept.contains(&sorted(image)));
+            }
+        }
+    }
+
+    #[test]
+    fn test_asymmetric_position() {
+        let mut state = State::new(20, 20);
+        let moves = state.get_moves(&Player::Player1);
+        // a piece off the diagonal breaks the symmetry
+        let mv = moves
+            .iter()
+            .find(|mv| {
+                let cells = state.move_cells(mv);
+                cells.iter().any(|(x, y)| !cells.contains(&(*y, *x)))
+            })
+            .unwrap()
+            .clone();
+        state.place_piece(&mv);
+        assert_eq!(
+            player_symmetries(&state, &Player::Player1),
+            vec![Symmetry::Identity]
+        );
+    }
+}
@@ -274,6 +274,106 @@ impl From<&Player> for usize {
     }
 }
 
+/// Whose turn it is and which players have passed.
+/// A player without moves never gets any back, so a pass lasts until the end of the game.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Turn {
+    pub to_move: Player,
+    pub passed: [bool; Player::N],
+}
+
+impl Default for Turn {
+    fn default() -> Self {
+        Self {
+            to_move: Player::Player1,
+            passed: [false; Player::N],
+        }
+    }
+}
+
+/// Base shape of a piece, ignoring rotations and reflections.
+/// Shapes are named after the letter they resemble, followed by their size.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Debug)]
+pub enum Shape {
+    I1,
+    I2,
+    V3,
+    I3,
+    I4,
+    L4,
+    Z4,
+    O4,
+    T4,
+    F5,
+    I5,
+    L5,
+    N5,
+    P5,
+    T5,
+    U5,
+    V5,
+    W5,
+    X5,
+    Y5,
+    Z5,
+}
+
+impl Shape {
+    pub const N: usize = 21;
+
+    #[inline]
+    pub fn iter() -> impl Iterator<Item = Shape> {
+        (0..Shape::N).map(Shape::from)
+    }
+
+    /// Number of tiles in the shape
+    #[inline]
+    pub const fn size(&self) -> u8 {
+        match self {
+            Shape::I1 => 1,
+            Shape::I2 => 2,
+            Shape::V3 | Shape::I3 => 3,
+            Shape::I4 | Shape::L4 | Shape::Z4 | Shape::O4 | Shape::T4 => 4,
+            _ => 5,
+        }
+    }
+}
+
+impl From<usize> for Shape {
+    fn from(i: usize) -> Self {
+        match i {
+            0 => Shape::I1,
+            1 => Shape::I2,
+            2 => Shape::V3,
+            3 => Shape::I3,
+            4 => Shape::I4,
+            5 => Shape::L4,
+            6 => Shape::Z4,
+            7 => Shape::O4,
+            8 => Shape::T4,
+            9 => Shape::F5,
+            10 => Shape::I5,
+            11 => Shape::L5,
+            12 => Shape::N5,
+            13 => Shape::P5,
+            14 => Shape::T5,
+            15 => Shape::U5,
+            16 => Shape::V5,
+            17 => Shape::W5,
+            18 => Shape::X5,
+            19 => Shape::Y5,
+            20 => Shape::Z5,
+            _ => panic!("Invalid shape"),
+        }
+    }
+}
+
+impl From<Shape> for usize {
+    fn from(value: Shape) -> Self {
+        value as usize
+    }
+}
+
 #[derive(Clone, Hash)]
 /// Represents the rotation of a piece.
 pub enum Rotation {
@@ -4,4 +4,4 @@ mod state;
 
 pub use mask::Mask;
 pub use piece::{Piece, TransformedPiece};
-pub use state::{piece_dims, piece_size, Move, State, PIECES};
+pub use state::{Move, State, PIECES};
@@ -157,17 +157,23 @@ impl Debug for TransformedPiece {
 pub struct Piece {
     /// The different unique versions of the piece.
     pub versions: Vec<TransformedPiece>,
+    /// Number of tiles in the piece
+    pub size: u8,
 }
 
 impl Piece {
     pub fn new(mask: Mask) -> Self {
+        let size = (0..mask.w())
+            .flat_map(|x| (0..mask.h()).map(move |y| (x, y)))
+            .filter(|(x, y)| mask.get(*x, *y) != Some(0))
+            .count() as u8;
         let versions: Vec<_> = Transformation::iter()
             .map(|transformation| TransformedPiece::new(transform(transformation, &mask)))
             .collect::<FxHashSet<TransformedPiece>>()
             .into_iter()
             .collect();
 
-        Self { versions }
+        Self { versions, size }
     }
 }
 
@@ -1,14 +1,22 @@
 use super::{Mask, Piece};
 use crate::game::{
+    legality::check_placement,
     utils::{PieceID, PieceTransformID},
-    Corner, Dimensioned, Player,
+    Corner, Dimensioned, IllegalMove, PieceSet, Player,
 };
 use colored::{Color, Colorize};
 use core::panic;
 use itertools::Itertools;
 use once_cell::sync::Lazy;
+use rand::{
+    rngs::ThreadRng,
+    seq::{IteratorRandom, SliceRandom},
+};
 use rustc_hash::FxHashSet;
-use std::fmt::{Debug, Display};
+use std::{
+    fmt::{Debug, Display},
+    sync::Arc,
+};
 
 static NEIGHBOR_MASKS: Lazy<[Mask; 4]> = Lazy::new(|| {
     [
@@ -19,35 +27,33 @@ static NEIGHBOR_MASKS: Lazy<[Mask; 4]> = Lazy::new(|| {
     ]
 });
 
-pub static PIECES: Lazy<[Vec<Piece>; 4]> = Lazy::new(|| {
-    let blocks = vec![
-        // 1 tile
-        Mask::new(1, vec![0x1]),
-        // 2 tiles
-        Mask::new(2, vec![0x11]),
-        // 3 tiles
-        Mask::new(2, vec![0x11, 0x01]),
-        Mask::new(3, vec![0x111]),
-        // 4 tiles
-        Mask::new(4, vec![0x1111]),
-        Mask::new(3, vec![0x111, 0x001]),
-        Mask::new(3, vec![0x110, 0x011]),
-        Mask::new(2, vec![0x11, 0x11]),
-        Mask::new(3, vec![0x111, 0x010]),
-        // 5 tiles
-        Mask::new(3, vec![0x011, 0x110, 0x010]),
-        Mask::new(5, vec![0x11111]),
-        Mask::new(4, vec![0x1111, 0x1000]),
-        Mask::new(4, vec![0x0111, 0x1100]),
-        Mask::new(3, vec![0x111, 0x110]),
-        Mask::new(3, vec![0x111, 0x010, 0x010]),
-        Mask::new(3, vec![0x111, 0x101]),
-        Mask::new(3, vec![0x111, 0x100, 0x100]),
-        Mask::new(3, vec![0x001, 0x011, 0x110]),
-        Mask::new(3, vec![0x010, 0x111, 0x010]),
-        Mask::new(4, vec![0x1111, 0x0100]),
-        Mask::new(3, vec![0x110, 0x010, 0x011]),
-    ];
+/// The pieces of every player
+type PieceTable = [Vec<Piece>; Player::N];
+
+/// The standard set, shared by every game that doesn't use a custom one
+static STANDARD: Lazy<Arc<PieceSet>> = Lazy::new(|| Arc::new(PieceSet::standard()));
+
+/// Pieces of the standard set, shared by every game that doesn't use a custom one
+pub static PIECES: Lazy<Arc<PieceTable>> = Lazy::new(|| Arc::new(piece_table(&STANDARD)));
+
+/// Build the pieces of every player from a set of shapes
+fn piece_table(set: &PieceSet) -> PieceTable {
+    let blocks: Vec<_> = set
+        .shapes()
+        .iter()
+        .map(|shape| {
+            let rows = shape
+                .rows()
+                .into_iter()
+                .map(|row| {
+                    (0..shape.w())
+                        .filter(|x| row >> x & 1 != 0)
+                        .fold(0, |mask, x| mask | 1 << (x * 4))
+                })
+                .collect();
+            Mask::new(shape.w(), rows)
+        })
+        .collect();
 
     // Uses a hack to generate the pieces for all 4 players.
     // Given a piece that looks like
@@ -59,49 +65,22 @@ pub static PIECES: Lazy<[Vec<Piece>; 4]> = Lazy::new(|| {
     // 222
     // which is the same piece for player 2.
     // This is done for all 4 players.
-
-    [
-        blocks.clone().into_iter().map(Piece::new).collect(),
-        blocks
-            .clone()
-            .into_iter()
-            .map(|block| block << 1)
-            .map(Piece::new)
-            .collect(),
-        blocks
-            .clone()
-            .into_iter()
-            .map(|block| block << 2)
-            .map(Piece::new)
-            .collect(),
+    std::array::from_fn(|player| {
         blocks
-            .into_iter()
-            .map(|block| block << 3)
+            .iter()
+            .cloned()
+            .map(|block| block << player)
             .map(Piece::new)
-            .collect(),
-    ]
-});
-
-/// Abstracted function to get size from a piece given a move
-/// Useful for algorithms
-#[inline]
-pub fn piece_size(mv: &Move) -> u8 {
-    match usize::from(mv.piece.piece) {
-        0 => 1,
-        1 => 2,
-        2..=3 => 3,
-        4..=9 => 4,
-        10..=21 => 5,
-        _ => unreachable!(),
-    }
+            .collect()
+    })
 }
 
-/// Abstracted function to get dimensions of a piece given a move
-#[inline]
-pub fn piece_dims(mv: &Move) -> (u8, u8) {
-    let PieceTransformID { piece, version } = mv.piece;
-    let p = &PIECES[0][usize::from(piece)].versions[version];
-    (p.w() as u8, p.h() as u8)
+/// Cells covered by a piece mask placed at the origin
+fn mask_cells(mask: &Mask) -> Vec<(i8, i8)> {
+    (0..mask.h())
+        .flat_map(|y| (0..mask.w()).map(move |x| (x, y)))
+        .filter(|(x, y)| mask.get(*x, *y) != Some(0))
+        .collect()
 }
 
 /// A move.
@@ -128,6 +107,10 @@ impl From<Move> for (PieceTransformID, (i8, i8)) {
 #[derive(Clone)]
 pub struct State {
     board: Mask,
+    /// Shapes the game is played with
+    set: Arc<PieceSet>,
+    /// Pieces the game is played with
+    pieces: Arc<PieceTable>,
     /// Corners for every player
     /// separated by corner direction
     corners: [[FxHashSet<(i8, i8)>; Corner::N]; Player::N],
@@ -139,6 +122,15 @@ pub struct State {
 
 impl State {
     pub fn new(w: i8, h: i8) -> Self {
+        Self::with_table(w, h, STANDARD.clone(), PIECES.clone())
+    }
+
+    /// Create a game where every player starts with the shapes of a custom piece set
+    pub fn with_pieces(w: i8, h: i8, set: &PieceSet) -> Self {
+        Self::with_table(w, h, Arc::new(set.clone()), Arc::new(piece_table(set)))
+    }
+
+    fn with_table(w: i8, h: i8, set: Arc<PieceSet>, pieces: Arc<PieceTable>) -> Self {
         let mut corners: [[FxHashSet<(i8, i8)>; Corner::N]; Player::N] =
             std::array::from_fn(|_| std::array::from_fn(|_| FxHashSet::default()));
 
@@ -151,10 +143,12 @@ impl State {
         // Fourth player starts at the (0, h - 1) corner
         corners[usize::from(Player::Player4)][Corner::PosNeg as usize].insert((0, h - 1));
 
-        let player_pieces = std::array::from_fn(|i| vec![true; PIECES[i].len()]);
+        let player_pieces = std::array::from_fn(|i| vec![true; pieces[i].len()]);
 
         Self {
             board: Mask::new(w, vec![0; h as usize]),
+            set,
+            pieces,
             corners,
             player_pieces,
             scores: [0; Player::N],
@@ -165,6 +159,70 @@ impl State {
         &self.scores
     }
 
+    /// Shapes the game is played with
+    pub fn piece_set(&self) -> &PieceSet {
+        &self.set
+    }
+
+    /// Get the pieces a player still has in hand, as indices into [State::piece_set]
+    pub fn remaining_pieces(&self, player: &Player) -> Vec<usize> {
+        self.player_pieces[usize::from(player)]
+            .iter()
+            .enumerate()
+            .filter(|(_, v)| **v)
+            .map(|(piece, _)| piece)
+            .collect()
+    }
+
+    /// Index into [State::piece_set] of the piece a move places
+    #[inline]
+    pub fn move_piece(&self, mv: &Move) -> usize {
+        usize::from(mv.piece.piece)
+    }
+
+    /// Number of tiles a move covers
+    #[inline]
+    pub fn move_size(&self, mv: &Move) -> u8 {
+        self.set.shapes()[self.move_piece(mv)].size() as u8
+    }
+
+    /// Width and height of the piece a move places
+    #[inline]
+    pub fn move_dims(&self, mv: &Move) -> (u8, u8) {
+        let PieceTransformID { piece, version } = mv.piece;
+        let p = &self.pieces[usize::from(mv.player)][usize::from(piece)].versions[version];
+        (p.w() as u8, p.h() as u8)
+    }
+
+    /// Get the player whose piece covers a cell, if any
+    pub fn cell(&self, x: i8, y: i8) -> Option<Player> {
+        let cell = self.board.get_i8(x, y)?;
+        Player::iter().find(|p| p.mask() == cell)
+    }
+
+    /// Whether a player could cover a cell with one of their pieces,
+    /// I.E. the cell is in bounds, empty and not next to the player's own color
+    pub fn is_free(&self, player: &Player, (x, y): (i8, i8)) -> bool {
+        x >= 0
+            && y >= 0
+            && x < self.w()
+            && y < self.h()
+            && self
+                .board
+                .no_overlap(&NEIGHBOR_MASKS[usize::from(player)], (x - 1, y - 1))
+    }
+
+    /// Get the cells a player can currently start a piece from
+    pub fn corners(&self, player: &Player) -> Vec<(i8, i8)> {
+        self.corners[usize::from(player)]
+            .iter()
+            .flatten()
+            .copied()
+            .sorted()
+            .dedup()
+            .collect()
+    }
+
     fn get_moves_for_piece<'a>(
         &'a self,
         player: &Player,
@@ -176,7 +234,7 @@ impl State {
             "Attempted to play a piece that the player doesn't have."
         );
 
-        PIECES[player][usize::from(piece)]
+        self.pieces[player][usize::from(piece)]
             .versions
             .iter()
             .enumerate()
@@ -214,9 +272,59 @@ impl State {
             })
     }
 
+    /// Check whether a move follows the rules
+    pub fn is_legal(&self, mv: &Move) -> Result<(), IllegalMove> {
+        let player = usize::from(mv.player);
+        let PieceTransformID { piece, version } = mv.piece;
+        let Some(transformed) = self.pieces[player]
+            .get(usize::from(piece))
+            .and_then(|piece| piece.versions.get(version))
+        else {
+            return Err(IllegalMove::UnknownPiece);
+        };
+        if !self.player_pieces[player][usize::from(piece)] {
+            return Err(IllegalMove::PieceUsed);
+        }
+
+        check_placement(
+            &mv.player,
+            &mask_cells(&transformed.mask),
+            mv.pos,
+            (self.w(), self.h()),
+            self.scores[player] == 0,
+            |x, y| self.cell(x, y),
+        )
+    }
+
+    /// Board cells covered by a move
+    pub fn move_cells(&self, mv: &Move) -> Vec<(i8, i8)> {
+        let PieceTransformID { piece, version } = mv.piece;
+        let transformed =
+            &self.pieces[usize::from(mv.player)][usize::from(piece)].versions[version];
+        mask_cells(&transformed.mask)
+            .into_iter()
+            .map(|(x, y)| (mv.pos.0 + x, mv.pos.1 + y))
+            .collect()
+    }
+
+    /// Place a piece if the move follows the rules
+    pub fn try_place_piece(&mut self, mv: &Move) -> Result<(), IllegalMove> {
+        self.is_legal(mv)?;
+        self.place_piece(mv);
+        Ok(())
+    }
+
     /// Get the possible moves for a player
     pub fn get_moves<'a>(&'a self, player: &'a Player) -> Vec<Move> {
-        let mut pieces: [bool; 400 * 21 * 8] = [false; 400 * 21 * 8];
+        self.unique_moves(player).collect()
+    }
+
+    /// Generate the possible moves for a player one at a time, skipping duplicates.
+    /// A placement can be found from several corners, so a table of the ones seen is kept.
+    fn unique_moves<'a>(&'a self, player: &'a Player) -> impl Iterator<Item = Move> + 'a {
+        let (w, h) = (self.w() as usize, self.h() as usize);
+        let n = self.pieces[0].len();
+        let mut pieces = vec![false; w * h * n * 8];
         // All the different piece transforms for the player
         self.player_pieces[usize::from(player)]
             .iter()
@@ -224,26 +332,59 @@ impl State {
             .filter(|(_, v)| **v)
             .flat_map(move |(piece, _)| self.get_moves_for_piece(player, PieceID::from(piece)))
             // faster way to filter only unique moves
-            .filter(|m| {
+            .filter(move |m| {
                 let piece = usize::from(m.piece.piece);
                 let ver = m.piece.version;
                 let (x, y) = m.pos;
                 let x = x as usize;
                 let y = y as usize;
-                let uid = ((x * 20 + y) * 21 + piece) * 8 + ver;
+                let uid = ((y * w + x) * n + piece) * 8 + ver;
                 let seen = pieces[uid];
                 pieces[uid] = true;
                 !seen
             })
-            .collect()
+    }
+
+    /// Count the possible moves for a player without collecting them.
+    /// Every placement is still generated, this only saves building the list.
+    pub fn count_moves(&self, player: &Player) -> usize {
+        self.unique_moves(player).count()
+    }
+
+    /// Count the possible moves for a player with each piece of [State::piece_set]
+    pub fn count_moves_by_piece(&self, player: &Player) -> Vec<usize> {
+        self.unique_moves(player)
+            .fold(vec![0; self.set.shapes().len()], |mut counts, mv| {
+                counts[self.move_piece(&mv)] += 1;
+                counts
+            })
+    }
+
+    /// Sample a random legal move for a player without generating all of them.
+    /// Pieces are tried in a random order and a random placement of the first one that fits
+    /// is returned, so every piece that fits is equally likely however many orientations it has,
+    /// and moves are not uniform.
+    /// Returns [None] if the player has no moves.
+    pub fn random_move(&self, rng: &mut ThreadRng, player: &Player) -> Option<Move> {
+        let mut pieces: Vec<_> = self.player_pieces[usize::from(player)]
+            .iter()
+            .enumerate()
+            .filter(|(_, v)| **v)
+            .map(|(piece, _)| PieceID::from(piece))
+            .collect();
+        pieces.shuffle(rng);
+
+        pieces
+            .into_iter()
+            .find_map(|piece| self.get_moves_for_piece(player, piece).choose(rng))
     }
 
     /// Place a piece on the board
     pub fn place_piece(&mut self, mv: &Move) {
         let Move { player, piece, pos } = *mv;
 
-        let transformed_piece =
-            &PIECES[usize::from(player)][usize::from(piece.piece)].versions[piece.version];
+        let placed = &self.pieces[usize::from(player)][usize::from(piece.piece)];
+        let transformed_piece = &placed.versions[piece.version];
 
         let (x, y) = pos;
 
@@ -258,7 +399,7 @@ impl State {
         self.board = self.board.or(&transformed_piece.mask, (x, y));
         // Remove the piece from the player's pieces
         self.player_pieces[usize::from(player)][usize::from(piece.piece)] = false;
-        self.scores[usize::from(player)] += piece_size(mv);
+        self.scores[usize::from(player)] += placed.size;
 
         // Update the corners
         for (x, y, v) in transformed_piece.tile_iter() {
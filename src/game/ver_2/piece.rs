@@ -1,8 +1,11 @@
-use crate::game::{ver_2::utils::ymm, Corner, Neighbor};
-use std::{arch::x86_64::*, fmt::Debug};
+use crate::game::{
+    ver_2::utils::{rows, ymm, Ymm},
+    Corner, Neighbor,
+};
+use std::fmt::Debug;
 
 /// Struct representing a piece
-/// All masks in this piece are stored as an __m256i, meaning
+/// All masks in this piece are stored as a Ymm, meaning
 /// they can store 32 * 8 bits, or 8 rows of mask information.
 pub struct Piece {
     /// Width of the piece
@@ -15,7 +18,7 @@ pub struct Piece {
     /// used to remove pieces from the player's hand
     pub id_mask: u128,
     /// Bitmask of the piece
-    pub occupied_mask: __m256i,
+    pub occupied_mask: Ymm,
     // /// Say we have a piece that looks like
     // /// ```str
     // /// XX
@@ -131,38 +134,34 @@ impl Piece {
         //     }
         // }
 
-        unsafe {
-            Self {
-                id_mask,
-                width,
-                height,
-                occupied_mask: ymm(piece),
-                corner_mask,
-                // repeated_mask: ymm(repeated_piece),
-                neighbor_mask,
-            }
+        Self {
+            id_mask,
+            width,
+            height,
+            occupied_mask: ymm(piece),
+            corner_mask,
+            // repeated_mask: ymm(repeated_piece),
+            neighbor_mask,
         }
     }
 }
 
 impl Debug for Piece {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        unsafe {
-            let rows = std::mem::transmute::<__m256i, [u32; 8]>(self.occupied_mask);
-            writeln!(f, "Occupied Mask:")?;
-            for x in rows.iter() {
-                writeln!(f, "{:032b}", x)?;
-            }
-            let rows = self.corner_mask;
-            writeln!(f, "Corner Mask:")?;
-            for x in rows.iter() {
-                writeln!(f, "{:032b}", x)?;
-            }
-            writeln!(f, "Neighbor Mask:")?;
-            let rows = self.neighbor_mask;
-            for x in rows.iter() {
-                writeln!(f, "{:032b}", x)?;
-            }
+        let rows = rows(self.occupied_mask);
+        writeln!(f, "Occupied Mask:")?;
+        for x in rows.iter() {
+            writeln!(f, "{:032b}", x)?;
+        }
+        let rows = self.corner_mask;
+        writeln!(f, "Corner Mask:")?;
+        for x in rows.iter() {
+            writeln!(f, "{:032b}", x)?;
+        }
+        writeln!(f, "Neighbor Mask:")?;
+        let rows = self.neighbor_mask;
+        for x in rows.iter() {
+            writeln!(f, "{:032b}", x)?;
         }
         Ok(())
     }
@@ -170,16 +169,6 @@ impl Debug for Piece {
 
 #[cfg(test)]
 mod tests {
-    use crate::game::ver_2::utils::ymm;
-
-    unsafe fn ymm_eq(a: __m256i, b: __m256i) -> bool {
-        // Fills mask with 1 if a and b are equal
-        let mask = _mm256_cmpeq_epi32(a, b);
-        // Take the most significant bit from each 8 bit chunk
-        let ret = _mm256_movemask_epi8(mask);
-        ret == -1
-    }
-
     use super::*;
     #[test]
     fn test_occupied_mask() {
@@ -188,7 +177,7 @@ mod tests {
 
         let expected = [0b110, 0b010, 0b011, 0, 0, 0, 0, 0];
 
-        assert!(unsafe { ymm_eq(piece.occupied_mask, ymm(expected),) });
+        assert_eq!(rows(piece.occupied_mask), expected);
     }
 
     // #[test]
@@ -216,6 +205,6 @@ mod tests {
 
         let expected = [0b01100, 0b11110, 0b01110, 0b01111, 0b00110, 0, 0, 0];
 
-        assert!(unsafe { ymm_eq(ymm(piece.neighbor_mask), ymm(expected),) });
+        assert_eq!(piece.neighbor_mask, expected);
     }
 }
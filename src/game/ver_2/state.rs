@@ -1,160 +1,33 @@
 use super::{
-    utils::{rotate_down_1, shift_left_1},
+    utils::{load, rotate_down_1, rows, shift_left_1, testz, Ymm},
     Piece,
 };
-use crate::game::Player;
+use crate::game::{Dimensioned, PieceSet, Player};
 use colored::Colorize;
 use once_cell::sync::Lazy;
 use std::{
-    arch::x86_64::*,
+    array,
     cmp::min,
     fmt::{Debug, Display},
 };
 
 const PIECE_COUNT: usize = 91;
 
-pub static PIECES: Lazy<[Piece; PIECE_COUNT]> = Lazy::new(|| {
-    [
-        // 1 tile pieces
-        // X - no transformations
-        Piece::new(1, 1, 0b1, [0b1, 0, 0, 0, 0, 0, 0, 0]),
-        // 2 tile pieces
-        // XX - 2 states
-        Piece::new(2, 1, 0b11 << 1, [0b11, 0, 0, 0, 0, 0, 0, 0]),
-        Piece::new(1, 2, 0b11 << 1, [0b1, 0b1, 0, 0, 0, 0, 0, 0]),
-        // 3 tile pieces
-        // XXX - 2 states
-        Piece::new(3, 1, 0b11 << 3, [0b111, 0, 0, 0, 0, 0, 0, 0]),
-        Piece::new(1, 3, 0b11 << 3, [0b1, 0b1, 0b1, 0, 0, 0, 0, 0]),
-        // XX
-        // X  - 4 states
-        Piece::new(2, 2, 0b1111 << 5, [0b11, 0b1, 0, 0, 0, 0, 0, 0]),
-        Piece::new(2, 2, 0b1111 << 5, [0b11, 0b10, 0, 0, 0, 0, 0, 0]),
-        Piece::new(2, 2, 0b1111 << 5, [0b1, 0b11, 0, 0, 0, 0, 0, 0]),
-        Piece::new(2, 2, 0b1111 << 5, [0b10, 0b11, 0, 0, 0, 0, 0, 0]),
-        // 4 tile pieces
-        // XXXX - 2 states
-        Piece::new(4, 1, 0b11 << 9, [0b1111, 0, 0, 0, 0, 0, 0, 0]),
-        Piece::new(1, 4, 0b11 << 9, [0b1, 0b1, 0b1, 0b1, 0, 0, 0, 0]),
-        // XXX
-        // X   - 8 states
-        Piece::new(3, 2, 0b11111111 << 11, [0b111, 0b1, 0, 0, 0, 0, 0, 0]),
-        Piece::new(3, 2, 0b11111111 << 11, [0b111, 0b100, 0, 0, 0, 0, 0, 0]),
-        Piece::new(3, 2, 0b11111111 << 11, [0b1, 0b111, 0, 0, 0, 0, 0, 0]),
-        Piece::new(3, 2, 0b11111111 << 11, [0b100, 0b111, 0, 0, 0, 0, 0, 0]),
-        Piece::new(2, 3, 0b11111111 << 11, [0b11, 0b10, 0b10, 0, 0, 0, 0, 0]),
-        Piece::new(2, 3, 0b11111111 << 11, [0b11, 0b1, 0b1, 0, 0, 0, 0, 0]),
-        Piece::new(2, 3, 0b11111111 << 11, [0b1, 0b1, 0b11, 0, 0, 0, 0, 0]),
-        Piece::new(2, 3, 0b11111111 << 11, [0b10, 0b10, 0b11, 0, 0, 0, 0, 0]),
-        // XX
-        //  XX - 4 states
-        Piece::new(3, 2, 0b1111 << 19, [0b110, 0b011, 0, 0, 0, 0, 0, 0]),
-        Piece::new(3, 2, 0b1111 << 19, [0b011, 0b110, 0, 0, 0, 0, 0, 0]),
-        Piece::new(2, 3, 0b1111 << 19, [0b10, 0b11, 0b01, 0, 0, 0, 0, 0]),
-        Piece::new(2, 3, 0b1111 << 19, [0b01, 0b11, 0b10, 0, 0, 0, 0, 0]),
-        // XXX
-        //  X - 4 states
-        Piece::new(3, 2, 0b1111 << 23, [0b111, 0b010, 0, 0, 0, 0, 0, 0]),
-        Piece::new(3, 2, 0b1111 << 23, [0b010, 0b111, 0, 0, 0, 0, 0, 0]),
-        Piece::new(2, 3, 0b1111 << 23, [0b10, 0b11, 0b10, 0, 0, 0, 0, 0]),
-        Piece::new(2, 3, 0b1111 << 23, [0b01, 0b11, 0b01, 0, 0, 0, 0, 0]),
-        // XX
-        // XX - 1 state
-        Piece::new(2, 2, 0b1 << 27, [0b11, 0b11, 0, 0, 0, 0, 0, 0]),
-        // 5 tile pieces
-        // XXXXX - 2 states
-        Piece::new(5, 1, 0b11 << 28, [0b11111, 0, 0, 0, 0, 0, 0, 0]),
-        Piece::new(1, 5, 0b11 << 28, [0b1, 0b1, 0b1, 0b1, 0b1, 0, 0, 0]),
-        //  XX
-        // XX
-        //  X - 8 states
-        Piece::new(3, 3, 0b11111111 << 30, [0b110, 0b011, 0b010, 0, 0, 0, 0, 0]),
-        Piece::new(3, 3, 0b11111111 << 30, [0b011, 0b110, 0b010, 0, 0, 0, 0, 0]),
-        Piece::new(3, 3, 0b11111111 << 30, [0b010, 0b110, 0b011, 0, 0, 0, 0, 0]),
-        Piece::new(3, 3, 0b11111111 << 30, [0b010, 0b011, 0b110, 0, 0, 0, 0, 0]),
-        Piece::new(3, 3, 0b11111111 << 30, [0b010, 0b111, 0b001, 0, 0, 0, 0, 0]),
-        Piece::new(3, 3, 0b11111111 << 30, [0b001, 0b111, 0b010, 0, 0, 0, 0, 0]),
-        Piece::new(3, 3, 0b11111111 << 30, [0b010, 0b111, 0b100, 0, 0, 0, 0, 0]),
-        Piece::new(3, 3, 0b11111111 << 30, [0b100, 0b111, 0b010, 0, 0, 0, 0, 0]),
-        // XXXX
-        // X    - 8 states
-        Piece::new(4, 2, 0b11111111 << 38, [0b1111, 0b0001, 0, 0, 0, 0, 0, 0]),
-        Piece::new(4, 2, 0b11111111 << 38, [0b1111, 0b1000, 0, 0, 0, 0, 0, 0]),
-        Piece::new(4, 2, 0b11111111 << 38, [0b1000, 0b1111, 0, 0, 0, 0, 0, 0]),
-        Piece::new(4, 2, 0b11111111 << 38, [0b0001, 0b1111, 0, 0, 0, 0, 0, 0]),
-        Piece::new(2, 4, 0b11111111 << 38, [0b11, 0b10, 0b10, 0b10, 0, 0, 0, 0]),
-        Piece::new(2, 4, 0b11111111 << 38, [0b11, 0b01, 0b01, 0b01, 0, 0, 0, 0]),
-        Piece::new(2, 4, 0b11111111 << 38, [0b10, 0b10, 0b10, 0b11, 0, 0, 0, 0]),
-        Piece::new(2, 4, 0b11111111 << 38, [0b01, 0b01, 0b01, 0b11, 0, 0, 0, 0]),
-        // XXX
-        //   XX - 8 states
-        Piece::new(4, 2, 0b11111111 << 46, [0b1110, 0b0011, 0b0, 0, 0, 0, 0, 0]),
-        Piece::new(4, 2, 0b11111111 << 46, [0b0111, 0b1100, 0b0, 0, 0, 0, 0, 0]),
-        Piece::new(4, 2, 0b11111111 << 46, [0b0011, 0b1110, 0b0, 0, 0, 0, 0, 0]),
-        Piece::new(4, 2, 0b11111111 << 46, [0b1100, 0b0111, 0b0, 0, 0, 0, 0, 0]),
-        Piece::new(2, 4, 0b11111111 << 46, [0b10, 0b10, 0b11, 0b01, 0, 0, 0, 0]),
-        Piece::new(2, 4, 0b11111111 << 46, [0b01, 0b01, 0b11, 0b10, 0, 0, 0, 0]),
-        Piece::new(2, 4, 0b11111111 << 46, [0b10, 0b11, 0b01, 0b01, 0, 0, 0, 0]),
-        Piece::new(2, 4, 0b11111111 << 46, [0b01, 0b11, 0b10, 0b10, 0, 0, 0, 0]),
-        // XX
-        // XXX - 8 states
-        Piece::new(3, 2, 0b11111111 << 54, [0b110, 0b111, 0, 0, 0, 0, 0, 0]),
-        Piece::new(3, 2, 0b11111111 << 54, [0b111, 0b110, 0, 0, 0, 0, 0, 0]),
-        Piece::new(3, 2, 0b11111111 << 54, [0b011, 0b111, 0, 0, 0, 0, 0, 0]),
-        Piece::new(3, 2, 0b11111111 << 54, [0b111, 0b011, 0, 0, 0, 0, 0, 0]),
-        Piece::new(2, 3, 0b11111111 << 54, [0b11, 0b11, 0b01, 0, 0, 0, 0, 0]),
-        Piece::new(2, 3, 0b11111111 << 54, [0b11, 0b11, 0b10, 0, 0, 0, 0, 0]),
-        Piece::new(2, 3, 0b11111111 << 54, [0b01, 0b11, 0b11, 0, 0, 0, 0, 0]),
-        Piece::new(2, 3, 0b11111111 << 54, [0b10, 0b11, 0b11, 0, 0, 0, 0, 0]),
-        // XXX
-        //  X
-        //  X - 4 states
-        Piece::new(3, 3, 0b1111 << 62, [0b111, 0b010, 0b010, 0, 0, 0, 0, 0]),
-        Piece::new(3, 3, 0b1111 << 62, [0b010, 0b010, 0b111, 0, 0, 0, 0, 0]),
-        Piece::new(3, 3, 0b1111 << 62, [0b001, 0b111, 0b001, 0, 0, 0, 0, 0]),
-        Piece::new(3, 3, 0b1111 << 62, [0b100, 0b111, 0b100, 0, 0, 0, 0, 0]),
-        // X X
-        // XXX - 4 states
-        Piece::new(3, 2, 0b1111 << 66, [0b101, 0b111, 0, 0, 0, 0, 0, 0]),
-        Piece::new(3, 2, 0b1111 << 66, [0b111, 0b101, 0, 0, 0, 0, 0, 0]),
-        Piece::new(2, 3, 0b1111 << 66, [0b11, 0b10, 0b11, 0, 0, 0, 0, 0]),
-        Piece::new(2, 3, 0b1111 << 66, [0b11, 0b01, 0b11, 0, 0, 0, 0, 0]),
-        // X
-        // X
-        // XXX - 4 states
-        Piece::new(3, 3, 0b1111 << 70, [0b100, 0b100, 0b111, 0, 0, 0, 0, 0]),
-        Piece::new(3, 3, 0b1111 << 70, [0b001, 0b001, 0b111, 0, 0, 0, 0, 0]),
-        Piece::new(3, 3, 0b1111 << 70, [0b111, 0b100, 0b100, 0, 0, 0, 0, 0]),
-        Piece::new(3, 3, 0b1111 << 70, [0b111, 0b001, 0b001, 0, 0, 0, 0, 0]),
-        // X
-        // XX
-        //  XX - 4 states
-        Piece::new(3, 3, 0b1111 << 74, [0b100, 0b110, 0b011, 0, 0, 0, 0, 0]),
-        Piece::new(3, 3, 0b1111 << 74, [0b011, 0b110, 0b100, 0, 0, 0, 0, 0]),
-        Piece::new(3, 3, 0b1111 << 74, [0b001, 0b011, 0b110, 0, 0, 0, 0, 0]),
-        Piece::new(3, 3, 0b1111 << 74, [0b110, 0b011, 0b001, 0, 0, 0, 0, 0]),
-        //  X
-        // XXX
-        //  X  - 1 state
-        Piece::new(3, 3, 0b1 << 78, [0b010, 0b111, 0b010, 0, 0, 0, 0, 0]),
-        //   X
-        // XXXX - 8 states
-        Piece::new(4, 2, 0b11111111 << 79, [0b1111, 0b0010, 0, 0, 0, 0, 0, 0]),
-        Piece::new(4, 2, 0b11111111 << 79, [0b1111, 0b0100, 0, 0, 0, 0, 0, 0]),
-        Piece::new(4, 2, 0b11111111 << 79, [0b0010, 0b1111, 0, 0, 0, 0, 0, 0]),
-        Piece::new(4, 2, 0b11111111 << 79, [0b0100, 0b1111, 0, 0, 0, 0, 0, 0]),
-        Piece::new(2, 4, 0b11111111 << 79, [0b01, 0b01, 0b11, 0b01, 0, 0, 0, 0]),
-        Piece::new(2, 4, 0b11111111 << 79, [0b01, 0b11, 0b01, 0b01, 0, 0, 0, 0]),
-        Piece::new(2, 4, 0b11111111 << 79, [0b10, 0b10, 0b11, 0b10, 0, 0, 0, 0]),
-        Piece::new(2, 4, 0b11111111 << 79, [0b10, 0b11, 0b10, 0b10, 0, 0, 0, 0]),
-        // XX
-        //  X
-        //  XX - 4 states
-        Piece::new(3, 3, 0b1111 << 87, [0b110, 0b010, 0b011, 0, 0, 0, 0, 0]),
-        Piece::new(3, 3, 0b1111 << 87, [0b011, 0b010, 0b110, 0, 0, 0, 0, 0]),
-        Piece::new(3, 3, 0b1111 << 87, [0b001, 0b111, 0b100, 0, 0, 0, 0, 0]),
-        Piece::new(3, 3, 0b1111 << 87, [0b100, 0b111, 0b001, 0, 0, 0, 0, 0]),
-    ]
+/// Every transformation of the standard pieces, grouped by shape
+pub static PIECES: Lazy<Vec<Piece>> = Lazy::new(|| {
+    let mut pieces = Vec::with_capacity(PIECE_COUNT);
+    for shape in PieceSet::standard().shapes() {
+        let versions = shape.transformations();
+        // The player has every version of a shape until one of them is placed
+        let id_mask = ((1 << versions.len()) - 1) << pieces.len();
+        for version in versions {
+            let rows = version.rows();
+            let rows = array::from_fn(|y| rows.get(y).copied().unwrap_or(0));
+            pieces.push(Piece::new(version.w(), version.h(), id_mask, rows));
+        }
+    }
+    assert_eq!(pieces.len(), PIECE_COUNT);
+    pieces
 });
 
 /// A move.
@@ -188,7 +61,7 @@ pub struct State {
     player_pieces: [u128; Player::N],
 }
 
-type Checker = (__m256i, __m256i, __m256i);
+type Checker = (Ymm, Ymm, Ymm);
 
 impl State {
     pub fn new(w: usize, h: usize) -> Self {
@@ -207,14 +80,14 @@ impl State {
                 board
             },
             // Player 2 gets
-            // 0b0000...0000
+            // 0b1000...0000
             // 0b0000...0000
             //    :   :   :
             // 0b0000...0000
-            // 0b0000...0001
+            // 0b0000...0000
             {
                 let mut board = [0; 20];
-                board[19] = 1;
+                board[0] = 1 << 19;
                 board
             },
             // Player 3 gets
@@ -229,14 +102,14 @@ impl State {
                 board
             },
             // Player 4 gets
-            // 0b1000...0000
             // 0b0000...0000
-            //    :   :   :
             // 0b0000...0000
+            //    :   :   :
             // 0b0000...0000
+            // 0b0000...0001
             {
                 let mut board = [0; 20];
-                board[0] = 1 << 19;
+                board[19] = 1;
                 board
             },
         ];
@@ -254,49 +127,32 @@ impl State {
     /// none(piece & occupied) & some(piece & corners) means that the piece fits in those 8 rows
     #[inline]
     fn get_checker(&self, player: &Player, offset: usize) -> Checker {
-        unsafe {
-            (
-                // _mm256_or_si256(
-                _mm256_loadu_si256(
-                    self.occupied_mask.as_ptr().wrapping_add(offset) as *const __m256i
-                ),
-                _mm256_loadu_si256(
-                    self.color_masks[usize::from(player)]
-                        .as_ptr()
-                        // This is moved back 1 because the neighbor mask is expanded
-                        .wrapping_add(offset) as *const __m256i,
-                ),
-                // ),
-                _mm256_loadu_si256(
-                    self.corner_masks[usize::from(player)]
-                        .as_ptr()
-                        .wrapping_add(offset) as *const __m256i,
-                ),
-            )
-        }
+        (
+            load(&self.occupied_mask, offset),
+            load(&self.color_masks[usize::from(player)], offset),
+            load(&self.corner_masks[usize::from(player)], offset),
+        )
     }
 
     #[inline]
-    fn check((occupied, colors, corners): Checker, shape: __m256i) -> bool {
-        unsafe {
-            // check if this is unoccupied
-            // we want the result to be 0
-            _mm256_testz_si256(colors, shape) != 0 &&
-            // check if its a valid corner
-            // testz returns 0 if the result of the & is >0
-            // and 1 if the result is 0
-            _mm256_testz_si256(corners, shape) == 0 &&
-            // check if this is unoccupied
-            // we want the result to be 0
-            _mm256_testz_si256(occupied, shape) != 0
-        }
+    fn check((occupied, colors, corners): Checker, shape: Ymm) -> bool {
+        // check if this is unoccupied
+        // we want the result to be 0
+        testz(colors, shape) &&
+        // check if its a valid corner
+        // testz returns false if the result of the & is >0
+        // and true if the result is 0
+        !testz(corners, shape) &&
+        // check if this is unoccupied
+        // we want the result to be 0
+        testz(occupied, shape)
     }
 
     #[inline(never)]
     fn get_moves_for_piece(
         &self,
         moves: &mut Vec<Move>,
-        pieces: &[Piece; PIECE_COUNT],
+        pieces: &[Piece],
         (check0to4, check4to8, check8to12, check12to16): (Checker, Checker, Checker, Checker),
         pieceid: usize,
     ) {
@@ -304,49 +160,45 @@ impl State {
         // The number of rows we need to check
         // let to_check = 20 - piece.width + 1;
 
-        if piece.height <= 4 {
-            // The height of the piece is <= 4.
-            // we can check the rows in groups of 4.
-            // We do as follows:
-            // Generate a checker for the first row in the gap
-            // Rotate down by 1 and check again
-            // repeat
-
-            let mut shape = piece.occupied_mask;
-
-            for x in 0..(21 - piece.width) {
-                let mut y_shape = shape;
-                for offset in 0..4 {
-                    // 21 here because we need to check the last row
-                    if Self::check(check0to4, y_shape) {
-                        moves.push(Move::new(pieceid, (x, offset)));
-                    }
-                    if Self::check(check4to8, y_shape) {
-                        moves.push(Move::new(pieceid, (x, offset + 4)));
-                    }
-                    if Self::check(check8to12, y_shape) {
-                        moves.push(Move::new(pieceid, (x, offset + 8)));
-                    }
-                    if Self::check(check12to16, y_shape) {
-                        moves.push(Move::new(pieceid, (x, offset + 12)));
-                    }
-                    y_shape = unsafe { rotate_down_1(y_shape) };
+        // We check the rows in groups of 4.
+        // We do as follows:
+        // Generate a checker for the first row in the gap
+        // Rotate down by 1 and check again
+        // repeat
+        // This also works for the 5 tall piece, as a piece rotated down
+        // by at most 3 rows still fits in the 8 rows of a checker.
+
+        let mut shape = piece.occupied_mask;
+
+        for x in 0..(21 - piece.width) {
+            let mut y_shape = shape;
+            for offset in 0..4 {
+                // 21 here because we need to check the last row
+                if Self::check(check0to4, y_shape) {
+                    moves.push(Move::new(pieceid, (x, offset)));
+                }
+                if Self::check(check4to8, y_shape) {
+                    moves.push(Move::new(pieceid, (x, offset + 4)));
                 }
+                if Self::check(check8to12, y_shape) {
+                    moves.push(Move::new(pieceid, (x, offset + 8)));
+                }
+                if Self::check(check12to16, y_shape) {
+                    moves.push(Move::new(pieceid, (x, offset + 12)));
+                }
+                y_shape = rotate_down_1(y_shape);
+            }
 
-                // this last one is special. The number of rows to check is dependent on the height of the piece
-                // by here, the shape has shifted down 4 tiles already.
+            // this last one is special. The number of rows to check is dependent on the height of the piece
+            // by here, the shape has shifted down 4 tiles already.
 
-                for offset in 4..(9 - piece.height) {
-                    if Self::check(check12to16, y_shape) {
-                        moves.push(Move::new(pieceid, (x, offset + 12)));
-                    }
-                    y_shape = unsafe { rotate_down_1(y_shape) };
+            for offset in 4..(9 - piece.height) {
+                if Self::check(check12to16, y_shape) {
+                    moves.push(Move::new(pieceid, (x, offset + 12)));
                 }
-                shape = unsafe { shift_left_1(shape) };
+                y_shape = rotate_down_1(y_shape);
             }
-        } else {
-            // This is the one case of the 5 high piece
-            debug_assert!(piece.height == 5 && piece.width == 1);
+            shape = shift_left_1(shape);
         }
     }
 
@@ -427,8 +279,7 @@ impl State {
         //     );
         // }
 
-        let occupied_mask =
-            unsafe { std::mem::transmute::<__m256i, [u32; 8]>(piece.occupied_mask) };
+        let occupied_mask = rows(piece.occupied_mask);
         for i in 0..piece.height {
             self.occupied_mask[(y + i) as usize] |= occupied_mask[i as usize] << x;
         }
@@ -518,3 +369,111 @@ impl Debug for State {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::game::ver_3;
+    use rand::seq::SliceRandom;
+
+    /// Map every ver_3 piece index to its ver_2 index.
+    /// Both are generated in the same order, but ver_3 keeps the 5 long pieces at the end
+    fn from_ver_3() -> Vec<usize> {
+        let (fits, long): (Vec<_>, Vec<_>) =
+            (0..PIECE_COUNT).partition(|i| PIECES[*i].width <= 4 && PIECES[*i].height <= 4);
+        fits.into_iter().chain(long).collect()
+    }
+
+    #[test]
+    fn check_place_corner_one() {
+        // Take the
+        // X
+        // piece and place it in the top left at (0, 0)
+        let mut game = State::new(20, 20);
+        game.place_piece(&Player::Player1, &Move::new(0, (0, 0)));
+
+        assert_eq!(game.occupied_mask[0], 1);
+        assert_eq!(game.occupied_mask[1], 0);
+        // The cell and its neighbors are unplayable for the player
+        assert_eq!(game.color_masks[0][0], 0b11);
+        assert_eq!(game.color_masks[0][1], 0b1);
+        assert_eq!(game.color_masks[1][0], 0);
+        // The diagonal is a new corner
+        assert_eq!(game.corner_masks[0][1] & 0b10, 0b10);
+
+        let moves = game.get_moves(&Player::Player1);
+        assert!(moves
+            .iter()
+            .all(|mv| mv.pos.0 + PIECES[mv.piece].width > 1
+                && mv.pos.1 + PIECES[mv.piece].height > 1));
+        assert!(moves.contains(&Move::new(1, (1, 1))));
+    }
+
+    #[test]
+    fn check_place_corner_tall() {
+        // Take the
+        // X
+        // X
+        // X
+        // X
+        // X
+        // piece
+        // ver_3 keeps it last
+        let tall = from_ver_3()[PIECE_COUNT - 1];
+        let game = State::new(20, 20);
+        for (player, pos) in [
+            (Player::Player1, (0, 0)),
+            (Player::Player2, (19, 0)),
+            (Player::Player3, (19, 15)),
+            (Player::Player4, (0, 15)),
+        ] {
+            assert!(game.get_moves(&player).contains(&Move::new(tall, pos)));
+        }
+
+        let mut game = State::new(20, 20);
+        game.place_piece(&Player::Player3, &Move::new(tall, (19, 15)));
+        for row in 15..20 {
+            assert_eq!(game.occupied_mask[row], 1 << 19);
+            // bits past the edge of the board are never checked
+            assert_eq!(game.color_masks[2][row] & ((1 << 20) - 1), 0b11 << 18);
+        }
+        assert_eq!(game.color_masks[2][14] & ((1 << 20) - 1), 1 << 19);
+        assert_eq!(game.corner_masks[2][14] & (1 << 18), 1 << 18);
+    }
+
+    #[test]
+    fn check_moves_match_ver_3() {
+        let mut rng = rand::thread_rng();
+        let from_ver_3 = from_ver_3();
+        for _ in 0..5 {
+            let mut game = State::new(20, 20);
+            let mut game3 = ver_3::State::new(20, 20);
+            let mut done = [false; Player::N];
+            while !done.iter().all(|d| *d) {
+                for player in Player::iter() {
+                    let pid = usize::from(player);
+                    if done[pid] {
+                        continue;
+                    }
+                    let mut moves = game.get_moves(&player);
+                    let moves3 = game3.get_moves(&player);
+                    let mut mapped: Vec<_> = moves3
+                        .iter()
+                        .map(|mv| Move::new(from_ver_3[mv.piece], mv.pos))
+                        .collect();
+                    moves.sort();
+                    mapped.sort();
+                    assert_eq!(moves, mapped);
+
+                    match moves3.choose(&mut rng) {
+                        Some(mv) => {
+                            game.place_piece(&player, &Move::new(from_ver_3[mv.piece], mv.pos));
+                            game3.place_piece(mv);
+                        }
+                        None => done[pid] = true,
+                    }
+                }
+            }
+        }
+    }
+}
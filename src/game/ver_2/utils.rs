@@ -1,53 +1,161 @@
-use std::arch::x86_64::*;
+//! Operations on 8 rows of 32 bit masks.
+//! These are held in an AVX2 register when the build targets it,
+//! and in a plain array otherwise.
 
-#[inline]
-pub unsafe fn ymm(a: [u32; 8]) -> __m256i {
-    _mm256_loadu_si256(a.as_ptr() as *const __m256i)
-}
+// Only one of these is selected, the other is kept for the differential test
+#[cfg(target_arch = "x86_64")]
+#[allow(dead_code)]
+mod avx2 {
+    use std::arch::x86_64::*;
 
-/// Rotates the ymms down 1
-#[inline]
-pub unsafe fn rotate_down_1(ymm: __m256i) -> __m256i {
-    // This is safe because we only care about pieces that have height <= 4
-    _mm256_permutevar8x32_epi32(ymm, _mm256_set_epi32(6, 5, 4, 3, 2, 1, 0, 7))
+    pub type Ymm = __m256i;
+
+    #[inline]
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn ymm(a: [u32; 8]) -> Ymm {
+        _mm256_loadu_si256(a.as_ptr() as *const __m256i)
+    }
+
+    #[inline]
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn rows(a: Ymm) -> [u32; 8] {
+        std::mem::transmute::<__m256i, [u32; 8]>(a)
+    }
+
+    #[inline]
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn rotate_down_1(ymm: Ymm) -> Ymm {
+        // This is safe as long as the piece plus the rotation fits in 8 rows
+        _mm256_permutevar8x32_epi32(ymm, _mm256_set_epi32(6, 5, 4, 3, 2, 1, 0, 7))
+    }
+
+    #[inline]
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn shift_left_1(ymm: Ymm) -> Ymm {
+        _mm256_slli_epi32::<1>(ymm)
+    }
+
+    #[inline]
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn testz(a: Ymm, b: Ymm) -> bool {
+        _mm256_testz_si256(a, b) != 0
+    }
 }
 
-/// Shift ymms to the left
-#[inline]
-pub unsafe fn shift_left_1(ymm: __m256i) -> __m256i {
-    _mm256_slli_epi32::<1>(ymm)
+#[allow(dead_code)]
+mod portable {
+    pub type Ymm = [u32; 8];
+
+    #[inline]
+    pub fn ymm(a: [u32; 8]) -> Ymm {
+        a
+    }
+
+    #[inline]
+    pub fn rows(a: Ymm) -> [u32; 8] {
+        a
+    }
+
+    #[inline]
+    pub fn rotate_down_1(ymm: Ymm) -> Ymm {
+        let mut rotated = ymm;
+        rotated.rotate_right(1);
+        rotated
+    }
+
+    #[inline]
+    pub fn shift_left_1(ymm: Ymm) -> Ymm {
+        ymm.map(|row| row << 1)
+    }
+
+    #[inline]
+    pub fn testz(a: Ymm, b: Ymm) -> bool {
+        a.iter().zip(b).all(|(a, b)| a & b == 0)
+    }
 }
 
-#[cfg(test)]
-mod tests {
-    use crate::game::ver_2::utils::*;
-    use std::fmt::Write;
+#[cfg(all(target_arch = "x86_64", target_feature = "avx2", not(no_simd)))]
+mod selected {
+    // Safety: every function here needs AVX2, which the whole build is compiled for
+    use super::avx2;
+    pub use avx2::Ymm;
 
-    fn ymm_str(ymm: __m256i) -> String {
-        let rows = unsafe { std::mem::transmute::<__m256i, [u32; 8]>(ymm) };
+    #[inline]
+    pub fn ymm(a: [u32; 8]) -> Ymm {
+        unsafe { avx2::ymm(a) }
+    }
 
-        rows.iter().fold(String::new(), |mut output, v| {
-            let _ = writeln!(output, "{v:032b}");
-            output
-        })
+    #[inline]
+    pub fn rows(a: Ymm) -> [u32; 8] {
+        unsafe { avx2::rows(a) }
     }
 
-    unsafe fn ymm_eq(a: __m256i, b: __m256i) -> bool {
-        // Fills mask with 1 if a and b are equal
-        let mask = _mm256_cmpeq_epi32(a, b);
-        // Take the most significant bit from each 8 bit chunk
-        let ret = _mm256_movemask_epi8(mask);
-        ret == -1
+    /// Rotates the ymms down 1
+    #[inline]
+    pub fn rotate_down_1(ymm: Ymm) -> Ymm {
+        unsafe { avx2::rotate_down_1(ymm) }
     }
 
+    /// Shift ymms to the left
+    #[inline]
+    pub fn shift_left_1(ymm: Ymm) -> Ymm {
+        unsafe { avx2::shift_left_1(ymm) }
+    }
+
+    /// Whether `a & b` is all zeros
+    #[inline]
+    pub fn testz(a: Ymm, b: Ymm) -> bool {
+        unsafe { avx2::testz(a, b) }
+    }
+}
+
+#[cfg(not(all(target_arch = "x86_64", target_feature = "avx2", not(no_simd))))]
+use portable as selected;
+
+pub use selected::*;
+
+/// Load 8 rows of a board starting at `offset`
+#[inline]
+pub fn load(board: &[u32], offset: usize) -> Ymm {
+    ymm(board[offset..offset + 8].try_into().unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+
     #[test]
     fn rotate_down_test() {
-        let ymm1 = unsafe { ymm([0, 1, 2, 3, 4, 5, 6, 7]) };
-        let expected = unsafe { ymm([7, 0, 1, 2, 3, 4, 5, 6]) };
-        let rotated = unsafe { rotate_down_1(ymm1) };
+        let ymm1 = ymm([0, 1, 2, 3, 4, 5, 6, 7]);
+        let rotated = rotate_down_1(ymm1);
 
-        println!("{}", ymm_str(rotated));
-        println!("{}", ymm_str(expected));
-        assert!(unsafe { ymm_eq(rotated, expected) })
+        assert_eq!(rows(rotated), [7, 0, 1, 2, 3, 4, 5, 6]);
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    #[test]
+    fn portable_matches_avx2() {
+        if !is_x86_feature_detected!("avx2") {
+            return;
+        }
+        let mut rng = rand::thread_rng();
+        for _ in 0..1000 {
+            let a: [u32; 8] = rng.gen();
+            let b: [u32; 8] = rng.gen::<[u32; 8]>().map(|row| row & rng.gen::<u32>());
+            unsafe {
+                let (sa, sb) = (avx2::ymm(a), avx2::ymm(b));
+                assert_eq!(avx2::rows(sa), portable::rows(portable::ymm(a)));
+                assert_eq!(
+                    avx2::rows(avx2::rotate_down_1(sa)),
+                    portable::rotate_down_1(a)
+                );
+                assert_eq!(
+                    avx2::rows(avx2::shift_left_1(sa)),
+                    portable::shift_left_1(a)
+                );
+                assert_eq!(avx2::testz(sa, sb), portable::testz(a, b));
+            }
+        }
     }
 }
@@ -1,5 +1,7 @@
 mod piece;
+mod position;
 mod state;
 
 pub use piece::Piece;
-pub use state::{piece_dims, piece_size, Move, State};
+pub use position::PositionError;
+pub use state::{Move, State};
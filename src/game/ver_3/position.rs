@@ -0,0 +1,467 @@
+use super::state::{State, MAX_SIZE};
+use crate::game::{Dimensioned, Player, Shape, Turn};
+use std::{
+    fmt::{Display, Write},
+    str::FromStr,
+};
+
+/// Mask with a bit for every shape
+const ALL_SHAPES: u32 = (1 << Shape::N) - 1;
+
+/// Reasons a position could not be loaded
+#[derive(Debug, PartialEq, Eq)]
+pub enum PositionError {
+    /// A field is missing or the data ends early
+    Truncated,
+    /// The board is smaller than 5 or larger than [MAX_SIZE] in some direction
+    InvalidSize(usize, usize),
+    /// A character that doesn't belong in its field
+    InvalidChar(char),
+    /// A row doesn't have as many cells as the board is wide
+    InvalidRow(usize),
+    /// The remaining pieces have bits set that aren't shapes
+    InvalidPieces,
+    /// A player covers a different number of cells than the pieces they used
+    InconsistentScore(Player),
+}
+
+impl Display for PositionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PositionError::Truncated => write!(f, "Position ends early"),
+            PositionError::InvalidSize(w, h) => write!(f, "Invalid board size {}x{}", w, h),
+            PositionError::InvalidChar(c) => write!(f, "Invalid character {:?}", c),
+            PositionError::InvalidRow(y) => write!(f, "Row {} has the wrong number of cells", y),
+            PositionError::InvalidPieces => write!(f, "Invalid remaining pieces"),
+            PositionError::InconsistentScore(player) => write!(
+                f,
+                "Player {} covers a different number of cells than the pieces they used",
+                usize::from(player) + 1
+            ),
+        }
+    }
+}
+
+impl std::error::Error for PositionError {}
+
+fn player_char(player: &Player) -> char {
+    match player {
+        Player::Player1 => 'r',
+        Player::Player2 => 'g',
+        Player::Player3 => 'y',
+        Player::Player4 => 'b',
+    }
+}
+
+fn char_player(c: char) -> Result<Player, PositionError> {
+    match c {
+        'r' => Ok(Player::Player1),
+        'g' => Ok(Player::Player2),
+        'y' => Ok(Player::Player3),
+        'b' => Ok(Player::Player4),
+        c => Err(PositionError::InvalidChar(c)),
+    }
+}
+
+fn parse_number(s: &str) -> Result<usize, PositionError> {
+    match s.chars().find(|c| !c.is_ascii_digit()) {
+        Some(c) => Err(PositionError::InvalidChar(c)),
+        None => s.parse().map_err(|_| PositionError::Truncated),
+    }
+}
+
+impl State {
+    /// Shapes a player has left, with bit i set if they have [Shape] i
+    fn shape_mask(&self, player: &Player) -> u32 {
+        self.remaining_pieces(player)
+            .into_iter()
+            .fold(0, |mask, piece| mask | 1 << piece)
+    }
+
+    /// Build a state from its cells and the shapes every player has left,
+    /// making sure they agree with each other
+    fn from_parts(
+        (w, h): (usize, usize),
+        cells: &[Option<Player>],
+        shapes: [u32; Player::N],
+    ) -> Result<Self, PositionError> {
+        let mut remaining: [Vec<Shape>; Player::N] = Default::default();
+        for player in Player::iter() {
+            let pid = usize::from(player);
+            if shapes[pid] & !ALL_SHAPES != 0 {
+                return Err(PositionError::InvalidPieces);
+            }
+            remaining[pid] = Shape::iter()
+                .filter(|shape| shapes[pid] & 1 << usize::from(*shape) != 0)
+                .collect();
+
+            let used: usize = Shape::iter()
+                .filter(|shape| !remaining[pid].contains(shape))
+                .map(|shape| shape.size() as usize)
+                .sum();
+            if cells.iter().filter(|c| **c == Some(player)).count() != used {
+                return Err(PositionError::InconsistentScore(player));
+            }
+        }
+        Ok(Self::from_cells(w, h, cells, &remaining))
+    }
+
+    /// Write the position as a FEN-like string such as
+    /// ```text
+    /// 20x20 r19/20/20/20/20/20/20/20/20/20/20/20/20/20/20/20/20/20/20/20 1ffffe,1fffff,1fffff,1fffff g -
+    /// ```
+    /// The fields are separated by spaces:
+    /// - the board size as `<w>x<h>`
+    /// - the rows from top to bottom separated by `/`, where players are `r`, `g`, `y` and `b`
+    ///   after their colors and numbers are runs of empty cells
+    /// - the shapes every player has left, as hexadecimal masks where bit i is [Shape] i
+    /// - the player to move
+    /// - the players that have passed, or `-` if none have
+    pub fn to_fen(&self, turn: &Turn) -> String {
+        let mut fen = format!("{}x{} ", self.w(), self.h());
+        for y in 0..self.h() {
+            if y > 0 {
+                fen.push('/');
+            }
+            let mut empty = 0;
+            for x in 0..self.w() {
+                match self.cell(x, y) {
+                    Some(player) => {
+                        if empty > 0 {
+                            write!(fen, "{}", empty).unwrap();
+                            empty = 0;
+                        }
+                        fen.push(player_char(&player));
+                    }
+                    None => empty += 1,
+                }
+            }
+            if empty > 0 {
+                write!(fen, "{}", empty).unwrap();
+            }
+        }
+
+        let shapes: Vec<_> = Player::iter()
+            .map(|player| format!("{:x}", self.shape_mask(&player)))
+            .collect();
+        let passed: String = Player::iter()
+            .filter(|player| turn.passed[usize::from(player)])
+            .map(|player| player_char(&player))
+            .collect();
+        write!(
+            fen,
+            " {} {} {}",
+            shapes.join(","),
+            player_char(&turn.to_move),
+            if passed.is_empty() { "-" } else { &passed }
+        )
+        .unwrap();
+        fen
+    }
+
+    /// Read a position written by [State::to_fen]
+    pub fn from_fen(fen: &str) -> Result<(Self, Turn), PositionError> {
+        let mut fields = fen.split_whitespace();
+        let mut field = || fields.next().ok_or(PositionError::Truncated);
+
+        let (w, h) = field()?.split_once('x').ok_or(PositionError::Truncated)?;
+        let (w, h) = (parse_number(w)?, parse_number(h)?);
+        if !(5..=MAX_SIZE).contains(&w) || !(5..=MAX_SIZE).contains(&h) {
+            return Err(PositionError::InvalidSize(w, h));
+        }
+
+        let mut cells = Vec::with_capacity(w * h);
+        let rows: Vec<_> = field()?.split('/').collect();
+        if rows.len() != h {
+            return Err(PositionError::InvalidRow(rows.len().min(h)));
+        }
+        for (y, row) in rows.into_iter().enumerate() {
+            let start = cells.len();
+            let mut empty = 0;
+            for c in row.chars() {
+                match c.to_digit(10) {
+                    Some(d) => {
+                        empty = empty * 10 + d as usize;
+                        if empty > w {
+                            return Err(PositionError::InvalidRow(y));
+                        }
+                    }
+                    None => {
+                        cells.extend(std::iter::repeat_n(None, empty));
+                        empty = 0;
+                        cells.push(Some(char_player(c)?));
+                    }
+                }
+            }
+            cells.extend(std::iter::repeat_n(None, empty));
+            if cells.len() - start != w {
+                return Err(PositionError::InvalidRow(y));
+            }
+        }
+
+        let mut shapes = [0; Player::N];
+        let mut masks = field()?.split(',');
+        for mask in shapes.iter_mut() {
+            let hex = masks.next().ok_or(PositionError::Truncated)?;
+            *mask = u32::from_str_radix(hex, 16).map_err(|_| PositionError::InvalidPieces)?;
+        }
+        if masks.next().is_some() {
+            return Err(PositionError::InvalidPieces);
+        }
+
+        let mut turn = Turn::default();
+        let mut to_move = field()?.chars();
+        turn.to_move = char_player(to_move.next().ok_or(PositionError::Truncated)?)?;
+        if let Some(c) = to_move.next() {
+            return Err(PositionError::InvalidChar(c));
+        }
+        let passed = field()?;
+        if passed != "-" {
+            for c in passed.chars() {
+                turn.passed[usize::from(char_player(c)?)] = true;
+            }
+        }
+
+        Ok((Self::from_parts((w, h), &cells, shapes)?, turn))
+    }
+
+    /// Encode the position as bytes:
+    /// - the board width and height
+    /// - the player to move in bits 0-1 and the players that have passed in bits 4-7
+    /// - the shapes every player has left, as 3 little endian bytes where bit i is [Shape] i
+    /// - a bit for every cell in row major order, set if it is covered
+    /// - 2 bits with the player covering every covered cell, in the same order
+    pub fn to_bytes(&self, turn: &Turn) -> Vec<u8> {
+        let (w, h) = (self.w(), self.h());
+        let mut bytes = vec![w as u8, h as u8];
+        let passed = (0..Player::N).fold(0, |byte, pid| byte | (turn.passed[pid] as u8) << pid);
+        bytes.push(usize::from(turn.to_move) as u8 | passed << 4);
+        for player in Player::iter() {
+            bytes.extend_from_slice(&self.shape_mask(&player).to_le_bytes()[..3]);
+        }
+
+        let cells: Vec<_> = (0..h)
+            .flat_map(|y| (0..w).map(move |x| (x, y)))
+            .map(|(x, y)| self.cell(x, y))
+            .collect();
+        let mut occupied = vec![0u8; cells.len().div_ceil(8)];
+        let mut owners = vec![];
+        for (i, player) in cells.iter().enumerate() {
+            if let Some(player) = player {
+                occupied[i / 8] |= 1 << (i % 8);
+                owners.push(usize::from(player) as u8);
+            }
+        }
+        bytes.extend(occupied);
+        bytes.extend(owners.chunks(4).map(|chunk| {
+            chunk
+                .iter()
+                .enumerate()
+                .fold(0, |byte, (i, pid)| byte | pid << (i * 2))
+        }));
+        bytes
+    }
+
+    /// Decode a position encoded by [State::to_bytes]
+    pub fn from_bytes(bytes: &[u8]) -> Result<(Self, Turn), PositionError> {
+        let mut bytes = bytes.iter().copied();
+        let mut next = || bytes.next().ok_or(PositionError::Truncated);
+
+        let (w, h) = (next()? as usize, next()? as usize);
+        if !(5..=MAX_SIZE).contains(&w) || !(5..=MAX_SIZE).contains(&h) {
+            return Err(PositionError::InvalidSize(w, h));
+        }
+
+        let turn_byte = next()?;
+        let turn = Turn {
+            to_move: Player::from((turn_byte & 0b11) as usize),
+            passed: std::array::from_fn(|pid| turn_byte >> (4 + pid) & 1 != 0),
+        };
+
+        let mut shapes = [0; Player::N];
+        for mask in shapes.iter_mut() {
+            *mask = u32::from_le_bytes([next()?, next()?, next()?, 0]);
+        }
+
+        let mut occupied = Vec::with_capacity(w * h);
+        for _ in 0..(w * h).div_ceil(8) {
+            let byte = next()?;
+            occupied.extend((0..8).map(|i| byte >> i & 1 != 0));
+        }
+        occupied.truncate(w * h);
+
+        let mut cells = vec![None; w * h];
+        let mut owner_byte = 0;
+        for (n, i) in (0..w * h).filter(|i| occupied[*i]).enumerate() {
+            if n % 4 == 0 {
+                owner_byte = next()?;
+            }
+            cells[i] = Some(Player::from((owner_byte >> ((n % 4) * 2) & 0b11) as usize));
+        }
+
+        Ok((Self::from_parts((w, h), &cells, shapes)?, turn))
+    }
+}
+
+impl FromStr for State {
+    type Err = PositionError;
+
+    /// Read the board and pieces of a FEN-like string, ignoring whose turn it is
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::from_fen(s).map(|(state, _)| state)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::ver_3::Move;
+    use rand::seq::SliceRandom;
+
+    /// Play a random game for a number of plies, keeping track of whose turn it is
+    fn random_position(plies: usize) -> (State, Turn) {
+        let mut rng = rand::thread_rng();
+        let mut state = State::new(20, 20);
+        let mut turn = Turn::default();
+        for _ in 0..plies {
+            if turn.passed.iter().all(|p| *p) {
+                break;
+            }
+            let player = turn.to_move;
+            match state.get_moves(&player).choose(&mut rng) {
+                Some(mv) => state.place_piece(mv),
+                None => turn.passed[usize::from(player)] = true,
+            }
+            turn.to_move = player.next();
+        }
+        (state, turn)
+    }
+
+    /// Make sure two states have the same cells, pieces and moves
+    fn assert_same(a: &State, b: &State) {
+        assert_eq!((a.w(), a.h()), (b.w(), b.h()));
+        assert_eq!(a.scores(), b.scores());
+        for player in Player::iter() {
+            assert_eq!(a.remaining_pieces(&player), b.remaining_pieces(&player));
+            let mut moves = a.get_moves(&player);
+            let mut other = b.get_moves(&player);
+            moves.sort();
+            other.sort();
+            assert_eq!(moves, other);
+        }
+        for y in 0..a.h() {
+            for x in 0..a.w() {
+                assert_eq!(a.cell(x, y), b.cell(x, y));
+            }
+        }
+    }
+
+    #[test]
+    fn test_fen_start() {
+        let fen = State::new(20, 20).to_fen(&Turn::default());
+        assert_eq!(
+            fen,
+            format!(
+                "20x20 {} 1fffff,1fffff,1fffff,1fffff r -",
+                vec!["20"; 20].join("/")
+            )
+        );
+
+        let mut state = State::new(20, 20);
+        state.place_piece(&Move::new(Player::Player1, 0, (0, 0)));
+        let turn = Turn {
+            to_move: Player::Player2,
+            passed: [false, false, true, false],
+        };
+        let fen = state.to_fen(&turn);
+        assert!(fen.starts_with("20x20 r19/20/"));
+        assert!(fen.ends_with(" 1ffffe,1fffff,1fffff,1fffff g y"));
+
+        let (loaded, loaded_turn) = State::from_fen(&fen).unwrap();
+        assert_same(&state, &loaded);
+        assert_eq!(turn, loaded_turn);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for plies in [0, 4, 20, 40, 60, 100] {
+            let (state, turn) = random_position(plies);
+
+            let (loaded, loaded_turn) = State::from_fen(&state.to_fen(&turn)).unwrap();
+            assert_same(&state, &loaded);
+            assert_eq!(turn, loaded_turn);
+            assert_eq!(loaded.to_fen(&turn), state.to_fen(&turn));
+
+            let bytes = state.to_bytes(&turn);
+            let (loaded, loaded_turn) = State::from_bytes(&bytes).unwrap();
+            assert_same(&state, &loaded);
+            assert_eq!(turn, loaded_turn);
+            assert_eq!(loaded.to_bytes(&turn), bytes);
+
+            let parsed: State = state.to_fen(&turn).parse().unwrap();
+            assert_same(&state, &parsed);
+        }
+    }
+
+    #[test]
+    fn test_continue_loaded_game() {
+        let (state, turn) = random_position(30);
+        let (mut loaded, _) = State::from_fen(&state.to_fen(&turn)).unwrap();
+        let mut state = state;
+        // Both states keep agreeing as the game goes on
+        for player in (0..40).map(|i| Player::from(i % Player::N)) {
+            if let Some(mv) = state.get_moves(&player).first() {
+                state.place_piece(mv);
+                loaded.place_piece(mv);
+            }
+            assert_same(&state, &loaded);
+        }
+    }
+
+    #[test]
+    fn test_other_sizes() {
+        let mut state = State::new(11, 17);
+        state.place_piece(&Move::new(Player::Player3, 0, (10, 16)));
+        let fen = state.to_fen(&Turn::default());
+        assert!(fen.starts_with("11x17 11/"));
+        assert!(fen.contains("/10y "));
+        assert_same(&state, &fen.parse().unwrap());
+        let (loaded, _) = State::from_bytes(&state.to_bytes(&Turn::default())).unwrap();
+        assert_same(&state, &loaded);
+    }
+
+    #[test]
+    fn test_invalid() {
+        let empty = vec!["20"; 20].join("/");
+        let all = "1fffff,1fffff,1fffff,1fffff";
+        assert_eq!(
+            State::from_fen(&format!("30x20 {} {} r -", empty, all)).err(),
+            Some(PositionError::InvalidSize(30, 20))
+        );
+        assert_eq!(
+            State::from_fen(&format!("20x20 {}", empty)).err(),
+            Some(PositionError::Truncated)
+        );
+        assert_eq!(
+            State::from_fen(&format!("20x20 19/{} {} r -", &empty[3..], all)).err(),
+            Some(PositionError::InvalidRow(0))
+        );
+        assert_eq!(
+            State::from_fen(&format!("20x20 x19/{} {} r -", &empty[3..], all)).err(),
+            Some(PositionError::InvalidChar('x'))
+        );
+        assert_eq!(
+            State::from_fen(&format!("20x20 {} 3fffff,1fffff,1fffff,1fffff r -", empty)).err(),
+            Some(PositionError::InvalidPieces)
+        );
+        // Player 1 used a piece without covering any cells
+        assert_eq!(
+            State::from_fen(&format!("20x20 {} 1ffffe,1fffff,1fffff,1fffff r -", empty)).err(),
+            Some(PositionError::InconsistentScore(Player::Player1))
+        );
+        assert_eq!(
+            State::from_bytes(&[20, 20, 0]).err(),
+            Some(PositionError::Truncated)
+        );
+    }
+}
@@ -1,174 +1,137 @@
 use once_cell::sync::Lazy;
 
 use super::Piece;
-use crate::game::Player;
+use crate::game::{
+    legality::check_placement, Corner, Dimensioned, IllegalMove, Neighbor, PieceSet, Player, Shape,
+};
+use rand::{rngs::ThreadRng, seq::SliceRandom};
+#[cfg(target_arch = "x86_64")]
+use std::arch::x86_64::*;
 use std::{
-    arch::x86_64::*,
     array,
     cmp::{max, min},
     fmt::{Debug, Display},
 };
 
 const PIECE_COUNT: usize = 89;
-
+/// Largest board width or height supported
+pub const MAX_SIZE: usize = 20;
+/// Number of subsquares stored for every player.
+/// A multiple of 16 so every row of 16 positions can be read
+const MAX_CELLS: usize = MAX_SIZE * MAX_SIZE;
+/// Index of the horizontal 4 long piece in [PIECES]
+static I4_WIDE: Lazy<usize> = Lazy::new(|| piece_index(0b1111));
+/// Index of the vertical 4 long piece in [PIECES]
+static I4_TALL: Lazy<usize> = Lazy::new(|| piece_index(0x1111));
+
+/// Every transformation of the standard pieces that fits in a 4x4 subsquare,
+/// grouped by shape. The 5 long piece doesn't fit, so its two transformations
+/// come after these at [PIECE_COUNT] (wide) and [PIECE_COUNT] + 1 (tall).
 pub static PIECES: Lazy<[Piece; PIECE_COUNT]> = Lazy::new(|| {
-    [
-        // 1 tile pieces
-        // X - no transformations
-        Piece::new(1, 1, 0b1, [0b1, 0, 0, 0]),
-        // 2 tile pieces
-        // XX - 2 states
-        Piece::new(2, 1, 0b11 << 1, [0b11, 0, 0, 0]),
-        Piece::new(1, 2, 0b11 << 1, [0b1, 0b1, 0, 0]),
-        // 3 tile pieces
-        // XXX - 2 states
-        Piece::new(3, 1, 0b11 << 3, [0b111, 0, 0, 0]),
-        Piece::new(1, 3, 0b11 << 3, [0b1, 0b1, 0b1, 0]),
-        // XX
-        // X  - 4 states
-        Piece::new(2, 2, 0b1111 << 5, [0b11, 0b1, 0, 0]),
-        Piece::new(2, 2, 0b1111 << 5, [0b11, 0b10, 0, 0]),
-        Piece::new(2, 2, 0b1111 << 5, [0b1, 0b11, 0, 0]),
-        Piece::new(2, 2, 0b1111 << 5, [0b10, 0b11, 0, 0]),
-        // 4 tile pieces
-        // XXXX - 2 states
-        Piece::new(4, 1, 0b11 << 9, [0b1111, 0, 0, 0]),
-        Piece::new(1, 4, 0b11 << 9, [0b1, 0b1, 0b1, 0b1]),
-        // XXX
-        // X   - 8 states
-        Piece::new(3, 2, 0b11111111 << 11, [0b111, 0b1, 0, 0]),
-        Piece::new(3, 2, 0b11111111 << 11, [0b111, 0b100, 0, 0]),
-        Piece::new(3, 2, 0b11111111 << 11, [0b1, 0b111, 0, 0]),
-        Piece::new(3, 2, 0b11111111 << 11, [0b100, 0b111, 0, 0]),
-        Piece::new(2, 3, 0b11111111 << 11, [0b11, 0b10, 0b10, 0]),
-        Piece::new(2, 3, 0b11111111 << 11, [0b11, 0b1, 0b1, 0]),
-        Piece::new(2, 3, 0b11111111 << 11, [0b1, 0b1, 0b11, 0]),
-        Piece::new(2, 3, 0b11111111 << 11, [0b10, 0b10, 0b11, 0]),
-        // XX
-        //  XX - 4 states
-        Piece::new(3, 2, 0b1111 << 19, [0b110, 0b011, 0, 0]),
-        Piece::new(3, 2, 0b1111 << 19, [0b011, 0b110, 0, 0]),
-        Piece::new(2, 3, 0b1111 << 19, [0b10, 0b11, 0b01, 0]),
-        Piece::new(2, 3, 0b1111 << 19, [0b01, 0b11, 0b10, 0]),
-        // XXX
-        //  X - 4 states
-        Piece::new(3, 2, 0b1111 << 23, [0b111, 0b010, 0, 0]),
-        Piece::new(3, 2, 0b1111 << 23, [0b010, 0b111, 0, 0]),
-        Piece::new(2, 3, 0b1111 << 23, [0b10, 0b11, 0b10, 0]),
-        Piece::new(2, 3, 0b1111 << 23, [0b01, 0b11, 0b01, 0]),
-        // XX
-        // XX - 1 state
-        Piece::new(2, 2, 0b1 << 27, [0b11, 0b11, 0, 0]),
-        // 5 tile pieces
-        //  XX
-        // XX
-        //  X - 8 states
-        Piece::new(3, 3, 0b11111111 << 28, [0b110, 0b011, 0b010, 0]),
-        Piece::new(3, 3, 0b11111111 << 28, [0b011, 0b110, 0b010, 0]),
-        Piece::new(3, 3, 0b11111111 << 28, [0b010, 0b110, 0b011, 0]),
-        Piece::new(3, 3, 0b11111111 << 28, [0b010, 0b011, 0b110, 0]),
-        Piece::new(3, 3, 0b11111111 << 28, [0b010, 0b111, 0b001, 0]),
-        Piece::new(3, 3, 0b11111111 << 28, [0b001, 0b111, 0b010, 0]),
-        Piece::new(3, 3, 0b11111111 << 28, [0b010, 0b111, 0b100, 0]),
-        Piece::new(3, 3, 0b11111111 << 28, [0b100, 0b111, 0b010, 0]),
-        // XXXX
-        // X    - 8 states
-        Piece::new(4, 2, 0b11111111 << 36, [0b1111, 0b0001, 0, 0]),
-        Piece::new(4, 2, 0b11111111 << 36, [0b1111, 0b1000, 0, 0]),
-        Piece::new(4, 2, 0b11111111 << 36, [0b1000, 0b1111, 0, 0]),
-        Piece::new(4, 2, 0b11111111 << 36, [0b0001, 0b1111, 0, 0]),
-        Piece::new(2, 4, 0b11111111 << 36, [0b11, 0b10, 0b10, 0b10]),
-        Piece::new(2, 4, 0b11111111 << 36, [0b11, 0b01, 0b01, 0b01]),
-        Piece::new(2, 4, 0b11111111 << 36, [0b10, 0b10, 0b10, 0b11]),
-        Piece::new(2, 4, 0b11111111 << 36, [0b01, 0b01, 0b01, 0b11]),
-        // XXX
-        //   XX - 8 states
-        Piece::new(4, 2, 0b11111111 << 44, [0b1110, 0b0011, 0b0, 0]),
-        Piece::new(4, 2, 0b11111111 << 44, [0b0111, 0b1100, 0b0, 0]),
-        Piece::new(4, 2, 0b11111111 << 44, [0b0011, 0b1110, 0b0, 0]),
-        Piece::new(4, 2, 0b11111111 << 44, [0b1100, 0b0111, 0b0, 0]),
-        Piece::new(2, 4, 0b11111111 << 44, [0b10, 0b10, 0b11, 0b01]),
-        Piece::new(2, 4, 0b11111111 << 44, [0b01, 0b01, 0b11, 0b10]),
-        Piece::new(2, 4, 0b11111111 << 44, [0b10, 0b11, 0b01, 0b01]),
-        Piece::new(2, 4, 0b11111111 << 44, [0b01, 0b11, 0b10, 0b10]),
-        // XX
-        // XXX - 8 states
-        Piece::new(3, 2, 0b11111111 << 52, [0b110, 0b111, 0, 0]),
-        Piece::new(3, 2, 0b11111111 << 52, [0b111, 0b110, 0, 0]),
-        Piece::new(3, 2, 0b11111111 << 52, [0b011, 0b111, 0, 0]),
-        Piece::new(3, 2, 0b11111111 << 52, [0b111, 0b011, 0, 0]),
-        Piece::new(2, 3, 0b11111111 << 52, [0b11, 0b11, 0b01, 0]),
-        Piece::new(2, 3, 0b11111111 << 52, [0b11, 0b11, 0b10, 0]),
-        Piece::new(2, 3, 0b11111111 << 52, [0b01, 0b11, 0b11, 0]),
-        Piece::new(2, 3, 0b11111111 << 52, [0b10, 0b11, 0b11, 0]),
-        // XXX
-        //  X
-        //  X - 4 states
-        Piece::new(3, 3, 0b1111 << 60, [0b111, 0b010, 0b010, 0]),
-        Piece::new(3, 3, 0b1111 << 60, [0b010, 0b010, 0b111, 0]),
-        Piece::new(3, 3, 0b1111 << 60, [0b001, 0b111, 0b001, 0]),
-        Piece::new(3, 3, 0b1111 << 60, [0b100, 0b111, 0b100, 0]),
-        // X X
-        // XXX - 4 states
-        Piece::new(3, 2, 0b1111 << 64, [0b101, 0b111, 0, 0]),
-        Piece::new(3, 2, 0b1111 << 64, [0b111, 0b101, 0, 0]),
-        Piece::new(2, 3, 0b1111 << 64, [0b11, 0b10, 0b11, 0]),
-        Piece::new(2, 3, 0b1111 << 64, [0b11, 0b01, 0b11, 0]),
-        // X
-        // X
-        // XXX - 4 states
-        Piece::new(3, 3, 0b1111 << 68, [0b100, 0b100, 0b111, 0]),
-        Piece::new(3, 3, 0b1111 << 68, [0b001, 0b001, 0b111, 0]),
-        Piece::new(3, 3, 0b1111 << 68, [0b111, 0b100, 0b100, 0]),
-        Piece::new(3, 3, 0b1111 << 68, [0b111, 0b001, 0b001, 0]),
-        // X
-        // XX
-        //  XX - 4 states
-        Piece::new(3, 3, 0b1111 << 72, [0b100, 0b110, 0b011, 0]),
-        Piece::new(3, 3, 0b1111 << 72, [0b011, 0b110, 0b100, 0]),
-        Piece::new(3, 3, 0b1111 << 72, [0b001, 0b011, 0b110, 0]),
-        Piece::new(3, 3, 0b1111 << 72, [0b110, 0b011, 0b001, 0]),
-        //  X
-        // XXX
-        //  X  - 1 state
-        Piece::new(3, 3, 0b1 << 76, [0b010, 0b111, 0b010, 0]),
-        //   X
-        // XXXX - 8 states
-        Piece::new(4, 2, 0b11111111 << 77, [0b1111, 0b0010, 0, 0]),
-        Piece::new(4, 2, 0b11111111 << 77, [0b1111, 0b0100, 0, 0]),
-        Piece::new(4, 2, 0b11111111 << 77, [0b0010, 0b1111, 0, 0]),
-        Piece::new(4, 2, 0b11111111 << 77, [0b0100, 0b1111, 0, 0]),
-        Piece::new(2, 4, 0b11111111 << 77, [0b01, 0b01, 0b11, 0b01]),
-        Piece::new(2, 4, 0b11111111 << 77, [0b01, 0b11, 0b01, 0b01]),
-        Piece::new(2, 4, 0b11111111 << 77, [0b10, 0b10, 0b11, 0b10]),
-        Piece::new(2, 4, 0b11111111 << 77, [0b10, 0b11, 0b10, 0b10]),
-        // XX
-        //  X
-        //  XX - 4 states
-        Piece::new(3, 3, 0b1111 << 85, [0b110, 0b010, 0b011, 0]),
-        Piece::new(3, 3, 0b1111 << 85, [0b011, 0b010, 0b110, 0]),
-        Piece::new(3, 3, 0b1111 << 85, [0b001, 0b111, 0b100, 0]),
-        Piece::new(3, 3, 0b1111 << 85, [0b100, 0b111, 0b001, 0]),
-    ]
+    standard_pieces()
+        .map(|(_, piece)| piece)
+        .collect::<Vec<_>>()
+        .try_into()
+        .expect("The standard set should have 89 pieces that fit in a subsquare")
+});
+
+/// Base shape of every piece index, including the 5 long pieces
+static PIECE_SHAPES: Lazy<[Shape; PIECE_COUNT + 2]> = Lazy::new(|| {
+    let mut shapes = [Shape::I5; PIECE_COUNT + 2];
+    for (i, (shape, _)) in standard_pieces().enumerate() {
+        shapes[i] = shape;
+    }
+    shapes
+});
+
+/// Index of the first transformation of every shape in [PIECES]
+static SHAPE_OFFSETS: Lazy<[usize; Shape::N]> = Lazy::new(|| {
+    array::from_fn(|shape| {
+        PIECE_SHAPES
+            .iter()
+            .position(|s| usize::from(*s) == shape)
+            .unwrap()
+    })
 });
 
+/// Test of a row of 16 positions for a piece, see [Subsquares::test_piece_row_portable]
+type RowTest = fn(&Subsquares, u16, usize) -> u16;
+
+/// The fastest row test the cpu supports, detected once so testing a row never checks again
+static TEST_PIECE_ROW: Lazy<RowTest> = Lazy::new(|| {
+    #[cfg(all(target_arch = "x86_64", not(no_simd)))]
+    if is_x86_feature_detected!("avx2") {
+        // Safety: the cpu supports AVX2
+        return |subsquares, piece, start| unsafe { subsquares.test_piece_row_avx2(piece, start) };
+    }
+    Subsquares::test_piece_row_portable
+});
+
+/// The standard set, the only one this engine plays with
+static PIECE_SET: Lazy<PieceSet> = Lazy::new(PieceSet::standard);
+
+/// Generate the pieces from the standard piece set
+fn standard_pieces() -> impl Iterator<Item = (Shape, Piece)> {
+    let mut start = 0;
+    Shape::iter()
+        .zip(PIECE_SET.shapes().to_vec())
+        .flat_map(move |(shape, polyomino)| {
+            let versions: Vec<_> = polyomino
+                .transformations()
+                .into_iter()
+                .filter(|version| version.w() <= 4 && version.h() <= 4)
+                .collect();
+            // The player has every version of a shape until one of them is placed
+            let id_mask = ((1 << versions.len()) - 1) << start;
+            start += versions.len();
+
+            versions.into_iter().map(move |version| {
+                let rows = version.rows();
+                let rows = array::from_fn(|y| rows.get(y).map_or(0, |row| *row as u8));
+                (shape, Piece::new(version.w(), version.h(), id_mask, rows))
+            })
+        })
+}
+
+/// Index of the piece with the given 4x4 mask in [PIECES]
+fn piece_index(as_u16: u16) -> usize {
+    PIECES.iter().position(|p| p.as_u16 == as_u16).unwrap()
+}
+
 /// Abstracted function to get number of filled tiles in a piece given a move
 /// Useful for algorithms
 #[inline]
-pub fn piece_size(mv: &Move) -> u8 {
-    match mv.piece {
-        0 => 1,
-        1..=2 => 2,
-        3..=8 => 3,
-        9..=27 => 4,
-        28..=90 => 5,
-        _ => unreachable!(),
+fn piece_size(mv: &Move) -> u8 {
+    index_shape(mv.piece).size()
+}
+
+/// Abstracted function to get the base shape of a piece given a move
+#[inline]
+fn piece_shape(mv: &Move) -> Shape {
+    index_shape(mv.piece)
+}
+
+/// Base shape of a piece given its index
+#[inline]
+fn index_shape(piece: usize) -> Shape {
+    PIECE_SHAPES[piece]
+}
+
+/// Cells covered by a piece placed at the origin
+fn piece_cells(piece: usize) -> Vec<(i8, i8)> {
+    match piece {
+        PIECE_COUNT => (0..5).map(|i| (i, 0)).collect(),
+        _ if piece == PIECE_COUNT + 1 => (0..5).map(|i| (0, i)).collect(),
+        piece => (0..16)
+            .filter(|i| PIECES[piece].as_u16 & (1 << i) != 0)
+            .map(|i| (i % 4, i / 4))
+            .collect(),
     }
 }
 
 /// Abstracted function to get dimensions of a piece given a move
 #[inline]
-pub fn piece_dims(mv: &Move) -> (u8, u8) {
+fn piece_dims(mv: &Move) -> (u8, u8) {
     const PC1: usize = PIECE_COUNT + 1;
     match mv.piece {
         PC1 => (1, 5),
@@ -200,14 +163,15 @@ enum SubsquareMaskTyp {
     Validcorners,
 }
 
-fn subsquare_str(mask: &[u16; 400]) -> String {
+/// Print a board of `w` wide subsquares
+fn subsquare_str(mask: &[u16], w: usize) -> String {
     let mut f = String::new();
     use std::fmt::Write;
-    for y in 0..20 {
+    for y in 0..mask.len() / w {
         for row in 0..4 {
-            for x in (0..20).rev() {
+            for x in (0..w).rev() {
                 // get the row of this mask
-                let row = (mask[y * 20 + x] >> (row * 4)) & 0xf;
+                let row = (mask[y * w + x] >> (row * 4)) & 0xf;
                 write!(f, "{:04b} ", row).unwrap();
             }
             writeln!(f).unwrap();
@@ -219,52 +183,78 @@ fn subsquare_str(mask: &[u16; 400]) -> String {
 
 #[derive(Clone)]
 pub struct Subsquares {
-    /// Entry at index x contains a 4x4 mask, corresponding to a move at (x // 20, x % 20).
-    occupied_or_color: [u16; 400],
-    valid_corners: [u16; 400],
+    /// Entry at index i contains a 4x4 mask, corresponding to a move at (i % w, i / w).
+    occupied_or_color: [u16; MAX_CELLS],
+    valid_corners: [u16; MAX_CELLS],
+    /// Board width
+    w: usize,
+    /// Board height
+    h: usize,
 }
 
 impl Subsquares {
-    pub fn test_piece(&self, moves: &mut Vec<Move>, player: &Player, piece_id: usize, piece: u16) {
-        unsafe {
-            let piece = _mm256_set1_epi16(piece as i16);
-            let zero = _mm256_setzero_si256();
-
-            for i in 0..25 {
-                let occupied_or_color = _mm256_loadu_si256(std::ptr::addr_of!(
-                    self.occupied_or_color[i * 16]
-                ) as *const __m256i);
-                let valid_corners = _mm256_loadu_si256(std::ptr::addr_of!(
-                    self.valid_corners[i * 16]
-                ) as *const __m256i);
-                let ok1 = _mm256_movemask_epi8(_mm256_cmpeq_epi16(
-                    _mm256_and_si256(piece, occupied_or_color),
-                    zero,
-                )) as u32;
-                let not_ok2 = _mm256_movemask_epi8(_mm256_cmpeq_epi16(
-                    _mm256_and_si256(piece, valid_corners),
-                    zero,
-                )) as u32;
-
-                let mut ok = (ok1 & !not_ok2) as u32; // pairs of 2 bits in here
-                let mut move_index = i * 16;
-
-                while ok != 0 {
-                    let skip = ok.trailing_zeros();
-
-                    move_index += (skip >> 1) as usize;
-                    ok >>= skip;
-                    ok >>= 2;
-
-                    moves.push(Move::new(
-                        *player,
-                        piece_id,
-                        ((move_index % 20) as i8, (move_index / 20) as i8),
-                    ));
-                    move_index += 1;
-                }
+    /// Create the subsquares of an empty `w` by `h` board.
+    /// Valid corners start empty as nothing is valid
+    /// but occupied_or_color has ones for every cell that is "out of bounds".
+    fn new(w: usize, h: usize) -> Self {
+        let mut occupied_or_color = [0u16; MAX_CELLS];
+
+        for y in h - 3..h {
+            let n_empty_rows = h - y;
+            // every bit is full except for the empty rows
+            let mask = (u16::MAX) ^ ((1 << (n_empty_rows * 4)) - 1);
+            for x in 0..w {
+                occupied_or_color[y * w + x] = mask;
             }
         }
+        for x in w - 3..w {
+            let n_empty_cols = w - x;
+            let rep_mask = 0xf ^ ((1 << n_empty_cols) - 1);
+            // Repeat the rep mask 4 times
+            let mask = rep_mask | rep_mask << 4 | rep_mask << 8 | rep_mask << 12;
+            for y in 0..h {
+                occupied_or_color[y * w + x] |= mask;
+            }
+        }
+
+        Subsquares {
+            occupied_or_color,
+            valid_corners: [0u16; MAX_CELLS],
+            w,
+            h,
+        }
+    }
+
+    /// Starts of the rows of 16 positions covering the board.
+    /// Positions past the end of the board have no valid corners, so they never fit.
+    #[inline]
+    fn row_starts(&self) -> impl Iterator<Item = usize> {
+        (0..(self.w * self.h).div_ceil(16)).map(|i| i * 16)
+    }
+
+    pub fn test_piece(&self, moves: &mut Vec<Move>, player: &Player, piece_id: usize, piece: u16) {
+        moves.extend(
+            self.positions(piece)
+                .map(|(x, y)| Move::new(*player, piece_id, (x as i8, y as i8))),
+        );
+    }
+
+    /// Positions a piece can be placed at, found without allocating
+    fn positions(&self, piece: u16) -> impl Iterator<Item = (usize, usize)> + '_ {
+        let test_row = *TEST_PIECE_ROW;
+        self.row_starts()
+            .flat_map(move |start| {
+                let mut ok = test_row(self, piece, start);
+                std::iter::from_fn(move || {
+                    if ok == 0 {
+                        return None;
+                    }
+                    let move_index = start + ok.trailing_zeros() as usize;
+                    ok &= ok - 1;
+                    Some(move_index)
+                })
+            })
+            .map(|move_index| (move_index % self.w, move_index / self.w))
     }
 
     /// Set a single (x, y) bit in the board, updating all the subsquares containing it
@@ -283,7 +273,7 @@ impl Subsquares {
             let rel_y = y - start_y;
             for start_x in (max(3, x) - 3)..x + 1 {
                 let rel_x = x - start_x;
-                let idx = start_y * 20 + start_x;
+                let idx = start_y * self.w + start_x;
 
                 let bit = 1 << (rel_y * 4 + rel_x);
 
@@ -299,17 +289,67 @@ impl Subsquares {
     // used in the 5x1 / 1x5 case
     #[inline]
     fn get_unchecked(&self, mask_typ: SubsquareMaskTyp, x: usize, y: usize) -> Option<bool> {
-        if x < 20 && y < 20 {
+        if x < self.w && y < self.h {
             let mask = match mask_typ {
                 SubsquareMaskTyp::OccupiedOrColor => &self.occupied_or_color,
                 SubsquareMaskTyp::Validcorners => &self.valid_corners,
             };
-            Some(mask[y * 20 + x] & 1 != 0)
+            Some(mask[y * self.w + x] & 1 != 0)
         } else {
             None
         }
     }
 
+    /// Count the positions a piece can be placed at, without creating the moves
+    pub fn count_piece(&self, piece: u16) -> usize {
+        let test_row = *TEST_PIECE_ROW;
+        self.row_starts()
+            .map(|start| test_row(self, piece, start).count_ones() as usize)
+            .sum()
+    }
+
+    /// Version of [Subsquares::test_piece_row_portable] for cpus with AVX2
+    #[cfg(target_arch = "x86_64")]
+    #[target_feature(enable = "avx2")]
+    unsafe fn test_piece_row_avx2(&self, piece: u16, start: usize) -> u16 {
+        debug_assert!(start + 16 <= MAX_CELLS);
+        let piece = _mm256_set1_epi16(piece as i16);
+        let zero = _mm256_setzero_si256();
+
+        let occupied_or_color =
+            _mm256_loadu_si256(std::ptr::addr_of!(self.occupied_or_color[start]) as *const __m256i);
+        let valid_corners =
+            _mm256_loadu_si256(std::ptr::addr_of!(self.valid_corners[start]) as *const __m256i);
+        let ok = _mm256_andnot_si256(
+            _mm256_cmpeq_epi16(_mm256_and_si256(piece, valid_corners), zero),
+            _mm256_cmpeq_epi16(_mm256_and_si256(piece, occupied_or_color), zero),
+        );
+        // Packing to bytes leaves positions 0-7 in bits 0-7 and 8-15 in bits 16-23
+        let ok = _mm256_movemask_epi8(_mm256_packs_epi16(ok, zero)) as u32;
+        (ok & 0xff | (ok >> 8) & 0xff00) as u16
+    }
+
+    /// Test a piece at the 16 positions starting at index `start`,
+    /// returning a mask with a bit set for every position it can be placed at
+    fn test_piece_row_portable(&self, piece: u16, start: usize) -> u16 {
+        (0..16).fold(0, |mask, i| {
+            let fits = piece & self.occupied_or_color[start + i] == 0
+                && piece & self.valid_corners[start + i] != 0;
+            mask | (fits as u16) << i
+        })
+    }
+
+    /// Whether a 5 long piece can be placed starting at `(x, y)` and extending in `(dx, dy)`,
+    /// I.E. all its tiles are free and one of its ends is a corner
+    fn fits_5_long(&self, (x, y): (usize, usize), (dx, dy): (usize, usize)) -> bool {
+        (0..5).all(|i| {
+            self.get_unchecked(SubsquareMaskTyp::OccupiedOrColor, x + i * dx, y + i * dy)
+                == Some(false)
+        }) && [(x, y), (x + 4 * dx, y + 4 * dy)]
+            .into_iter()
+            .any(|(x, y)| self.get_unchecked(SubsquareMaskTyp::Validcorners, x, y) == Some(true))
+    }
+
     /// Make sure all 16 subsquares containing this bit agree with each other
     fn check_bit(&self, mask_typ: SubsquareMaskTyp, x: usize, y: usize) -> Result<bool, String> {
         let mask = match mask_typ {
@@ -322,11 +362,11 @@ impl Subsquares {
         // XXXX
         // XXXX
         // *XXX
-        for start_y in (max(3, y) - 3)..min(20, y + 1) {
+        for start_y in (max(3, y) - 3)..min(self.h, y + 1) {
             let rel_y = y - start_y;
-            for start_x in (max(3, x) - 3)..min(20, x + 1) {
+            for start_x in (max(3, x) - 3)..min(self.w, x + 1) {
                 let rel_x = x - start_x;
-                let idx = start_y * 20 + start_x;
+                let idx = start_y * self.w + start_x;
                 let mask_v = (mask[idx] >> (rel_y * 4 + rel_x)) & 1;
 
                 match expected {
@@ -356,19 +396,24 @@ impl Subsquares {
 
     /// Make sure the entire subsquare mask is conformal
     fn check(&self) -> Result<(), String> {
-        for y in 0..23 {
-            for x in 0..23 {
+        let cells = self.w * self.h;
+        for y in 0..self.h + 3 {
+            for x in 0..self.w + 3 {
                 self.check_bit(SubsquareMaskTyp::OccupiedOrColor, x, y)
                     .map_err(|msg| {
                         format!(
                             "{}\nOccupied/Color Mask:\n{}",
                             msg,
-                            subsquare_str(&self.occupied_or_color)
+                            subsquare_str(&self.occupied_or_color[..cells], self.w)
                         )
                     })?;
                 self.check_bit(SubsquareMaskTyp::Validcorners, x, y)
                     .map_err(|msg| {
-                        format!("{}\nMasks:\n{}", msg, subsquare_str(&self.valid_corners))
+                        format!(
+                            "{}\nMasks:\n{}",
+                            msg,
+                            subsquare_str(&self.valid_corners[..cells], self.w)
+                        )
                     })?;
             }
         }
@@ -376,67 +421,17 @@ impl Subsquares {
     }
 }
 
-impl Default for Subsquares {
-    fn default() -> Self {
-        // Valid corners should start empty as nothing is valid
-        // but occupied_or_color should have ones for every cell that is "out of bounds".
-        let mut occupied_or_color = [0u16; 400];
-
-        for y in 17..20 {
-            let n_empty_rows = 20 - y;
-            // every bit is full except for the empty rows
-            let mask = (u16::MAX) ^ ((1 << (n_empty_rows * 4)) - 1);
-            for x in 0..20 {
-                occupied_or_color[y * 20 + x] = mask;
-            }
-        }
-        for x in 17..20 {
-            let n_empty_cols = 20 - x;
-            let rep_mask = 0xf ^ ((1 << n_empty_cols) - 1);
-            // Repeat the rep mask 4 times
-            let mask = rep_mask | rep_mask << 4 | rep_mask << 8 | rep_mask << 12;
-            for y in 0..20 {
-                occupied_or_color[y * 20 + x] |= mask;
-            }
-        }
-
-        Subsquares {
-            occupied_or_color,
-            valid_corners: [0u16; 400],
-        }
-    }
-}
-
 impl Debug for Subsquares {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let cells = self.w * self.h;
         writeln!(f, "Occupied/Color Mask:")?;
-        for y in 0..20 {
-            for row in 0..4 {
-                // rev bc little endian
-                for x in (0..20).rev() {
-                    // get the row of this mask
-                    let row = (self.occupied_or_color[y * 20 + x] >> (row * 4)) & 0xf;
-                    write!(f, "{:04b} ", row)?;
-                }
-                writeln!(f)?;
-            }
-            writeln!(f)?;
-        }
-
+        write!(
+            f,
+            "{}",
+            subsquare_str(&self.occupied_or_color[..cells], self.w)
+        )?;
         writeln!(f, "Corner Mask:")?;
-        for y in 0..20 {
-            for row in 0..4 {
-                for x in (0..20).rev() {
-                    // get the row of this mask
-                    let row = (self.valid_corners[y * 20 + x] >> (row * 4)) & 0xf;
-                    write!(f, "{:04b} ", row)?;
-                }
-                writeln!(f)?;
-            }
-            writeln!(f)?;
-        }
-
-        Ok(())
+        write!(f, "{}", subsquare_str(&self.valid_corners[..cells], self.w))
     }
 }
 
@@ -451,23 +446,140 @@ pub struct State {
     player_pieces: [u128; Player::N],
     /// Player scores
     scores: [u8; Player::N],
+    /// Cells covered by each player's pieces,
+    /// stored as one row bitmask per y coordinate
+    occupied: [[u32; MAX_SIZE]; Player::N],
+    /// Board width
+    w: usize,
+    /// Board height
+    h: usize,
+    /// Placements kept up to date as pieces are placed, if enabled
+    move_cache: Option<Box<MoveCache>>,
+}
+
+/// Positions every piece can be placed at for every player.
+/// When a piece is placed only the positions near it are tested again,
+/// instead of generating every move from scratch.
+#[derive(Clone)]
+struct MoveCache {
+    /// One row bitmask per y coordinate for every piece, including both 5 long pieces
+    positions: [[[u32; MAX_SIZE]; PIECE_COUNT + 2]; Player::N],
+}
+
+impl MoveCache {
+    fn new(state: &State) -> Self {
+        let mut cache = Self {
+            positions: [[[0; MAX_SIZE]; PIECE_COUNT + 2]; Player::N],
+        };
+        for player in Player::iter() {
+            cache.refresh(state, &player, (0, 0), (state.w - 1, state.h - 1), true);
+        }
+        cache
+    }
+
+    /// Test every piece the player has again at the positions where it would cover
+    /// a cell between `from` and `to` inclusive.
+    /// Unless `grow` is set, positions can only stop being valid so only those are tested.
+    fn refresh(
+        &mut self,
+        state: &State,
+        player: &Player,
+        (x0, y0): (usize, usize),
+        (x1, y1): (usize, usize),
+        grow: bool,
+    ) {
+        let pid = usize::from(player);
+        let ss = &state.subsquares[pid];
+        let test_row = *TEST_PIECE_ROW;
+
+        for piece in (0..PIECE_COUNT + 2)
+            .filter(|f| (1 << min(*f, PIECE_COUNT)) & state.player_pieces[pid] != 0)
+        {
+            let (w, h) = match piece {
+                PIECE_COUNT => (5, 1),
+                _ if piece == PIECE_COUNT + 1 => (1, 5),
+                piece => (PIECES[piece].width as usize, PIECES[piece].height as usize),
+            };
+            let (ax0, ax1) = (x0.saturating_sub(w - 1), x1);
+            let region: u32 = ((1 << (ax1 + 1)) - 1) & !((1 << ax0) - 1);
+
+            for y in y0.saturating_sub(h - 1)..=y1 {
+                let positions = &mut self.positions[pid][piece][y];
+                if !grow && *positions & region == 0 {
+                    continue;
+                }
+
+                let row = match piece {
+                    PIECE_COUNT => (ax0..=ax1)
+                        .filter(|x| ss.fits_5_long((*x, y), (1, 0)))
+                        .fold(0, |row, x| row | 1 << x),
+                    _ if piece == PIECE_COUNT + 1 => (ax0..=ax1)
+                        .filter(|x| ss.fits_5_long((*x, y), (0, 1)))
+                        .fold(0, |row, x| row | 1 << x),
+                    piece => {
+                        let mut row = 0;
+                        let mut x = ax0;
+                        while x <= ax1 {
+                            // The last positions of the board are tested from further back
+                            let start = min(y * state.w + x, MAX_CELLS - 16);
+                            let skip = y * state.w + x - start;
+                            row |= (test_row(ss, PIECES[piece].as_u16, start) as u32 >> skip) << x;
+                            x += 16 - skip;
+                        }
+                        row
+                    }
+                };
+                *positions = (*positions & !region) | (row & region);
+            }
+        }
+    }
+
+    /// Number of cached moves of a piece
+    fn count(&self, player: &Player, piece: usize) -> usize {
+        self.positions[usize::from(player)][piece]
+            .iter()
+            .map(|row| row.count_ones() as usize)
+            .sum()
+    }
+
+    /// Add the cached moves of a piece to `moves`
+    fn push_moves(&self, moves: &mut Vec<Move>, player: &Player, piece: usize) {
+        for (y, row) in self.positions[usize::from(player)][piece]
+            .iter()
+            .enumerate()
+        {
+            let mut row = *row;
+            while row != 0 {
+                let x = row.trailing_zeros();
+                row &= row - 1;
+                moves.push(Move::new(*player, piece, (x as i8, y as i8)));
+            }
+        }
+    }
 }
 
 impl State {
+    /// Create an empty `w` by `h` board, with every player starting in their own corner
     pub fn new(w: usize, h: usize) -> Self {
-        // this implementation is tailored for 20x20
-        assert!(w == 20 && h == 20);
-        let mut subsquares: [Subsquares; Player::N] = array::from_fn(|_| Subsquares::default());
+        assert!(
+            (5..=MAX_SIZE).contains(&w) && (5..=MAX_SIZE).contains(&h),
+            "Board sizes must be between 5 and {MAX_SIZE}, got {w}x{h}"
+        );
+        let mut subsquares: [Subsquares; Player::N] = array::from_fn(|_| Subsquares::new(w, h));
 
         subsquares[0].set_bit(SubsquareMaskTyp::Validcorners, 0, 0, true);
-        subsquares[1].set_bit(SubsquareMaskTyp::Validcorners, 19, 0, true);
-        subsquares[2].set_bit(SubsquareMaskTyp::Validcorners, 19, 19, true);
-        subsquares[3].set_bit(SubsquareMaskTyp::Validcorners, 0, 19, true);
+        subsquares[1].set_bit(SubsquareMaskTyp::Validcorners, w - 1, 0, true);
+        subsquares[2].set_bit(SubsquareMaskTyp::Validcorners, w - 1, h - 1, true);
+        subsquares[3].set_bit(SubsquareMaskTyp::Validcorners, 0, h - 1, true);
 
         let s = Self {
             subsquares,
             player_pieces: [(1 << (PIECE_COUNT + 1)) - 1; Player::N], // Players start with all the pieces
             scores: [0; Player::N],
+            occupied: [[0; MAX_SIZE]; Player::N],
+            w,
+            h,
+            move_cache: None,
         };
 
         // check if on debug
@@ -477,125 +589,320 @@ impl State {
         s
     }
 
+    /// Create a position from the player covering every cell, in row major order,
+    /// and the shapes every player has left.
+    /// Corners are derived from the cells, so the moves are the same as in the game that led to it.
+    pub(super) fn from_cells(
+        w: usize,
+        h: usize,
+        cells: &[Option<Player>],
+        remaining: &[Vec<Shape>; Player::N],
+    ) -> Self {
+        let mut state = Self::new(w, h);
+        let in_bounds = |(x, y): (i8, i8)| x >= 0 && y >= 0 && (x as usize) < w && (y as usize) < h;
+
+        for (i, player) in cells.iter().enumerate() {
+            let Some(player) = player else {
+                continue;
+            };
+            let pid = usize::from(player);
+            let (x, y) = (i % w, i / w);
+            state.occupied[pid][y] |= 1 << x;
+            state.scores[pid] += 1;
+            for ss in state.subsquares.iter_mut() {
+                ss.set_bit(SubsquareMaskTyp::OccupiedOrColor, x, y, true);
+            }
+
+            let cell = (x as i8, y as i8);
+            let ss = &mut state.subsquares[pid];
+            for (nx, ny) in Neighbor::iter().map(|n| n + cell).filter(|n| in_bounds(*n)) {
+                ss.set_bit(
+                    SubsquareMaskTyp::OccupiedOrColor,
+                    nx as usize,
+                    ny as usize,
+                    true,
+                );
+            }
+            for (cx, cy) in Corner::iter().map(|c| c + cell).filter(|c| in_bounds(*c)) {
+                ss.set_bit(
+                    SubsquareMaskTyp::Validcorners,
+                    cx as usize,
+                    cy as usize,
+                    true,
+                );
+            }
+        }
+
+        for (pid, shapes) in remaining.iter().enumerate() {
+            state.player_pieces[pid] = shapes
+                .iter()
+                .map(|shape| match shape {
+                    Shape::I5 => 0b11 << PIECE_COUNT,
+                    shape => PIECES[SHAPE_OFFSETS[usize::from(*shape)]].id_mask,
+                })
+                .fold(0, |pieces, id_mask| pieces | id_mask);
+        }
+
+        #[cfg(debug_assertions)]
+        state.check();
+
+        state
+    }
+
+    /// Keep the moves of every player up to date as pieces are placed,
+    /// making [State::get_moves] cheaper at the cost of more work in [State::place_piece]
+    pub fn with_move_cache(mut self) -> Self {
+        self.move_cache = Some(Box::new(MoveCache::new(&self)));
+        self
+    }
+
     pub fn scores(&self) -> &[u8; Player::N] {
         &self.scores
     }
 
+    /// Shapes the game is played with, always the standard set
+    pub fn piece_set(&self) -> &PieceSet {
+        &PIECE_SET
+    }
+
+    /// Get the pieces a player still has in hand, as indices into [State::piece_set]
+    pub fn remaining_pieces(&self, player: &Player) -> Vec<usize> {
+        let offsets = &*SHAPE_OFFSETS;
+        (0..Shape::N)
+            .filter(|shape| (1 << offsets[*shape]) & self.player_pieces[usize::from(player)] != 0)
+            .collect()
+    }
+
+    /// Index into [State::piece_set] of the piece a move places
+    #[inline]
+    pub fn move_piece(&self, mv: &Move) -> usize {
+        usize::from(piece_shape(mv))
+    }
+
+    /// Number of tiles a move covers
+    #[inline]
+    pub fn move_size(&self, mv: &Move) -> u8 {
+        piece_size(mv)
+    }
+
+    /// Width and height of the piece a move places
+    #[inline]
+    pub fn move_dims(&self, mv: &Move) -> (u8, u8) {
+        piece_dims(mv)
+    }
+
+    /// Get the player whose piece covers a cell, if any
+    pub fn cell(&self, x: i8, y: i8) -> Option<Player> {
+        if x < 0 || y < 0 || x >= self.w() || y >= self.h() {
+            return None;
+        }
+        Player::iter().find(|p| self.occupied[usize::from(p)][y as usize] & (1 << x) != 0)
+    }
+
+    /// Whether a player could cover a cell with one of their pieces,
+    /// I.E. the cell is in bounds, empty and not next to the player's own color
+    pub fn is_free(&self, player: &Player, (x, y): (i8, i8)) -> bool {
+        self.subsquares[usize::from(player)]
+            .get_unchecked(SubsquareMaskTyp::OccupiedOrColor, x as usize, y as usize)
+            .is_some_and(|occupied| !occupied)
+    }
+
+    /// Get the cells a player can currently start a piece from
+    pub fn corners(&self, player: &Player) -> Vec<(i8, i8)> {
+        let ss = &self.subsquares[usize::from(player)];
+        (0..self.w * self.h)
+            .filter(|i| ss.valid_corners[*i] & !ss.occupied_or_color[*i] & 1 != 0)
+            .map(|i| ((i % self.w) as i8, (i / self.w) as i8))
+            .collect()
+    }
+
+    /// Check whether a move follows the rules
+    pub fn is_legal(&self, mv: &Move) -> Result<(), IllegalMove> {
+        let pid = usize::from(mv.player);
+        if mv.piece > PIECE_COUNT + 1 {
+            return Err(IllegalMove::UnknownPiece);
+        }
+        if (1 << min(mv.piece, PIECE_COUNT)) & self.player_pieces[pid] == 0 {
+            return Err(IllegalMove::PieceUsed);
+        }
+        check_placement(
+            &mv.player,
+            &piece_cells(mv.piece),
+            mv.pos,
+            (self.w(), self.h()),
+            self.scores[pid] == 0,
+            |x, y| self.cell(x, y),
+        )
+    }
+
+    /// Board cells covered by a move
+    pub fn move_cells(&self, mv: &Move) -> Vec<(i8, i8)> {
+        piece_cells(mv.piece)
+            .into_iter()
+            .map(|(x, y)| (mv.pos.0 + x, mv.pos.1 + y))
+            .collect()
+    }
+
+    /// Place a piece if the move follows the rules
+    pub fn try_place_piece(&mut self, mv: &Move) -> Result<(), IllegalMove> {
+        self.is_legal(mv)?;
+        self.place_piece(mv);
+        Ok(())
+    }
+
     /// Get the possible moves for a player
     pub fn get_moves<'a>(&'a self, player: &'a Player) -> Vec<Move> {
         let mut moves = Vec::with_capacity(1000);
 
-        let pieces = &*PIECES;
         // All the different piece transforms for the player
         for piece in (0..PIECE_COUNT)
             .filter(move |f| (1 << *f) & self.player_pieces[usize::from(player)] != 0)
         {
-            /*
-            // CORRECTNESS TEST:
-            let mut a1 = vec![];
-            let mut a2 = vec![];
-
-            subsquares[usize::from(player)].test_piece(&mut a2, piece, pieces[piece].as_u16);
-            self.get_moves_for_piece(&mut a1, pieces, player, piece);
-
-            a1.sort();
-            a2.sort();
-            */
-            let old_len = moves.len();
-            self.subsquares[usize::from(player)].test_piece(
-                &mut moves,
+            self.get_moves_for_piece(&mut moves, player, piece);
+        }
+        self.get_5_long_moves(&mut moves, player);
+        moves
+    }
+
+    /// Count the possible moves for a player without creating them
+    pub fn count_moves(&self, player: &Player) -> usize {
+        self.count_moves_by_piece(player).iter().sum()
+    }
+
+    /// Count the possible moves for a player with each piece of [State::piece_set]
+    pub fn count_moves_by_piece(&self, player: &Player) -> Vec<usize> {
+        let mut counts = vec![0; Shape::N];
+        let pid = usize::from(player);
+        let shapes = &*PIECE_SHAPES;
+        for piece in (0..PIECE_COUNT).filter(|f| (1 << *f) & self.player_pieces[pid] != 0) {
+            counts[usize::from(shapes[piece])] += match &self.move_cache {
+                Some(cache) => cache.count(player, piece),
+                None => self.subsquares[pid].count_piece(PIECES[piece].as_u16),
+            };
+        }
+
+        counts[usize::from(Shape::I5)] += self.count_5_long_moves(player);
+        counts
+    }
+
+    /// Sample a random legal move for a player without generating all of them.
+    /// Pieces are tried in a random order and a random placement of the first one that fits
+    /// is returned, so every piece that fits is equally likely however many orientations it has,
+    /// and moves are not uniform.
+    /// Returns [None] if the player has no moves.
+    pub fn random_move(&self, rng: &mut ThreadRng, player: &Player) -> Option<Move> {
+        // both 5 long pieces are found together, so they are tried as one
+        let pieces: Vec<_> = (0..=PIECE_COUNT)
+            .filter(|f| (1 << *f) & self.player_pieces[usize::from(player)] != 0)
+            .collect();
+        // every transformation of a shape is one piece
+        let mut shapes: Vec<_> = pieces.iter().map(|p| PIECE_SHAPES[*p]).collect();
+        shapes.sort_by_key(|s| usize::from(*s));
+        shapes.dedup();
+        shapes.shuffle(rng);
+
+        let mut moves = Vec::with_capacity(64);
+        for shape in shapes {
+            for piece in pieces.iter().filter(|p| PIECE_SHAPES[**p] == shape) {
+                match *piece {
+                    PIECE_COUNT => self.get_5_long_moves(&mut moves, player),
+                    piece => self.get_moves_for_piece(&mut moves, player, piece),
+                }
+            }
+            if let Some(mv) = moves.choose(rng) {
+                return Some(mv.clone());
+            }
+        }
+        None
+    }
+
+    /// Add the moves of a single piece transform to `moves`
+    fn get_moves_for_piece(&self, moves: &mut Vec<Move>, player: &Player, piece: usize) {
+        let pieces = &*PIECES;
+        /*
+        // CORRECTNESS TEST:
+        let mut a1 = vec![];
+        let mut a2 = vec![];
+
+        subsquares[usize::from(player)].test_piece(&mut a2, piece, pieces[piece].as_u16);
+        self.get_moves_for_piece(&mut a1, pieces, player, piece);
+
+        a1.sort();
+        a2.sort();
+        */
+        match &self.move_cache {
+            Some(cache) => cache.push_moves(moves, player, piece),
+            None => self.subsquares[usize::from(player)].test_piece(
+                moves,
                 player,
                 piece,
                 pieces[piece].as_u16,
-            );
+            ),
+        }
+    }
 
-            // Special case for 5 wide or 5 tall
-            if pieces[piece].width == 4
-                && pieces[piece].height == 1
-                && (1 << PIECE_COUNT) & self.player_pieces[usize::from(player)] != 0
-            {
-                // println!("Wide, {} to {}", old_len, moves.len());
-                // Loop through all 4x1 moves and check if the tile to the left
-                // is empty
-
-                let ss = &self.subsquares[usize::from(player)];
-
-                for mv_i in old_len..moves.len() {
-                    let Move {
-                        player: _,
-                        piece: _,
-                        pos: (x, y),
-                    } = &moves[mv_i];
-
-                    let (x, y) = (*x as usize, *y as usize);
-
-                    // There are two cases to check for every move
-                    //     v space for extra tile
-                    // XXXX+
-                    // ^ corner here
-                    // v space for extra tile
-                    // +XXXX
-                    //     ^ corner here
-                    // note that only one of these can ever be true
-
-                    if
-                    // (x, y) is a corner
-                    ss.get_unchecked(SubsquareMaskTyp::Validcorners, x, y).unwrap() // never out of bounds here
-                        // (x + 4, y) is empty
-                        && !ss.get_unchecked(SubsquareMaskTyp::OccupiedOrColor, x + 4, y)
-                                .unwrap_or(true)
-                    // treat out of bounds as full
-                    {
-                        moves.push(Move::new(*player, PIECE_COUNT, (x as i8, y as i8)))
-                    } else if
-                    // (x+3, y) is a corner
-                    ss.get_unchecked(SubsquareMaskTyp::Validcorners, x+3, y).unwrap() // never out of bounds 
-                    // (x - 1, y) is empty
-                    && x > 0 && !ss.get_unchecked(SubsquareMaskTyp::OccupiedOrColor, x - 1, y)
-                            .unwrap_or(true)
-                    // treat out of bounds as full
-                    {
-                        moves.push(Move::new(*player, PIECE_COUNT, (x as i8 - 1, y as i8)))
-                    }
+    /// Add the moves of the 5 long piece to `moves`.
+    /// These don't fit in a 4x4 subsquare, so they are found from the placements
+    /// of the 4 long piece with the same orientation.
+    fn get_5_long_moves(&self, moves: &mut Vec<Move>, player: &Player) {
+        if (1 << PIECE_COUNT) & self.player_pieces[usize::from(player)] == 0 {
+            return;
+        }
+        if let Some(cache) = &self.move_cache {
+            cache.push_moves(moves, player, PIECE_COUNT);
+            cache.push_moves(moves, player, PIECE_COUNT + 1);
+            return;
+        }
+        self.for_each_5_long(player, |piece, (x, y)| {
+            moves.push(Move::new(*player, piece, (x as i8, y as i8)))
+        });
+    }
+
+    /// Count the placements of the 5 long piece without creating them
+    fn count_5_long_moves(&self, player: &Player) -> usize {
+        if (1 << PIECE_COUNT) & self.player_pieces[usize::from(player)] == 0 {
+            return 0;
+        }
+        if let Some(cache) = &self.move_cache {
+            return cache.count(player, PIECE_COUNT) + cache.count(player, PIECE_COUNT + 1);
+        }
+        let mut count = 0;
+        self.for_each_5_long(player, |_, _| count += 1);
+        count
+    }
+
+    /// Call `f` with the piece and position of every placement of the 5 long piece,
+    /// found from the subsquares
+    fn for_each_5_long(&self, player: &Player, mut f: impl FnMut(usize, (usize, usize))) {
+        let ss = &self.subsquares[usize::from(player)];
+        for (long_4, long_5, (dx, dy)) in [
+            (*I4_WIDE, PIECE_COUNT, (1, 0)),
+            (*I4_TALL, PIECE_COUNT + 1, (0, 1)),
+        ] {
+            for (x, y) in ss.positions(PIECES[long_4].as_u16) {
+                // Only the ends of a line can touch a corner, so every placement
+                // of the 5 long piece contains a 4 long placement at one of its ends.
+                // It is added from the one at its start if that is a corner,
+                // and from the one at its end otherwise.
+                if ss.fits_5_long((x, y), (dx, dy)) {
+                    f(long_5, (x, y));
                 }
-            } else if pieces[piece].width == 1
-                && pieces[piece].height == 4
-                // we check the 5x1 here instead because using the 5x1 also uses the 1x5
-                // and the compiler thus only has to calculate this once.
-                && (1 << PIECE_COUNT) & self.player_pieces[usize::from(player)] != 0
-            {
-                let ss = &self.subsquares[usize::from(player)];
-
-                for mv_i in old_len..moves.len() {
-                    let Move {
-                        player: _,
-                        piece: _,
-                        pos: (x, y),
-                    } = &moves[mv_i];
-
-                    let (x, y) = (*x as usize, *y as usize);
-
-                    if ss.get_unchecked(SubsquareMaskTyp::Validcorners, x, y).unwrap() // never out of bounds here
-                        && !ss.get_unchecked(SubsquareMaskTyp::OccupiedOrColor, x, y + 4)
-                                .unwrap_or(true)
-                    // treat out of bounds as full
-                    {
-                        moves.push(Move::new(*player, PIECE_COUNT + 1, (x as i8, y as i8)))
-                    } else if ss.get_unchecked(SubsquareMaskTyp::Validcorners, x, y + 3).unwrap() // never out of bounds 
-                    && y > 0 && !ss.get_unchecked(SubsquareMaskTyp::OccupiedOrColor, x, y - 1)
-                            .unwrap_or(true)
-                    // treat out of bounds as full
-                    {
-                        moves.push(Move::new(*player, PIECE_COUNT + 1, (x as i8, y as i8 - 1)))
-                    }
+                if x >= dx
+                    && y >= dy
+                    && !ss
+                        .get_unchecked(SubsquareMaskTyp::Validcorners, x - dx, y - dy)
+                        .unwrap()
+                    && ss.fits_5_long((x - dx, y - dy), (dx, dy))
+                {
+                    f(long_5, (x - dx, y - dy));
                 }
             }
         }
-        moves
     }
 
     /// Moved to another function so the main function is not so big
     fn place_5_wide(&mut self, pid: usize, (x, y): (i8, i8)) {
+        let (bw, bh) = (self.w(), self.h());
         // 5 wide
         // XXXXXXXXXXXXX
         // XXXXXXXXXXXXX
@@ -607,7 +914,7 @@ impl State {
         // XXXXXXXXXXXXX
         // XXXXXXXXXXXXX
         // + h + 1 here because the neighbor map reaches to y + h + 1
-        for offset_y in max(0, y - 4)..min(20, y + 2) {
+        for offset_y in max(0, y - 4)..min(bh, y + 2) {
             // same calculations for y
             // Range of rows of the piece mask that will be used (y)
             let piece_y_range = (
@@ -627,7 +934,7 @@ impl State {
             let piece_y_w = min(4 - mask_y_start, piece_y_w);
 
             // + w + 1 here because the neighbor map reaches to x + w + 1
-            for offset_x in max(0, x - 4)..min(20, x + 5 + 1) {
+            for offset_x in max(0, x - 4)..min(bw, x + 5 + 1) {
                 // Range of columns of the piece mask that will be used (x)
                 let piece_x_range = (
                     max(0, offset_x - x + 1) as usize, // + 1 bc neighbor / corner masks start at -1
@@ -647,9 +954,9 @@ impl State {
                 let piece_x_w = (1 << piece_x_w) - 1;
 
                 // which 4x4 mask we are at
-                let mask_idx = offset_y as usize * 20 + offset_x as usize;
+                let mask_idx = offset_y as usize * bw as usize + offset_x as usize;
 
-                debug_assert!(mask_idx < 400);
+                debug_assert!(mask_idx < MAX_CELLS);
 
                 for row in 0..piece_y_w {
                     // where in the mask we are
@@ -700,7 +1007,7 @@ impl State {
                 let mask_y_start = max(0, y - offset_y) as usize;
 
                 // + w + 1 here because the neighbor map reaches to x + w + 1
-                for offset_x in max(0, x - 3)..min(20, x + 5) {
+                for offset_x in max(0, x - 3)..min(bw, x + 5) {
                     // Range of columns of the piece mask that will be used (x)
                     let piece_x_range = (
                         max(0, offset_x - x) as usize, // + 1 bc neighbor / corner masks start at -1
@@ -720,9 +1027,9 @@ impl State {
                     let piece_x_w = (1 << piece_x_w) - 1;
 
                     // which 4x4 mask we are at
-                    let mask_idx = offset_y as usize * 20 + offset_x as usize;
+                    let mask_idx = offset_y as usize * bw as usize + offset_x as usize;
 
-                    debug_assert!(mask_idx < 400);
+                    debug_assert!(mask_idx < MAX_CELLS);
 
                     // println!("{:?}, {:?}", (mask_x_start, mask_y), (piece_x_w, piece_y_w));
                     // Shift right to discard lowest cols
@@ -736,13 +1043,15 @@ impl State {
                 }
             }
         }
+        self.occupied[pid][y as usize] |= 0b11111 << x;
         self.player_pieces[pid] &= !(0b11 << PIECE_COUNT);
         self.scores[pid] += 5;
     }
 
     fn place_5_tall(&mut self, pid: usize, (x, y): (i8, i8)) {
+        let (bw, bh) = (self.w(), self.h());
         // + h + 1 here because the neighbor map reaches to y + h + 1
-        for offset_y in max(0, y - 4)..min(20, y + 5 + 1) {
+        for offset_y in max(0, y - 4)..min(bh, y + 5 + 1) {
             // same calculations for y
             // Range of rows of the piece mask that will be used (y)
             let piece_y_range = (
@@ -762,7 +1071,7 @@ impl State {
             let piece_y_w = min(4 - mask_y_start, piece_y_w);
 
             // + w + 1 here because the neighbor map reaches to x + w + 1
-            for offset_x in max(0, x - 4)..min(20, x + 1 + 1) {
+            for offset_x in max(0, x - 4)..min(bw, x + 1 + 1) {
                 // Range of columns of the piece mask that will be used (x)
                 let piece_x_range = (
                     max(0, offset_x - x + 1) as usize, // + 1 bc neighbor / corner masks start at -1
@@ -782,9 +1091,9 @@ impl State {
                 let piece_x_w = (1 << piece_x_w) - 1;
 
                 // which 4x4 mask we are at
-                let mask_idx = offset_y as usize * 20 + offset_x as usize;
+                let mask_idx = offset_y as usize * bw as usize + offset_x as usize;
 
-                debug_assert!(mask_idx < 400);
+                debug_assert!(mask_idx < MAX_CELLS);
 
                 for row in 0..piece_y_w {
                     // where in the mask we are
@@ -823,7 +1132,7 @@ impl State {
             if opid == pid {
                 continue;
             }
-            for offset_y in max(0, y - 3)..min(20, y + 5) {
+            for offset_y in max(0, y - 3)..min(bh, y + 5) {
                 // Range of columns of the piece mask that will be used (x)
                 let piece_y_range = (
                     max(0, offset_y - y) as usize,
@@ -847,9 +1156,9 @@ impl State {
                     let mask_x_start = max(0, x - offset_x) as usize;
 
                     // which 4x4 mask we are at
-                    let mask_idx = offset_y as usize * 20 + offset_x as usize;
+                    let mask_idx = offset_y as usize * bw as usize + offset_x as usize;
 
-                    debug_assert!(mask_idx < 400);
+                    debug_assert!(mask_idx < MAX_CELLS);
 
                     // println!("{:?}, {:?}", (mask_x_start, mask_y), (piece_x_w, piece_y_w));
                     // Shift right to discard lowest cols
@@ -864,6 +1173,9 @@ impl State {
             }
         }
 
+        for row in y..y + 5 {
+            self.occupied[pid][row as usize] |= 1 << x;
+        }
         self.player_pieces[pid] &= !(0b11 << PIECE_COUNT);
         self.scores[pid] += 5;
     }
@@ -876,21 +1188,22 @@ impl State {
         match mv.piece {
             PIECE_COUNT => {
                 #[cfg(debug_assertions)]
-                println!("Placing wide {:?}", &mv.pos);
+                println!("Placing wide {:?}", mv.pos);
                 self.place_5_wide(pid, (x, y))
             }
             PC1 => {
                 #[cfg(debug_assertions)]
-                println!("Placing tall {:?}", &mv.pos);
+                println!("Placing tall {:?}", mv.pos);
                 self.place_5_tall(pid, (x, y))
             }
             pieceid => {
                 // a piece placed at a position
                 let piece = &PIECES[pieceid];
+                let (bw, bh) = (self.w(), self.h());
                 #[cfg(debug_assertions)]
                 println!(
                     "Placing at {:?}:\n{}\n{}",
-                    &mv.pos,
+                    mv.pos,
                     piece
                         .neighbor_mask
                         .iter()
@@ -921,7 +1234,7 @@ impl State {
                 // XXXXXXXXXXXX
                 // XXXXXXXXXXXX
                 // + h + 1 here because the neighbor map reaches to y + h + 1
-                for offset_y in max(0, y - 4)..min(20, y + h + 1) {
+                for offset_y in max(0, y - 4)..min(bh, y + h + 1) {
                     // same calculations for y
                     // Range of rows of the piece mask that will be used (y)
                     let piece_y_range = (
@@ -940,7 +1253,7 @@ impl State {
                     let piece_y_w = min(4 - mask_y_start, piece_y_w);
 
                     // + w + 1 here because the neighbor map reaches to x + w + 1
-                    for offset_x in max(0, x - 4)..min(20, x + w + 1) {
+                    for offset_x in max(0, x - 4)..min(bw, x + w + 1) {
                         // Range of columns of the piece mask that will be used (x)
                         let piece_x_range = (
                             max(0, offset_x - x + 1) as usize, // + 1 bc neighbor / corner masks start at -1
@@ -960,9 +1273,9 @@ impl State {
                         let piece_x_w = (1 << piece_x_w) - 1;
 
                         // which 4x4 mask we are at
-                        let mask_idx = offset_y as usize * 20 + offset_x as usize;
+                        let mask_idx = offset_y as usize * bw as usize + offset_x as usize;
 
-                        debug_assert!(mask_idx < 400);
+                        debug_assert!(mask_idx < MAX_CELLS);
 
                         for row in 0..piece_y_w {
                             // where in the mask we are
@@ -1015,7 +1328,7 @@ impl State {
                     }
                     // 0..2
                     // y = 1
-                    for offset_y in max(0, y - 3)..min(20, y + h) {
+                    for offset_y in max(0, y - 3)..min(bh, y + h) {
                         // same calculations for y
                         // Range of rows of the piece mask that will be used (y)
                         let piece_y_range = (
@@ -1030,7 +1343,7 @@ impl State {
                         // where in this 4x4 mask does the piece mask begin
                         let mask_y_start = max(0, y - offset_y);
 
-                        for offset_x in max(0, x - 3)..min(20, x + w) {
+                        for offset_x in max(0, x - 3)..min(bw, x + w) {
                             // Range of columns of the piece mask that will be used (x)
                             let piece_x_range = (
                                 max(0, offset_x - x) as usize,
@@ -1045,7 +1358,7 @@ impl State {
                             let mask_x_start = max(0, x - offset_x);
 
                             // which 4x4 mask we are at
-                            let mask_idx = offset_y as usize * 20 + offset_x as usize;
+                            let mask_idx = offset_y as usize * bw as usize + offset_x as usize;
 
                             // Mask for number of cols used
                             let piece_x_w = x_masks[piece_x_range.1 - piece_x_range.0 - 1];
@@ -1068,11 +1381,37 @@ impl State {
                     }
                 }
 
+                for row in 0..h {
+                    self.occupied[pid][(y + row) as usize] |=
+                        (((piece.as_u16 >> (row * 4)) & 0xf) as u32) << x;
+                }
+
                 self.player_pieces[pid] &= !PIECES[mv.piece].id_mask;
                 self.scores[pid] += piece_size(mv);
             }
         }
 
+        if let Some(mut cache) = self.move_cache.take() {
+            let (w, h) = piece_dims(mv);
+            let (w, h) = (w as usize, h as usize);
+            let (x, y) = (x as usize, y as usize);
+            for player in Player::iter() {
+                // Placements of other players only change if they cover the piece,
+                // but the player placing it has new corners and blocked cells around it
+                match player == mv.player {
+                    true => cache.refresh(
+                        self,
+                        &player,
+                        (x.saturating_sub(1), y.saturating_sub(1)),
+                        (min(self.w - 1, x + w), min(self.h - 1, y + h)),
+                        true,
+                    ),
+                    false => cache.refresh(self, &player, (x, y), (x + w - 1, y + h - 1), false),
+                }
+            }
+            self.move_cache = Some(cache);
+        }
+
         // check if on debug
         #[cfg(debug_assertions)]
         self.check()
@@ -1091,6 +1430,18 @@ impl State {
     }
 }
 
+impl Dimensioned for State {
+    #[inline]
+    fn w(&self) -> i8 {
+        self.w as i8
+    }
+
+    #[inline]
+    fn h(&self) -> i8 {
+        self.h as i8
+    }
+}
+
 impl Display for State {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         Debug::fmt(self, f)
@@ -1099,8 +1450,8 @@ impl Display for State {
 
 impl Debug for State {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        for y in 0..20 {
-            for x in 0..20 {
+        for y in 0..self.h {
+            for x in 0..self.w {
                 let cell = self.subsquares[0]
                     .check_bit(SubsquareMaskTyp::OccupiedOrColor, x, y)
                     .unwrap()
@@ -1167,13 +1518,87 @@ mod test {
         // 0010
         // 0000
         // 0000
-        println!("{}", subsquare_str(&game.subsquares[0].valid_corners));
+        println!("{}", subsquare_str(&game.subsquares[0].valid_corners, 20));
         assert!(game.subsquares[0].valid_corners[0] == 0b00100001);
         assert!(game.subsquares[0].valid_corners[1] == 0b00010000);
         assert!(game.subsquares[0].valid_corners[20] == 0b0010);
         assert!(game.subsquares[0].valid_corners[21] == 1);
     }
 
+    #[test]
+    fn check_corners_one() {
+        let mut game = State::new(20, 20);
+        assert_eq!(game.corners(&Player::Player1), vec![(0, 0)]);
+        assert_eq!(game.corners(&Player::Player3), vec![(19, 19)]);
+
+        game.place_piece(&Move::new(Player::Player1, 0, (0, 0)));
+
+        assert_eq!(game.cell(0, 0), Some(Player::Player1));
+        assert_eq!(game.cell(1, 0), None);
+        assert!(!game.is_free(&Player::Player1, (1, 0)));
+        assert!(game.is_free(&Player::Player2, (1, 0)));
+        assert!(!game.is_free(&Player::Player2, (0, 0)));
+        assert_eq!(game.corners(&Player::Player1), vec![(1, 1)]);
+    }
+
+    #[test]
+    fn check_remaining_pieces() {
+        // every shape's first transformation maps back to that shape
+        for shape in Shape::iter() {
+            let mv = Move::new(Player::Player1, SHAPE_OFFSETS[usize::from(shape)], (0, 0));
+            assert_eq!(piece_shape(&mv), shape);
+        }
+
+        let mut game = State::new(20, 20);
+        assert_eq!(game.remaining_pieces(&Player::Player1).len(), Shape::N);
+
+        // Place the
+        // XX
+        //  X
+        // piece
+        game.place_piece(&Move::new(Player::Player1, piece_index(0b10011), (0, 0)));
+        let remaining = game.remaining_pieces(&Player::Player1);
+        assert_eq!(remaining.len(), Shape::N - 1);
+        assert!(!remaining.contains(&usize::from(Shape::V3)));
+        assert_eq!(game.remaining_pieces(&Player::Player2).len(), Shape::N);
+
+        game.place_piece(&Move::new(Player::Player2, PIECE_COUNT + 1, (19, 0)));
+        assert!(!game
+            .remaining_pieces(&Player::Player2)
+            .contains(&usize::from(Shape::I5)));
+    }
+
+    #[test]
+    fn check_is_legal() {
+        let mut game = State::new(20, 20);
+        let player = Player::Player1;
+        let legal = |game: &State, piece, pos| game.is_legal(&Move::new(player, piece, pos));
+        assert_eq!(
+            legal(&game, PIECE_COUNT + 2, (0, 0)),
+            Err(IllegalMove::UnknownPiece)
+        );
+        assert_eq!(legal(&game, 0, (1, 1)), Err(IllegalMove::NotOnStartCorner));
+        assert_eq!(
+            legal(&game, PIECE_COUNT, (16, 0)),
+            Err(IllegalMove::OutOfBounds)
+        );
+        assert_eq!(legal(&game, 0, (-1, 0)), Err(IllegalMove::OutOfBounds));
+
+        game.try_place_piece(&Move::new(player, 0, (0, 0))).unwrap();
+        assert_eq!(legal(&game, 0, (1, 1)), Err(IllegalMove::PieceUsed));
+        // The 2 wide piece
+        assert_eq!(legal(&game, 1, (0, 0)), Err(IllegalMove::Overlaps));
+        assert_eq!(legal(&game, 1, (1, 0)), Err(IllegalMove::TouchesOwnColor));
+        assert_eq!(legal(&game, 1, (5, 5)), Err(IllegalMove::NoCornerContact));
+        assert_eq!(legal(&game, 1, (1, 1)), Ok(()));
+        // Nothing changes when a move is rejected
+        assert_eq!(
+            game.try_place_piece(&Move::new(player, 1, (5, 5))),
+            Err(IllegalMove::NoCornerContact)
+        );
+        assert_eq!(game.scores()[0], 1);
+    }
+
     #[test]
     fn check_place_off_corner_one() {
         // Take the
@@ -1211,7 +1636,7 @@ mod test {
         // 0000
         // 0101
         // 0000
-        println!("{}", subsquare_str(&game.subsquares[0].valid_corners));
+        println!("{}", subsquare_str(&game.subsquares[0].valid_corners, 20));
         assert!(game.subsquares[0].valid_corners[0] == 0b010100000101);
         assert!(game.subsquares[0].valid_corners[1] == 0b001000000010);
         assert!(game.subsquares[0].valid_corners[2] == 0b000100000001);
@@ -1244,7 +1669,10 @@ mod test {
         // 1111111
         // 1111111
         // 1111111
-        println!("{}", subsquare_str(&game.subsquares[0].occupied_or_color));
+        println!(
+            "{}",
+            subsquare_str(&game.subsquares[0].occupied_or_color, 20)
+        );
         assert!(game.subsquares[0].occupied_or_color[399] == u16::MAX);
         assert!(game.subsquares[0].occupied_or_color[398] == u16::MAX);
         assert!(game.subsquares[0].occupied_or_color[397] == u16::MAX - 1);
@@ -1266,7 +1694,7 @@ mod test {
         // 0010100
         // 0000000
         // 0000000
-        println!("{}", subsquare_str(&game.subsquares[0].valid_corners));
+        println!("{}", subsquare_str(&game.subsquares[0].valid_corners, 20));
         assert!(game.subsquares[0].valid_corners[399] == 0b00100000);
         assert!(game.subsquares[0].valid_corners[398] == 0b01010000);
         assert!(game.subsquares[0].valid_corners[397] == 0b10100000);
@@ -1291,7 +1719,7 @@ mod test {
         // XX
         //  X
         // piece
-        let piece = 5;
+        let piece = piece_index(0b10011);
 
         // Place it in the top right at (0, 0)
         let mut game = State::new(20, 20);
@@ -1313,7 +1741,10 @@ mod test {
                 .map(|v| format!("{:06b}", v))
                 .fold(String::new(), |acc, v| format!("{}\n{}", acc, v))
         );
-        println!("{}", subsquare_str(&game.subsquares[0].occupied_or_color));
+        println!(
+            "{}",
+            subsquare_str(&game.subsquares[0].occupied_or_color, 20)
+        );
         assert!(game.subsquares[0].occupied_or_color[0] == 0b000100110111);
         assert!(game.subsquares[0].occupied_or_color[1] == 0b00010011);
         assert!(game.subsquares[0].occupied_or_color[20] == 0b00010011);
@@ -1327,7 +1758,7 @@ mod test {
         // 0100
         // 0010
         // 0000
-        println!("{}", subsquare_str(&game.subsquares[0].valid_corners));
+        println!("{}", subsquare_str(&game.subsquares[0].valid_corners, 20));
         assert!(game.subsquares[0].valid_corners[0] == 0b001001000001);
         assert!(game.subsquares[0].valid_corners[1] == 0b000100100000);
         assert!(game.subsquares[0].valid_corners[2] == 0b00010000);
@@ -1346,7 +1777,7 @@ mod test {
         // X
         // X
         // piece
-        let piece = 10;
+        let piece = *I4_TALL;
 
         let mut game = State::new(20, 20);
         game.check();
@@ -1371,7 +1802,10 @@ mod test {
                 .map(|v| format!("{:06b}", v))
                 .fold(String::new(), |acc, v| format!("{}\n{}", acc, v))
         );
-        println!("{}", subsquare_str(&game.subsquares[0].occupied_or_color));
+        println!(
+            "{}",
+            subsquare_str(&game.subsquares[0].occupied_or_color, 20)
+        );
         assert!(game.subsquares[0].occupied_or_color[19 * 20] == 0b1111111111110011);
         assert!(game.subsquares[0].occupied_or_color[18 * 20] == 0b1111111100110011);
         assert!(game.subsquares[0].occupied_or_color[17 * 20] == 0b1111001100110011);
@@ -1386,7 +1820,7 @@ mod test {
         // 0100
         // 0010
         // 0000
-        println!("{}", subsquare_str(&game.subsquares[0].valid_corners));
+        println!("{}", subsquare_str(&game.subsquares[0].valid_corners, 20));
         assert!(game.subsquares[0].valid_corners[19 * 20] == 0b0000000000100000);
         assert!(game.subsquares[0].valid_corners[18 * 20] == 0b0000001000000000);
         assert!(game.subsquares[0].valid_corners[17 * 20] == 0b0010000000000000);
@@ -1417,13 +1851,16 @@ mod test {
         // Make sure all the masks are valid
         game.check();
 
-        println!("{}", subsquare_str(&game.subsquares[1].occupied_or_color));
+        println!(
+            "{}",
+            subsquare_str(&game.subsquares[1].occupied_or_color, 20)
+        );
         assert!(game.subsquares[1].occupied_or_color[0] == 0b11100000);
         assert!(game.subsquares[1].occupied_or_color[1] == 0b11110000);
         assert!(game.subsquares[1].occupied_or_color[2] == 0b11110000);
         assert!(game.subsquares[1].occupied_or_color[3] == 0b01110000);
 
-        println!("{}", subsquare_str(&game.subsquares[0].valid_corners));
+        println!("{}", subsquare_str(&game.subsquares[0].valid_corners, 20));
         assert!(game.subsquares[0].valid_corners[0] == 0b000100000001);
         assert!(game.subsquares[0].valid_corners[1] == 0);
         assert!(game.subsquares[0].valid_corners[2] == 0);
@@ -1442,13 +1879,16 @@ mod test {
         // Make sure all the masks are valid
         game.check();
 
-        println!("{}", subsquare_str(&game.subsquares[1].occupied_or_color));
+        println!(
+            "{}",
+            subsquare_str(&game.subsquares[1].occupied_or_color, 20)
+        );
         assert!(game.subsquares[1].occupied_or_color[0] == 0b0010001000100000);
         assert!(game.subsquares[1].occupied_or_color[20] == 0b0010001000100010);
         assert!(game.subsquares[1].occupied_or_color[40] == 0b0010001000100010);
         assert!(game.subsquares[1].occupied_or_color[60] == 0b0000001000100010);
 
-        println!("{}", subsquare_str(&game.subsquares[0].valid_corners));
+        println!("{}", subsquare_str(&game.subsquares[0].valid_corners, 20));
         assert!(game.subsquares[0].valid_corners[0] == 0b101);
         assert!(game.subsquares[0].valid_corners[20] == 0);
         assert!(game.subsquares[0].valid_corners[40] == 0);
@@ -1467,16 +1907,216 @@ mod test {
         // Make sure all the masks are valid
         game.check();
 
-        println!("{}", subsquare_str(&game.subsquares[1].occupied_or_color));
-        assert!(game.subsquares[1].occupied_or_color[0] == 0b0010001000100000);
-        assert!(game.subsquares[1].occupied_or_color[20] == 0b0010001000100010);
-        assert!(game.subsquares[1].occupied_or_color[40] == 0b0010001000100010);
-        assert!(game.subsquares[1].occupied_or_color[60] == 0b0000001000100010);
+        println!(
+            "{}",
+            subsquare_str(&game.subsquares[1].occupied_or_color, 20)
+        );
+        assert!(game.subsquares[1].occupied_or_color[8 * 20 + 16] == 0b0010001000100000);
+        assert!(game.subsquares[1].occupied_or_color[9 * 20 + 16] == 0b0010001000100010);
+        assert!(game.subsquares[1].occupied_or_color[10 * 20 + 16] == 0b0010001000100010);
+        assert!(game.subsquares[1].occupied_or_color[11 * 20 + 16] == 0b0000001000100010);
+
+        println!("{}", subsquare_str(&game.subsquares[0].valid_corners, 20));
+        assert!(game.subsquares[0].valid_corners[8 * 20 + 16] == 0b101);
+        assert!(game.subsquares[0].valid_corners[9 * 20 + 16] == 0);
+        assert!(game.subsquares[0].valid_corners[10 * 20 + 16] == 0);
+        assert!(game.subsquares[0].valid_corners[11 * 20 + 16] == 0b101000000000000);
+    }
 
-        println!("{}", subsquare_str(&game.subsquares[0].valid_corners));
-        assert!(game.subsquares[0].valid_corners[0] == 0b101);
-        assert!(game.subsquares[0].valid_corners[20] == 0);
-        assert!(game.subsquares[0].valid_corners[40] == 0);
-        assert!(game.subsquares[0].valid_corners[60] == 0b101000000000000);
+    /// Find every placement of the given pieces by checking the rules cell by cell
+    fn brute_force_moves(game: &State, player: &Player, pieces: &[usize]) -> Vec<Move> {
+        let (w, h) = (game.w(), game.h());
+        let start = [(0, 0), (w - 1, 0), (w - 1, h - 1), (0, h - 1)][usize::from(player)];
+        let mine = |(x, y): (i8, i8)| game.cell(x, y) == Some(*player);
+        let remaining = game.remaining_pieces(player);
+
+        let mut moves = vec![];
+        for &piece in pieces {
+            if !remaining.contains(&usize::from(index_shape(piece))) {
+                continue;
+            }
+            for y in 0..h {
+                for x in 0..w {
+                    let cells: Vec<_> = piece_cells(piece)
+                        .into_iter()
+                        .map(|(cx, cy)| (x + cx, y + cy))
+                        .collect();
+                    let free = cells.iter().all(|&(x, y)| {
+                        x < w
+                            && y < h
+                            && game.cell(x, y).is_none()
+                            && [(1, 0), (-1, 0), (0, 1), (0, -1)]
+                                .iter()
+                                .all(|(nx, ny)| !mine((x + nx, y + ny)))
+                    });
+                    let touches = cells.iter().any(|&(x, y)| {
+                        [(1, 1), (-1, 1), (1, -1), (-1, -1)]
+                            .iter()
+                            .any(|(nx, ny)| mine((x + nx, y + ny)))
+                    }) || (game.scores()[usize::from(player)] == 0
+                        && cells.contains(&start));
+                    if free && touches {
+                        moves.push(Move::new(*player, piece, (x, y)));
+                    }
+                }
+            }
+        }
+        moves.sort();
+        moves
+    }
+
+    #[test]
+    fn check_5_long_moves() {
+        let mut rng = rand::thread_rng();
+        for _ in 0..2 {
+            let mut game = State::new(20, 20);
+            let mut player = Player::Player1;
+            let mut passes = 0;
+            while passes < Player::N {
+                let mut moves: Vec<_> = game
+                    .get_moves(&player)
+                    .into_iter()
+                    .filter(|mv| mv.piece >= PIECE_COUNT)
+                    .collect();
+                moves.sort();
+                assert_eq!(
+                    moves,
+                    brute_force_moves(&game, &player, &[PIECE_COUNT, PIECE_COUNT + 1])
+                );
+
+                match game.random_move(&mut rng, &player) {
+                    Some(mv) => {
+                        game.place_piece(&mv);
+                        passes = 0;
+                    }
+                    None => passes += 1,
+                }
+                player = player.next();
+            }
+        }
+    }
+
+    #[test]
+    fn check_move_cache() {
+        let mut rng = rand::thread_rng();
+        let mut game = State::new(20, 20);
+        let mut cached = State::new(20, 20).with_move_cache();
+        let mut player = Player::Player1;
+        let mut passes = 0;
+        while passes < Player::N {
+            // The cache has to agree with the full generator for every player
+            for p in Player::iter() {
+                let mut expected = game.get_moves(&p);
+                let mut moves = cached.get_moves(&p);
+                expected.sort();
+                moves.sort();
+                assert_eq!(moves, expected);
+            }
+
+            match game.random_move(&mut rng, &player) {
+                Some(mv) => {
+                    game.place_piece(&mv);
+                    cached.place_piece(&mv);
+                    passes = 0;
+                }
+                None => passes += 1,
+            }
+            player = player.next();
+        }
+    }
+
+    #[test]
+    fn check_count_moves() {
+        let mut rng = rand::thread_rng();
+        let mut game = State::new(20, 20);
+        let mut cached = State::new(20, 20).with_move_cache();
+        let mut player = Player::Player1;
+        let mut passes = 0;
+        while passes < Player::N {
+            for p in Player::iter() {
+                let moves = game.get_moves(&p);
+                let mut counts = vec![0; Shape::N];
+                for mv in &moves {
+                    counts[usize::from(piece_shape(mv))] += 1;
+                }
+                for state in [&game, &cached] {
+                    assert_eq!(state.count_moves(&p), moves.len());
+                    assert_eq!(state.count_moves_by_piece(&p), counts);
+                }
+            }
+
+            match game.random_move(&mut rng, &player) {
+                Some(mv) => {
+                    game.place_piece(&mv);
+                    cached.place_piece(&mv);
+                    passes = 0;
+                }
+                None => passes += 1,
+            }
+            player = player.next();
+        }
+    }
+
+    #[test]
+    fn check_board_sizes() {
+        let mut rng = rand::thread_rng();
+        let pieces: Vec<_> = (0..PIECE_COUNT + 2).collect();
+        for (w, h) in [(14, 14), (11, 17), (5, 5)] {
+            let mut game = State::new(w, h);
+            let mut cached = State::new(w, h).with_move_cache();
+            assert_eq!((game.w(), game.h()), (w as i8, h as i8));
+            assert_eq!(
+                game.corners(&Player::Player3),
+                vec![(w as i8 - 1, h as i8 - 1)]
+            );
+
+            let mut player = Player::Player1;
+            let mut passes = 0;
+            while passes < Player::N {
+                let expected = brute_force_moves(&game, &player, &pieces);
+                for state in [&game, &cached] {
+                    let mut moves = state.get_moves(&player);
+                    moves.sort();
+                    assert_eq!(moves, expected);
+                    assert_eq!(state.count_moves(&player), expected.len());
+                }
+
+                match game.random_move(&mut rng, &player) {
+                    Some(mv) => {
+                        game.place_piece(&mv);
+                        cached.place_piece(&mv);
+                        passes = 0;
+                    }
+                    None => passes += 1,
+                }
+                player = player.next();
+            }
+        }
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    #[test]
+    fn check_portable_matches_avx2() {
+        if !is_x86_feature_detected!("avx2") {
+            return;
+        }
+        let mut rng = rand::thread_rng();
+        let mut game = State::new(20, 20);
+        let mut player = Player::Player1;
+        while let Some(mv) = game.random_move(&mut rng, &player) {
+            game.place_piece(&mv);
+            player = player.next();
+
+            for ss in &game.subsquares {
+                for piece in PIECES.iter() {
+                    for start in 0..=MAX_CELLS - 16 {
+                        assert_eq!(
+                            unsafe { ss.test_piece_row_avx2(piece.as_u16, start) },
+                            ss.test_piece_row_portable(piece.as_u16, start)
+                        );
+                    }
+                }
+            }
+        }
     }
 }
@@ -1,11 +1,12 @@
 use blokus_ai::evaluate::{
-    Distance, EnemyMoveCount, GreedyMax, GreedyMin, MiniMax, Mix, MonteCarlo, MoveCount, Opening,
-    Random, Rollout, Score, Tournament,
+    BlockedCorners, CornerCount, Distance, EnemyMoveCount, GreedyMax, GreedyMin, Inventory,
+    MiniMax, Mix, MoveCount, Opening, Random, Rollout, Score, Territory, Tournament,
 };
 use std::{path::PathBuf, time::Instant};
 
 fn main() {
     let tpath = PathBuf::from("tournament.json");
+    let gpath = PathBuf::from("tournament_games.jsonl");
 
     let mut tournament = Tournament::new(
         100.,
@@ -26,7 +27,17 @@ fn main() {
             Box::new(GreedyMax::<Score>::default()),
             Box::new(GreedyMax::<MoveCount>::default()),
             Box::new(GreedyMax::new(Rollout::new(25))),
-            // Box::new(MonteCarlo::new(1000, f64::sqrt(2.))),
+            Box::new(GreedyMax::<CornerCount>::default()),
+            Box::new(GreedyMax::<Territory>::default()),
+            Box::new(GreedyMax::<BlockedCorners>::default()),
+            Box::new(GreedyMax::<Inventory>::default()),
+            Box::new(Mix::<GreedyMax<Inventory>, GreedyMax<MoveCount>>::new_ratio(0.5)),
+            // Box::new(MonteCarlo::<Score>::new(1000, f64::sqrt(2.))),
+            // Box::new(MonteCarlo::<Score>::new(1000, f64::sqrt(2.)).rave(300.)),
+            // Box::new(MonteCarlo::<Score>::new(1000, f64::sqrt(2.)).widening(2., 0.5)),
+            // Box::new(MonteCarlo::<Score>::new(1000, f64::sqrt(2.)).guided(0.2)),
+            // Box::new(MonteCarlo::<Score>::new(1000, f64::sqrt(2.)).reuse_tree()),
+            // Box::new(MonteCarlo::<Score>::new(4000, f64::sqrt(2.)).threads(8)),
             Box::new(Mix::<GreedyMax<Score>, GreedyMax<MoveCount>>::new_ratio(
                 0.5,
             )),
@@ -74,10 +85,12 @@ fn main() {
             )),
             Box::new(MiniMax::<2, MoveCount>::default()),
             Box::new(MiniMax::<3, Score>::default()),
+            Box::new(MiniMax::<2, CornerCount>::default()),
         ],
         std::fs::File::open(tpath.clone()).ok(),
     )
-    .expect("Failed to load tournament");
+    .expect("Failed to load tournament")
+    .record_replays(0.01);
 
     loop {
         let now = Instant::now();
@@ -88,30 +101,50 @@ fn main() {
         tournament
             .save(tpath.clone())
             .expect("Failed to save tournament");
+        tournament
+            .save_games(gpath.clone())
+            .expect("Failed to save games");
     }
 }
 
 #[cfg(test)]
 mod test {
     use blokus_ai::game::ver_1::State as State1;
+    use blokus_ai::game::ver_2::State as State2;
     use blokus_ai::game::ver_3::State as State3;
     use blokus_ai::game::Player;
 
     #[test]
     fn test_move_counts_match() {
         // make sure move counts match across algorithms
-        // only checking version 2 and 3 because they have the same indexing method and also don't have 5 tall/wide pieces
 
         let game1 = State1::new(20, 20);
+        let game2 = State2::new(20, 20);
         let game3 = State3::new(20, 20);
 
         for player in Player::iter() {
-            let moves1 = game1.get_moves(&player);
-            let moves3 = game3.get_moves(&player);
+            let count1 = game1.count_moves(&player);
+            let count2 = game2.get_moves(&player).len();
+            let count3 = game3.count_moves(&player);
+
+            println!("{:?}", (count1, count2, count3));
+
+            assert!(count1 == count3);
+            assert!(count2 == count3);
+            assert!(count3 == game3.get_moves(&player).len());
+        }
+    }
 
-            println!("{:?}", (moves1.len(), moves3.len()));
+    #[test]
+    fn test_move_counts_match_other_sizes() {
+        // ver_2 only supports 20x20
+        for (w, h) in [(14, 14), (11, 17)] {
+            let game1 = State1::new(w, h);
+            let game3 = State3::new(w as usize, h as usize);
 
-            assert!(moves1.len() == moves3.len());
+            for player in Player::iter() {
+                assert_eq!(game1.count_moves(&player), game3.count_moves(&player));
+            }
         }
     }
 }
@@ -0,0 +1,25 @@
+use blokus_ai::evaluate::Report;
+use std::{
+    fs::File,
+    io::{BufReader, Cursor},
+};
+
+/// Build an HTML report from the files written by the tournament.
+/// Usage: report [ratings] [games] [output], defaulting to the files of the main binary
+fn main() {
+    let mut args = std::env::args().skip(1);
+    let ratings = args.next().unwrap_or("tournament.json".to_string());
+    let games = args.next().unwrap_or("tournament_games.jsonl".to_string());
+    let output = args.next().unwrap_or("report.html".to_string());
+
+    let ratings = File::open(&ratings).expect("Failed to open the ratings");
+    // the game log only exists once games have been recorded
+    let report = match File::open(&games) {
+        Ok(games) => Report::load(ratings, BufReader::new(games)),
+        Err(_) => Report::load(ratings, Cursor::new("")),
+    }
+    .expect("Failed to load the tournament");
+
+    std::fs::write(&output, report.to_html()).expect("Failed to write the report");
+    println!("Wrote {}", output);
+}
@@ -0,0 +1,23 @@
+use blokus_ai::evaluate::{GreedyMax, MonteCarlo, MoveCount, Score, SelfPlay};
+
+/// Play self-play games and write them as training data.
+/// Usage: selfplay [games] [output], defaulting to 10 games written to `selfplay`
+fn main() {
+    let mut args = std::env::args().skip(1);
+    let games = args
+        .next()
+        .map(|games| games.parse().expect("The number of games must be a number"))
+        .unwrap_or(10);
+    let output = args.next().unwrap_or("selfplay".to_string());
+
+    let self_play = SelfPlay::new(vec![
+        Box::new(MonteCarlo::<Score>::new(1000, f64::sqrt(2.))),
+        Box::new(GreedyMax::<Score>::default()),
+        Box::new(MonteCarlo::<Score>::new(1000, f64::sqrt(2.)).guided(0.2)),
+        Box::new(GreedyMax::<MoveCount>::default()),
+    ]);
+    let mut rng = rand::thread_rng();
+    let dataset = self_play.play(&mut rng, 0, games);
+    dataset.save(&output).expect("Failed to write the samples");
+    println!("Wrote {} samples to {}", dataset.samples.len(), output);
+}
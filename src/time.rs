@@ -1,10 +1,14 @@
-use blokus_ai::game::{Player, State};
+use blokus_ai::{
+    evaluate::{playout, random_playout},
+    game::{Game, Player, State},
+};
 use itertools::MultiUnzip;
-use rand::seq::SliceRandom;
-use std::time::Instant;
+use rand::{rngs::ThreadRng, seq::SliceRandom};
+use std::time::{Duration, Instant};
 
 struct Stats {
     move_ns: u128,
+    count_ns: u128,
     place_ns: u128,
     fanout: usize,
 }
@@ -20,6 +24,16 @@ fn arithmetic_mean(values: &[f64]) -> f64 {
     values.iter().copied().sum::<f64>() / values.len() as f64
 }
 
+/// Number of games played out by `play` per second
+fn playouts_per_sec(rng: &mut ThreadRng, games: usize, play: fn(&mut ThreadRng, &State)) -> f64 {
+    let state = State::new(20, 20);
+    let now = Instant::now();
+    for _ in 0..games {
+        play(rng, &state);
+    }
+    games as f64 / now.elapsed().max(Duration::from_nanos(1)).as_secs_f64()
+}
+
 fn main() {
     let mut rng = rand::thread_rng();
 
@@ -28,51 +42,55 @@ fn main() {
     let debug = cfg!(debug_assertions);
 
     loop {
-        let mut game = State::new(20, 20);
-
-        loop {
-            let mut played = false;
-            for player in Player::iter() {
-                let now = Instant::now();
-                let moves = game.get_moves(&player);
-                let moves: Vec<_> = moves;
-                let move_elapsed = now.elapsed();
-
-                if debug {
-                    println!("Calculation took {} ns", move_elapsed.as_nanos());
-                    println!("Player {} has {} moves", player, moves.len());
-                }
-
-                if moves.is_empty() {
-                    continue;
-                }
-
-                // Choose a random move
-                let move_ = moves.choose(&mut rng).unwrap();
-
-                let now = Instant::now();
-                game.place_piece(move_);
-                let place_elapsed = now.elapsed();
-                if debug {
-                    println!("{:?}", game);
-                }
-                played = true;
-
-                stats.push(Stats {
-                    move_ns: move_elapsed.as_nanos(),
-                    place_ns: place_elapsed.as_nanos(),
-                    fanout: moves.len(),
-                })
+        let mut game = Game::new(State::new(20, 20));
+
+        while !game.is_over() {
+            let player = game.to_move();
+            let now = Instant::now();
+            let moves = game.state().get_moves(&player);
+            let move_elapsed = now.elapsed();
+
+            let now = Instant::now();
+            let count = game.state().count_moves(&player);
+            let count_elapsed = now.elapsed();
+            debug_assert_eq!(count, moves.len());
+
+            if debug {
+                println!("Calculation took {} ns", move_elapsed.as_nanos());
+                println!("Player {} has {} moves", player, moves.len());
             }
 
-            if !played {
-                break;
+            // Choose a random move
+            let Some(move_) = moves.choose(&mut rng) else {
+                game.pass();
+                continue;
+            };
+
+            let now = Instant::now();
+            game.play(move_.clone());
+            let place_elapsed = now.elapsed();
+            if debug {
+                println!("{:?}", game.state());
             }
+
+            stats.push(Stats {
+                move_ns: move_elapsed.as_nanos(),
+                count_ns: count_elapsed.as_nanos(),
+                place_ns: place_elapsed.as_nanos(),
+                fanout: moves.len(),
+            })
         }
 
-        let stats: (Vec<_>, Vec<_>, Vec<_>) = stats
+        let stats: (Vec<_>, Vec<_>, Vec<_>, Vec<_>) = stats
             .iter()
-            .map(|s| (s.move_ns as f64, s.place_ns as f64, s.fanout as f64))
+            .map(|s| {
+                (
+                    s.move_ns as f64,
+                    s.place_ns as f64,
+                    s.fanout as f64,
+                    s.count_ns as f64,
+                )
+            })
             .multiunzip();
 
         println!(
@@ -80,6 +98,11 @@ fn main() {
             arithmetic_mean(&stats.0) / 1000.,
         );
 
+        println!(
+            "Average move count time:\n\tArithmetic:{} micros",
+            arithmetic_mean(&stats.3) / 1000.,
+        );
+
         println!(
             "Average place calculation time:\n\tArithmetic:{} micros",
             arithmetic_mean(&stats.1) / 1000.,
@@ -91,6 +114,39 @@ fn main() {
             arithmetic_mean(&stats.2)
         );
 
+        let games = if debug { 1 } else { 100 };
+        println!(
+            "Playouts per second:\n\tSampled:{}\n\tEnumerated:{}",
+            playouts_per_sec(&mut rng, games, |rng, state| {
+                random_playout(rng, state, Player::Player1);
+            }),
+            playouts_per_sec(&mut rng, games, |rng, state| {
+                playout(rng, state, Player::Player1, |rng, state, player| {
+                    state.get_moves(player).choose(rng).cloned()
+                });
+            }),
+        );
+
+        // The move cache pays off when the moves of every player are needed after every placement
+        #[cfg(alg_ver = "3")]
+        {
+            fn all_moves_playout(rng: &mut ThreadRng, state: &State) {
+                playout(rng, state, Player::Player1, |rng, state, player| {
+                    for p in Player::iter() {
+                        state.get_moves(&p);
+                    }
+                    state.random_move(rng, player)
+                });
+            }
+            println!(
+                "Playouts generating every player's moves per second:\n\tGenerated:{}\n\tCached:{}",
+                playouts_per_sec(&mut rng, games, all_moves_playout),
+                playouts_per_sec(&mut rng, games, |rng, state| {
+                    all_moves_playout(rng, &state.clone().with_move_cache())
+                }),
+            );
+        }
+
         // don't loop forever if on debug mode
         if debug {
             break;